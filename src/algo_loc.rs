@@ -1,62 +1,84 @@
-use crate::git_command_algo::{extract_details_parallel, get_latest_commit, index_some_commits};
-use crate::{config, contextgpt_structs::AuthorDetailsV2};
+use crate::git_command_algo::{
+    extract_details_with_merge_policy, get_latest_commit, index_some_commits,
+};
+use crate::embedded_store::EmbeddedStore;
+use crate::index_store::{FileLines, IndexStore};
+use crate::config_impl::{self, Config};
+use crate::contextgpt_structs::AuthorDetailsV2;
+use crate::git_backend::{GitBackend, backend};
 use std::collections::HashMap;
-use std::path::Path;
+use tokio::task;
 
 pub async fn perform_for_whole_file(
     origin_file_path: String,
     should_print: bool,
     commits_to_index: Option<Vec<String>>,
     workspace_path: Option<String>,
+    config: &Config,
 ) -> HashMap<u32, AuthorDetailsV2> {
+    let git = backend(config.git_backend);
     // Check if we should skip indexing based on existing metadata
     if let Some(workspace_path) = &workspace_path {
-        if is_already_indexed(&origin_file_path, workspace_path, should_print) {
+        if is_already_indexed(git, &origin_file_path, workspace_path, should_print, config) {
             return HashMap::new();
         }
     }
 
     // Perform the actual indexing
-    index_file(&origin_file_path, commits_to_index, should_print).await
+    index_file(
+        &origin_file_path,
+        commits_to_index,
+        workspace_path.as_deref(),
+        should_print,
+        config,
+    )
+    .await
 }
 
-fn is_already_indexed(origin_file_path: &str, workspace_path: &str, should_print: bool) -> bool {
+fn is_already_indexed(
+    git: &dyn GitBackend,
+    origin_file_path: &str,
+    workspace_path: &str,
+    should_print: bool,
+    config: &Config,
+) -> bool {
     // Get the latest commit safely
-    let Some(recent_commit) = get_latest_commit(&origin_file_path.to_string()) else {
+    let Some(recent_commit) = get_latest_commit(git, origin_file_path) else {
         if should_print {
-            println!("No commits found for file: {}", origin_file_path);
+            eprintln!("No commits found for file: {}", origin_file_path);
         }
         return false;
     };
 
     if should_print {
-        println!(
+        eprintln!(
             "Latest commit for file: {} is: {}",
             origin_file_path, recent_commit
         );
     }
 
-    let Some(indexing_path) = build_indexing_path(workspace_path) else {
+    let Some(db_folder_path) = config_impl::db_folder_path(workspace_path, config) else {
         return false;
     };
 
     if should_print {
-        println!("Indexing path: {}", indexing_path);
+        eprintln!("DB folder: {}", db_folder_path.display());
     }
 
-    if !Path::new(&indexing_path).exists() {
+    if !db_folder_path.exists() {
         return false;
     }
 
-    match read_indexing_metadata(&indexing_path) {
-        Ok(metadata) => {
-            if let Some(last_indexed_commit) = get_last_indexed_commit(&metadata, origin_file_path) {
+    let store = EmbeddedStore::new(&db_folder_path);
+    match store.last_indexed_commit(origin_file_path) {
+        Ok(last_indexed_commit) => {
+            if let Some(last_indexed_commit) = last_indexed_commit {
                 if should_print {
-                    println!("Last indexed commit: {}", last_indexed_commit);
+                    eprintln!("Last indexed commit: {}", last_indexed_commit);
                 }
                 if last_indexed_commit == recent_commit {
                     if should_print {
-                        println!(
+                        eprintln!(
                             "File {} is already indexed with the latest commit {}",
                             origin_file_path, recent_commit
                         );
@@ -75,63 +97,50 @@ fn is_already_indexed(origin_file_path: &str, workspace_path: &str, should_print
     false
 }
 
-fn build_indexing_path(workspace_path: &str) -> Option<String> {
-    let processed_workspace_path = if cfg!(target_os = "windows") {
-        // Handle Windows paths more safely
-        if let Some(stripped) = workspace_path.strip_prefix(r"C:\") {
-            stripped
-        } else {
-            workspace_path
-        }
-    } else {
-        workspace_path
-    };
-
-    let db_folder = format!(
-        "{}{}{}",
-        config::DB_FOLDER,
-        std::path::MAIN_SEPARATOR,
-        processed_workspace_path
-    );
-    let home = simple_home_dir::home_dir()?;
-    let folder_path = home.join(db_folder);
-    let path_str = folder_path.to_str()?;
-    Some(format!("{}/indexing_metadata.json", path_str))
-}
-
-fn read_indexing_metadata(
-    indexing_path: &str,
-) -> Result<HashMap<String, Vec<String>>, Box<dyn std::error::Error>> {
-    let metadata_str = std::fs::read_to_string(indexing_path)?;
-    if metadata_str.trim().is_empty() {
-        // Return empty HashMap if file is empty
-        return Ok(HashMap::new());
-    }
-    let metadata = serde_json::from_str(&metadata_str)?;
-    Ok(metadata)
-}
-
-fn get_last_indexed_commit(
-    metadata: &HashMap<String, Vec<String>>,
+// The commit the file was last indexed at, and the lines it had then.
+fn indexed_lines(
     origin_file_path: &str,
-) -> Option<String> {
-    metadata
-        .get(origin_file_path)
-        .and_then(|commits| commits.last())
-        .cloned()
+    workspace_path: &str,
+    config: &Config,
+) -> Option<(String, FileLines)> {
+    let db_folder_path = config_impl::db_folder_path(workspace_path, config)?;
+    if !db_folder_path.exists() {
+        return None;
+    }
+    let store = EmbeddedStore::new(&db_folder_path);
+    let last_indexed_commit = store.last_indexed_commit(origin_file_path).ok()??;
+    let lines = store.get_file_lines(origin_file_path).ok()??;
+    Some((last_indexed_commit, lines))
 }
 
 async fn index_file(
     origin_file_path: &str,
     commits_to_index: Option<Vec<String>>,
+    workspace_path: Option<&str>,
     should_print: bool,
+    config: &Config,
 ) -> HashMap<u32, AuthorDetailsV2> {
+    let git = backend(config.git_backend);
     if should_print {
-        println!("Indexing file: {}", origin_file_path);
+        eprintln!("Indexing file: {}", origin_file_path);
     }
 
-    match commits_to_index {
-        Some(commits) => index_some_commits(origin_file_path.to_string(), commits).await,
-        None => extract_details_parallel(origin_file_path.to_string()).await,
-    }
+    // Git and the index are read synchronously, off the async runtime.
+    let origin_file_path = origin_file_path.to_string();
+    let workspace_path = workspace_path.map(|workspace_path| workspace_path.to_string());
+    let config = config.clone();
+    task::spawn_blocking(move || match commits_to_index {
+        Some(commits) => {
+            let indexed_lines = workspace_path.and_then(|workspace_path| {
+                indexed_lines(&origin_file_path, &workspace_path, &config)
+            });
+            index_some_commits(git, origin_file_path, commits, indexed_lines)
+        }
+        None => extract_details_with_merge_policy(git, origin_file_path, config.merge_policy),
+    })
+    .await
+    .unwrap_or_else(|e| {
+        eprintln!("Indexing failed: {}", e);
+        HashMap::new()
+    })
 }
\ No newline at end of file
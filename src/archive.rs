@@ -0,0 +1,210 @@
+// Portable copies of a workspace's index, to share it across machines (e.g. built once on
+// CI, imported by every developer).
+//
+// The index keys files by absolute path, archives key them relative to the workspace so that
+// they can be imported wherever the repository is checked out. An archive is gzipped JSON:
+// the format version, the HEAD commit it was built at, and for every file its lines and the
+// commit it was indexed at, plus the details of all those commits. Files whose indexed
+// commit is behind are caught up incrementally by the next query (see index_commits_since).
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+
+use crate::config_impl::{self, Config};
+use crate::contextgpt_structs::CommitInfo;
+use crate::embedded_store::EmbeddedStore;
+use crate::git_backend::GitBackend;
+use crate::git_command_algo::{commit_exists, get_head_commit};
+use crate::index_store::{FileLines, IndexStore, StoreError};
+
+// 2: full commit hashes, version 1 had abbreviated ones.
+pub const ARCHIVE_FORMAT_VERSION: u32 = 2;
+
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct Archive {
+    pub format_version: u32,
+    pub head_commit: Option<String>,
+    // Relative to the workspace, with / separators.
+    pub files: BTreeMap<String, ArchivedFile>,
+    pub commits: HashMap<String, CommitInfo>,
+}
+
+#[derive(Deserialize)]
+struct ArchiveHeader {
+    format_version: u32,
+}
+
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct ArchivedFile {
+    pub lines: FileLines,
+    pub indexed_commit: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Store(StoreError),
+    UnsupportedVersion(u32),
+    NoDbFolder,
+}
+
+impl From<std::io::Error> for ArchiveError {
+    fn from(error: std::io::Error) -> Self {
+        ArchiveError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for ArchiveError {
+    fn from(error: serde_json::Error) -> Self {
+        ArchiveError::Json(error)
+    }
+}
+
+impl From<StoreError> for ArchiveError {
+    fn from(error: StoreError) -> Self {
+        ArchiveError::Store(error)
+    }
+}
+
+impl std::fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArchiveError::Io(error) => error.fmt(f),
+            ArchiveError::Json(error) => write!(f, "invalid archive: {}", error),
+            ArchiveError::Store(error) => error.fmt(f),
+            ArchiveError::UnsupportedVersion(format_version) => write!(
+                f,
+                "the archive is at version {}, this version of contextpilot reads version {}",
+                format_version, ARCHIVE_FORMAT_VERSION
+            ),
+            ArchiveError::NoDbFolder => write!(f, "failed to determine the home directory"),
+        }
+    }
+}
+
+// What an export or an import went through.
+#[derive(Default, Debug)]
+pub struct ArchiveSummary {
+    pub files: usize,
+    pub commits: usize,
+    pub head_commit: Option<String>,
+}
+
+fn canonical_workspace(workspace_path: &str) -> PathBuf {
+    std::fs::canonicalize(workspace_path).unwrap_or_else(|_| PathBuf::from(workspace_path))
+}
+
+fn open_store(workspace_path: &str, config: &Config) -> Result<EmbeddedStore, ArchiveError> {
+    let db_folder_path =
+        config_impl::db_folder_path(workspace_path, config).ok_or(ArchiveError::NoDbFolder)?;
+    std::fs::create_dir_all(&db_folder_path)?;
+    Ok(EmbeddedStore::new(&db_folder_path))
+}
+
+// Writes the index of the workspace to the archive. Run from the workspace, for git.
+pub fn export(
+    git: &dyn GitBackend,
+    workspace_path: &str,
+    archive_path: &Path,
+    config: &Config,
+) -> Result<ArchiveSummary, ArchiveError> {
+    let workspace = canonical_workspace(workspace_path);
+    let store = open_store(workspace_path, config)?;
+    let mut archive = Archive {
+        format_version: ARCHIVE_FORMAT_VERSION,
+        head_commit: get_head_commit(git),
+        ..Default::default()
+    };
+    let mut commit_hashes: Vec<String> = vec![];
+    for file_path in store.list_files()? {
+        // Only files inside the workspace can be found again elsewhere.
+        let Ok(relative_path) = Path::new(&file_path).strip_prefix(&workspace) else {
+            eprintln!("Skipping {}, it's outside of the workspace", file_path);
+            continue;
+        };
+        let Some(lines) = store.get_file_lines(&file_path)? else {
+            continue;
+        };
+        commit_hashes.extend(lines.values().flatten().cloned());
+        let relative_path: Vec<String> = relative_path
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().to_string())
+            .collect();
+        archive.files.insert(
+            relative_path.join("/"),
+            ArchivedFile {
+                lines,
+                indexed_commit: store.last_indexed_commit(&file_path)?,
+            },
+        );
+    }
+    archive.commits = store.get_commits(&commit_hashes)?;
+
+    let mut encoder = GzEncoder::new(
+        BufWriter::new(File::create(archive_path)?),
+        Compression::default(),
+    );
+    serde_json::to_writer(&mut encoder, &archive)?;
+    encoder.finish()?.flush()?;
+    Ok(ArchiveSummary {
+        files: archive.files.len(),
+        commits: archive.commits.len(),
+        head_commit: archive.head_commit,
+    })
+}
+
+// Adds the files of the archive to the index of the workspace, replacing the ones it already
+// has. Run from the workspace, for git.
+pub fn import(
+    git: &dyn GitBackend,
+    workspace_path: &str,
+    archive_path: &Path,
+    config: &Config,
+) -> Result<ArchiveSummary, ArchiveError> {
+    let mut data = vec![];
+    GzDecoder::new(BufReader::new(File::open(archive_path)?)).read_to_end(&mut data)?;
+    // The version first, other versions may not even parse.
+    let header: ArchiveHeader = serde_json::from_slice(&data)?;
+    if header.format_version != ARCHIVE_FORMAT_VERSION {
+        return Err(ArchiveError::UnsupportedVersion(header.format_version));
+    }
+    let archive: Archive = serde_json::from_slice(&data)?;
+    if let Some(head_commit) = &archive.head_commit
+        && !commit_exists(git, head_commit)
+    {
+        eprintln!(
+            "The archive was built at {}, which isn't in this repository, fetch it to catch up",
+            head_commit
+        );
+    }
+
+    let workspace = canonical_workspace(workspace_path);
+    let mut store = open_store(workspace_path, config)?;
+    // Commits first, so that the lines never point to commits the index doesn't know.
+    store.put_commits(&archive.commits)?;
+    let files: Vec<(String, FileLines, Option<String>)> = archive
+        .files
+        .into_iter()
+        .map(|(relative_path, archived_file)| {
+            let file_path = workspace.join(relative_path);
+            (
+                file_path.to_string_lossy().to_string(),
+                archived_file.lines,
+                archived_file.indexed_commit,
+            )
+        })
+        .collect();
+    store.put_files(&files)?;
+    Ok(ArchiveSummary {
+        files: files.len(),
+        commits: archive.commits.len(),
+        head_commit: archive.head_commit,
+    })
+}
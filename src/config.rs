@@ -1,11 +1,14 @@
 pub const DB_FOLDER: &str = ".context_pilot_db";
-pub const MAX_ITEMS_IN_EACH_DB_FILE: u32 = 30; // arbitrary number for each DB to split up after it crosses this limit
+// Under $XDG_CACHE_HOME, when it's set.
+pub const XDG_DB_FOLDER: &str = "contextpilot";
+// Overrides where the index is stored, see config_impl::db_root.
+pub const DB_DIR_ENV_VAR: &str = "CONTEXT_PILOT_DB_DIR";
 
 // Maybe I would prefer Lua as we go ahead
 pub const CONFIG_FILE_NAME: &str = "context_pilot.json";
+// Per-workspace config, at the root of the workspace. Takes precedence over the global one.
+pub const WORKSPACE_CONFIG_FILE_NAME: &str = ".contextpilot.json";
 
-// Default for the output to be shown in UI for selector
-pub const OUTPUT_COUNT_THRESHOLD: usize = 10;
-
-//  Options supported:
-//  - 
+//  Options supported (see config_impl::ConfigLayer, unknown ones are rejected):
+//  - file_count_threshold, include, exclude, ignored_authors, ignored_commits, db_dir,
+//    merge_policy, scoring_model, score_half_life_days, git_backend, indexing_mode
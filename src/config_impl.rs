@@ -1,77 +1,481 @@
 use crate::config;
 
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use serde::Deserialize;
 use serde::Serialize;
-use std::fs::File;
-use std::path::PathBuf;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-#[derive(Deserialize, Serialize, Debug)]
+// Layered configuration, later layers win:
+// 1. defaults
+// 2. the global ~/context_pilot.json
+// 3. the workspace's .contextpilot.json
+// 4. environment variables ($CONTEXT_PILOT_DB_DIR)
+// 5. CLI flags
+//
+// Scalars from a later layer replace earlier ones, lists are appended to.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
     pub file_count_threshold: usize,
+    // Globs (gitignore syntax, relative to the workspace) of files to index.
+    // Everything is indexed when empty.
+    pub include: Vec<String>,
+    // Globs of files and folders never to index, on top of .gitignore.
+    pub exclude: Vec<String>,
+    // Commits by these authors (name or email) are left out of all the results.
+    pub ignored_authors: Vec<String>,
+    // Commit hashes (or prefixes) left out of all the results, e.g. formatting commits.
+    pub ignored_commits: Vec<String>,
+    // Where the index is stored, see db_root for the defaults.
+    pub db_dir: Option<String>,
+    // How merge commits are attributed when indexing.
+    pub merge_policy: MergePolicy,
+    // How related files are scored.
+    pub scoring_model: ScoringModel,
+    // When set, a commit counts half as much every that many days.
+    pub score_half_life_days: Option<f64>,
+    // How the history is read.
+    pub git_backend: GitBackendKind,
+    // Whether the workspace is indexed file by file, or in a single walk of the history.
+    pub indexing_mode: IndexingMode,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
-            file_count_threshold: config::OUTPUT_COUNT_THRESHOLD,
+            // Number of related files shown in the UI for the selector.
+            file_count_threshold: 10,
+            include: vec![],
+            exclude: vec![],
+            ignored_authors: vec![],
+            ignored_commits: vec![],
+            db_dir: None,
+            merge_policy: MergePolicy::default(),
+            scoring_model: ScoringModel::default(),
+            score_half_life_days: None,
+            git_backend: GitBackendKind::default(),
+            indexing_mode: IndexingMode::default(),
+        }
+    }
+}
+
+// A single layer, only holds what was actually set.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigLayer {
+    pub file_count_threshold: Option<usize>,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub ignored_authors: Vec<String>,
+    pub ignored_commits: Vec<String>,
+    pub db_dir: Option<String>,
+    pub merge_policy: Option<MergePolicy>,
+    pub scoring_model: Option<ScoringModel>,
+    pub score_half_life_days: Option<f64>,
+    pub git_backend: Option<GitBackendKind>,
+    pub indexing_mode: Option<IndexingMode>,
+}
+
+// What to do with merge commits while walking the history of a file.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum MergePolicy {
+    // Follow every branch, a merge only owns the lines it changed compared to all of its
+    // parents (i.e. conflict resolutions).
+    #[default]
+    AllParents,
+    // Only follow the first parent, a merge owns everything it brought in from the other branches.
+    FirstParent,
+    // Like all-parents, but lines changed in a merge keep the commits from before it.
+    Skip,
+}
+
+impl FromStr for MergePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all-parents" => Ok(MergePolicy::AllParents),
+            "first-parent" => Ok(MergePolicy::FirstParent),
+            "skip" => Ok(MergePolicy::Skip),
+            _ => Err(format!(
+                "unknown merge policy {}, expected all-parents, first-parent or skip",
+                s
+            )),
+        }
+    }
+}
+
+// How much a commit that touched the selected lines counts for each file it changed.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScoringModel {
+    // Commits that touched many files count less than focused ones.
+    #[default]
+    TfIdf,
+    // Every commit counts the same, i.e. raw co-occurrences.
+    Count,
+}
+
+impl FromStr for ScoringModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tf-idf" => Ok(ScoringModel::TfIdf),
+            "count" => Ok(ScoringModel::Count),
+            _ => Err(format!(
+                "unknown scoring model {}, expected tf-idf or count",
+                s
+            )),
+        }
+    }
+}
+
+// Where the history is read from, see git_backend.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum GitBackendKind {
+    // Runs the git binary.
+    #[default]
+    Git,
+    // Reads lookups, commit details and patches in-process. History walks (log) still run
+    // git, so it has to be installed either way and indexing takes as long.
+    Libgit2,
+}
+
+impl FromStr for GitBackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "git" => Ok(GitBackendKind::Git),
+            "libgit2" => Ok(GitBackendKind::Libgit2),
+            _ => Err(format!("unknown git backend {}, expected git or libgit2", s)),
+        }
+    }
+}
+
+// How `-t index` goes through the workspace, `-t indexfile` always replays the one file.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum IndexingMode {
+    // Replays the history of every file on its own.
+    #[default]
+    PerFile,
+    // Replays the history of the repository once, for all the files at the same time.
+    Repository,
+}
+
+impl FromStr for IndexingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "per-file" => Ok(IndexingMode::PerFile),
+            "repository" => Ok(IndexingMode::Repository),
+            _ => Err(format!(
+                "unknown indexing mode {}, expected per-file or repository",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ConfigError {
+    pub path: Option<PathBuf>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "Invalid config {}: {}", path.display(), self.message),
+            None => write!(f, "Invalid config: {}", self.message),
         }
     }
 }
 
-pub fn read_config(config_file_name: &str) -> Config {
-    let home_dir_path: PathBuf = simple_home_dir::home_dir().unwrap_or_default();
-    let joined_path = home_dir_path.join(config_file_name);
-    let config_file = File::open(joined_path.clone());
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    pub fn apply(&mut self, layer: ConfigLayer) {
+        if let Some(file_count_threshold) = layer.file_count_threshold {
+            self.file_count_threshold = file_count_threshold;
+        }
+        if layer.db_dir.is_some() {
+            self.db_dir = layer.db_dir;
+        }
+        if let Some(merge_policy) = layer.merge_policy {
+            self.merge_policy = merge_policy;
+        }
+        if let Some(scoring_model) = layer.scoring_model {
+            self.scoring_model = scoring_model;
+        }
+        if layer.score_half_life_days.is_some() {
+            self.score_half_life_days = layer.score_half_life_days;
+        }
+        if let Some(git_backend) = layer.git_backend {
+            self.git_backend = git_backend;
+        }
+        if let Some(indexing_mode) = layer.indexing_mode {
+            self.indexing_mode = indexing_mode;
+        }
+        self.include.extend(layer.include);
+        self.exclude.extend(layer.exclude);
+        self.ignored_authors.extend(layer.ignored_authors);
+        self.ignored_commits.extend(layer.ignored_commits);
+    }
+
+    pub fn path_filter(&self, workspace_path: &str) -> Result<PathFilter, ConfigError> {
+        PathFilter::new(workspace_path, &self.include, &self.exclude)
+    }
+
+    pub fn is_ignored_commit(&self, commit_hash: &str) -> bool {
+        // Commits are kept by full hash, the configured ones may be abbreviated.
+        self.ignored_commits
+            .iter()
+            .any(|ignored| !ignored.is_empty() && commit_hash.starts_with(ignored))
+    }
 
-    match config_file {
-        Ok(_) => {
-            let mut config_stream = serde_json::Deserializer::from_reader(config_file.unwrap());
-            // TODO: Fix the error handling and ensure users are able to watch logs for this
-            let config = Config::deserialize(&mut config_stream)
-                .expect("Expected config to be deserialized, probably the format is wrong");
-            config
+    pub fn is_ignored_author(&self, author: &str, email: &str) -> bool {
+        self.ignored_authors
+            .iter()
+            .any(|ignored| ignored == author || ignored.eq_ignore_ascii_case(email))
+    }
+}
+
+// Reads a single config file, Ok(None) if it doesn't exist.
+pub fn read_config(config_file_path: &Path) -> Result<Option<ConfigLayer>, ConfigError> {
+    let config_str = match std::fs::read_to_string(config_file_path) {
+        Ok(config_str) => config_str,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(ConfigError {
+                path: Some(config_file_path.to_path_buf()),
+                message: e.to_string(),
+            });
         }
-        Err(_) => {
-            // TODO: Add a log that you are using defaults
-            Config::default()
+    };
+    if config_str.trim().is_empty() {
+        return Ok(Some(ConfigLayer::default()));
+    }
+    serde_json::from_str(&config_str)
+        .map(Some)
+        .map_err(|e| ConfigError {
+            path: Some(config_file_path.to_path_buf()),
+            message: e.to_string(),
+        })
+}
+
+pub fn load_config(workspace_path: &str, overrides: ConfigLayer) -> Result<Config, ConfigError> {
+    let mut config_obj = Config::default();
+    if let Some(home_dir_path) = simple_home_dir::home_dir()
+        && let Some(layer) = read_config(&home_dir_path.join(config::CONFIG_FILE_NAME))?
+    {
+        config_obj.apply(layer);
+    }
+    let workspace_config_path = Path::new(workspace_path).join(config::WORKSPACE_CONFIG_FILE_NAME);
+    if let Some(layer) = read_config(&workspace_config_path)? {
+        config_obj.apply(layer);
+    }
+    if let Some(db_dir) = non_empty_env(config::DB_DIR_ENV_VAR) {
+        config_obj.db_dir = Some(db_dir);
+    }
+    config_obj.apply(overrides);
+    if config_obj
+        .score_half_life_days
+        .is_some_and(|half_life| half_life.is_nan() || half_life <= 0.0)
+    {
+        return Err(ConfigError {
+            path: None,
+            message: "score_half_life_days must be greater than 0".to_string(),
+        });
+    }
+    // Fail early on bad globs rather than halfway through indexing.
+    config_obj.path_filter(workspace_path)?;
+    Ok(config_obj)
+}
+
+// Include/exclude globs, matched against paths relative to the workspace.
+#[derive(Clone, Debug)]
+pub struct PathFilter {
+    include: Gitignore,
+    exclude: Gitignore,
+}
+
+impl Default for PathFilter {
+    fn default() -> Self {
+        PathFilter {
+            include: Gitignore::empty(),
+            exclude: Gitignore::empty(),
         }
     }
 }
 
-pub fn trim_result(inp: String, threshold: usize) -> String {
-    // number of words in the string should not cross threshold
-    // just trim the string
-    let mut final_str: String = "".to_string();
-    let mut count: usize = 0;
-    for inp_word in inp.split(',') {
-        final_str.push_str(inp_word);
-        count += 1;
-        if count >= threshold {
-            break;
+impl PathFilter {
+    pub fn new(
+        workspace_path: &str,
+        include: &[String],
+        exclude: &[String],
+    ) -> Result<PathFilter, ConfigError> {
+        let build = |globs: &[String]| {
+            let mut builder = GitignoreBuilder::new(workspace_path);
+            for glob in globs {
+                builder.add_line(None, glob).map_err(|e| ConfigError {
+                    path: None,
+                    message: format!("bad glob {}: {}", glob, e),
+                })?;
+            }
+            builder.build().map_err(|e| ConfigError {
+                path: None,
+                message: e.to_string(),
+            })
+        };
+        Ok(PathFilter {
+            include: build(include)?,
+            exclude: build(exclude)?,
+        })
+    }
+
+    pub fn is_included(&self, relative_path: &Path, is_dir: bool) -> bool {
+        // Paths outside of the workspace can't match any of the globs.
+        if relative_path.has_root() && !relative_path.starts_with(self.exclude.path()) {
+            return is_dir || self.include.is_empty();
+        }
+        if self
+            .exclude
+            .matched_path_or_any_parents(relative_path, is_dir)
+            .is_ignore()
+        {
+            return false;
         }
-        final_str.push(',');
+        // Include globs usually name files (src/**/*.rs), so never prune folders with them.
+        if is_dir || self.include.is_empty() {
+            return true;
+        }
+        self.include
+            .matched_path_or_any_parents(relative_path, false)
+            .is_ignore()
+    }
+}
+
+// The folder all the workspace indices live in, the first of:
+// 1. `db_dir`, from --db-dir or the config
+// 2. $CONTEXT_PILOT_DB_DIR
+// 3. $XDG_CACHE_HOME/contextpilot
+// 4. ~/.context_pilot_db
+pub fn db_root(db_dir: Option<&str>) -> Option<String> {
+    if let Some(db_dir) = db_dir {
+        let db_dir = match db_dir.strip_prefix("~/") {
+            Some(stripped) => simple_home_dir::home_dir()
+                .map(|home| home.join(stripped).to_string_lossy().to_string())
+                .unwrap_or(db_dir.to_string()),
+            None => db_dir.to_string(),
+        };
+        return Some(db_dir.trim_end_matches(['/', '\\']).to_string());
+    }
+    if let Some(db_dir) = non_empty_env(config::DB_DIR_ENV_VAR) {
+        return Some(db_dir.trim_end_matches(['/', '\\']).to_string());
+    }
+    // Relative values are invalid per the spec, and ignored.
+    if let Some(cache_dir) = non_empty_env("XDG_CACHE_HOME")
+        && Path::new(&cache_dir).is_absolute()
+    {
+        return Some(
+            Path::new(&cache_dir)
+                .join(config::XDG_DB_FOLDER)
+                .to_str()?
+                .to_string(),
+        );
+    }
+    let home = simple_home_dir::home_dir()?;
+    Some(home.join(config::DB_FOLDER).to_str()?.to_string())
+}
+
+fn non_empty_env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+// Name of the folder a workspace's index lives in: the name of the workspace, for humans,
+// and a hash of its canonical path so that workspaces never share a folder, whatever the
+// platform or the way the path was spelled.
+pub fn workspace_key(workspace_path: &str) -> String {
+    let canonical_path = std::fs::canonicalize(workspace_path)
+        .or_else(|_| std::path::absolute(workspace_path))
+        .unwrap_or_else(|_| PathBuf::from(workspace_path));
+    let digest = Sha256::digest(canonical_path.to_string_lossy().as_bytes());
+    let hash: String = digest[..8]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    let name: String = canonical_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || "-_.".contains(c) { c } else { '_' })
+        .collect();
+    if name.is_empty() {
+        hash
+    } else {
+        format!("{}-{}", name, hash)
     }
-    final_str
 }
 
+// The folder the index of a workspace lives in. Everything that reads or writes the index
+// must go through this.
+pub fn db_folder_path(workspace_path: &str, config: &Config) -> Option<PathBuf> {
+    Some(Path::new(&db_root(config.db_dir.as_deref())?).join(workspace_key(workspace_path)))
+}
+
+// Where versions before the workspace keys put the index: the workspace path appended to
+// ~/.context_pilot_db, with C:\ stripped on Windows. They had no other root, so this doesn't
+// follow db_root.
+pub fn legacy_db_folder_path(workspace_path: &str) -> Option<PathBuf> {
+    let workspace_path = workspace_path.strip_prefix(r"C:\").unwrap_or(workspace_path);
+    let home = simple_home_dir::home_dir()?;
+    Some(PathBuf::from(format!(
+        "{}{}{}",
+        home.join(config::DB_FOLDER).to_str()?,
+        std::path::MAIN_SEPARATOR,
+        workspace_path
+    )))
+}
+
+// A page of ranked results: skips `offset` of them and keeps at most `limit` (0 keeps all).
+pub fn page_results<T>(results: Vec<T>, offset: usize, limit: usize) -> Vec<T> {
+    let limit = if limit == 0 { usize::MAX } else { limit };
+    results.into_iter().skip(offset).take(limit).collect()
+}
+
+#[cfg(test)]
 mod test {
     use super::*;
 
     #[test]
-    fn test_trim_result() {
-        let inp = "a,b,c,d,e,f,g,h,i,j,k,l,m,n,o,p,q,r,s,t,u,v,w,x,y,z".to_string();
-        let threshold = 10;
-        let out = trim_result(inp, threshold);
-        assert_eq!(out, "a,b,c,d,e,f,g,h,i,j");
+    fn test_page_results() {
+        let results: Vec<usize> = (1..=25).collect();
+        assert_eq!(page_results(results.clone(), 0, 10), (1..=10).collect::<Vec<usize>>());
+        assert_eq!(page_results(results.clone(), 20, 10), (21..=25).collect::<Vec<usize>>());
+        assert_eq!(page_results(results.clone(), 30, 10), Vec::<usize>::new());
+        assert_eq!(page_results(results.clone(), 5, 0).len(), 20);
     }
 
     #[test]
-    fn test_trim_result_from_config() {
-        let inp = "a,b,c,d,e,f,g,h,i,j,k,l,m,n,o,p,q,r,s,t,u,v,w,x,y,z".to_string();
-        let config_obj = Config {
-            file_count_threshold: 10,
+    fn test_db_folder_path_follows_the_config() {
+        let config_for = |db_dir: &str| Config {
+            db_dir: Some(db_dir.to_string()),
+            ..Default::default()
         };
-        let out = trim_result(inp, config_obj.file_count_threshold);
-        assert_eq!("a,b,c,d,e,f,g,h,i,j", out);
+        let first = db_folder_path("/workspace", &config_for("/tmp/first/")).unwrap();
+        let second = db_folder_path("/workspace", &config_for("/tmp/second")).unwrap();
+        assert_eq!(first.parent(), Some(Path::new("/tmp/first")));
+        assert_eq!(second.parent(), Some(Path::new("/tmp/second")));
+        assert_eq!(first.file_name(), second.file_name());
     }
 }
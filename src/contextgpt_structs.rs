@@ -3,6 +3,9 @@ use std::str::FromStr;
 use serde::{Deserialize, Serialize};
 use structopt::StructOpt;
 
+use crate::config_impl::{GitBackendKind, IndexingMode, MergePolicy, ScoringModel};
+use crate::output::OutputFormat;
+
 // This also adds an impl: get_field to get the corresponding field from the field name (&str)
 #[macro_export]
 macro_rules! get_struct_names {
@@ -35,7 +38,13 @@ get_struct_names! {
         Query,
         Descriptions,  // alias: desc
         ListSubdirs,  // To be used by plugins!
-        IndexFile
+        IndexFile,
+        Serve,  // JSON-RPC daemon over stdio
+        Lsp,  // Language server over stdio
+        Export,  // Index to a portable archive
+        Import,  // Index from a portable archive
+        Gc,  // Cleans up and compacts the index
+        Status  // What the index holds and what's missing from it
     }
 }
 
@@ -51,6 +60,12 @@ impl FromStr for RequestTypeOptions {
             "desc" => Ok(RequestTypeOptions::Descriptions),
             "listsubdirs" => Ok(RequestTypeOptions::ListSubdirs),
             "indexfile" => Ok(RequestTypeOptions::IndexFile),
+            "serve" => Ok(RequestTypeOptions::Serve),
+            "lsp" => Ok(RequestTypeOptions::Lsp),
+            "export" => Ok(RequestTypeOptions::Export),
+            "import" => Ok(RequestTypeOptions::Import),
+            "gc" => Ok(RequestTypeOptions::Gc),
+            "status" => Ok(RequestTypeOptions::Status),
             _ => Err(format!(
                 "Could not parse the request type: {}, available field names: {:?}",
                 request_type,
@@ -76,16 +91,46 @@ pub(crate) struct Cli {
 
     #[structopt(short = "i")]
     pub index_subfolder: Option<String>,
-}
 
-#[derive(Default, Debug, Clone, Deserialize, Serialize)]
-pub struct AuthorDetails {
-    pub commit_hash: String,
-    pub author_full_name: String,
-    pub origin_file_path: String,
-    pub contextual_file_paths: Vec<String>,
-    pub line_number: usize,
-    pub end_line_number: usize,
+    // One of: text (default), json, ndjson. Applies to query and desc requests.
+    #[structopt(long = "format", default_value = "text")]
+    pub format: OutputFormat,
+
+    // Config overrides, these take precedence over the config files.
+    // The list options are comma separated, like -i.
+    #[structopt(long = "include")]
+    pub include: Option<String>,
+    #[structopt(long = "exclude")]
+    pub exclude: Option<String>,
+    #[structopt(long = "ignore-authors")]
+    pub ignore_authors: Option<String>,
+    #[structopt(long = "ignore-commits")]
+    pub ignore_commits: Option<String>,
+    #[structopt(long = "db-dir")]
+    pub db_dir: Option<String>,
+    // One of: all-parents (default), first-parent, skip.
+    #[structopt(long = "merge-policy")]
+    pub merge_policy: Option<MergePolicy>,
+    // One of: tf-idf (default), count.
+    #[structopt(long = "scoring")]
+    pub scoring_model: Option<ScoringModel>,
+    // Halves the weight of commits every <days>, no decay by default.
+    #[structopt(long = "half-life")]
+    pub score_half_life_days: Option<f64>,
+    // One of: git (default), libgit2. libgit2 only reads lookups and commit details itself,
+    // history walks and their patches run git either way, so indexing isn't any faster.
+    #[structopt(long = "git-backend")]
+    pub git_backend: Option<GitBackendKind>,
+    // One of: per-file (default), repository.
+    #[structopt(long = "indexing-mode")]
+    pub indexing_mode: Option<IndexingMode>,
+
+    // Number of related files to return (0 for all), overrides file_count_threshold.
+    #[structopt(short = "n", long = "limit")]
+    pub limit: Option<usize>,
+    // Number of related files to skip, for pagination.
+    #[structopt(long = "offset", default_value = "0")]
+    pub offset: usize,
 }
 
 #[derive(Default, Debug, Clone, Deserialize, Serialize)]
@@ -93,5 +138,107 @@ pub struct AuthorDetailsV2 {
     pub line_number: usize,
     pub origin_file_path: String,
     pub commit_hashes: Vec<String>,
-    pub author_full_name: Vec<String>,
+}
+
+// The `gc` output: what was removed from the index, sizes are of the whole DB folder.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct GcReport {
+    // Files that don't exist in the workspace anymore.
+    pub removed_files: usize,
+    // Commits no line points to.
+    pub removed_commits: usize,
+    // Temporary files and files of older index layouts.
+    pub removed_leftovers: usize,
+    pub size_before: u64,
+    pub size_after: u64,
+}
+
+// The `status` output, paths are relative to the workspace.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct IndexStatus {
+    pub indexed_files: usize,
+    // Files indexing goes through: not ignored by .gitignore, include and exclude.
+    pub eligible_files: usize,
+    // Of the whole DB folder.
+    pub size_on_disk: u64,
+    // Tables and pages of the database file, the index has no shards anymore.
+    pub tables: usize,
+    pub pages: u64,
+    pub format_version: Option<u32>,
+    pub files: Vec<IndexedFileStatus>,
+    pub failed_files: Vec<FailedFile>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct IndexedFileStatus {
+    pub path: String,
+    // The commit the file was last indexed at.
+    pub indexed_commit: Option<String>,
+    // The commit that last changed the file at HEAD, none once it's deleted.
+    pub latest_commit: Option<String>,
+    // The index is behind HEAD for this file.
+    pub stale: bool,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FailedFile {
+    pub path: String,
+    pub reason: String,
+}
+
+// A single entry of the `query` output: a file that changed together with the selected lines.
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RelatedFile {
+    pub path: String,
+    // Relevance, relative to the best match (which gets 1).
+    pub score: f64,
+    // Number of times a commit of the selected lines also changed this file.
+    pub occurrences: usize,
+    // Set when the commits knew the file under another name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub renamed_from: Option<String>,
+    // The file doesn't exist anymore at HEAD.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deleted: bool,
+}
+
+// A single entry of the `desc` output: one commit that touched the selected lines.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CommitDescription {
+    pub hash: String,
+    pub title: String,
+    pub body: String,
+    pub author: String,
+    pub date: String,
+    pub url: String,
+}
+
+// Details of a single commit, as stored in the index.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CommitInfo {
+    pub author: String,
+    pub email: String,
+    pub date: String,   // ISO 8601
+    pub timestamp: i64, // Same as date, in seconds since epoch (used for ordering)
+    #[serde(default)]
+    pub subject: String,
+    #[serde(default)]
+    pub body: String,
+    // Paths relative to the repository root, as git reports them.
+    #[serde(default)]
+    pub files_changed: Vec<String>,
+    // Used to weigh the commit when ranking related files.
+    #[serde(default)]
+    pub files_touched: usize,
+}
+
+// A single entry of the `author` output: someone who touched the selected lines.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AuthorSummary {
+    pub author: String,
+    pub email: String,
+    pub lines: usize,
+    pub commits: usize,
+    pub first_touch: String,
+    pub last_touch: String,
 }
@@ -0,0 +1,318 @@
+// Long running mode for editor plugins: `contextpilot <workspace> -t serve`.
+//
+// Speaks JSON-RPC 2.0 over stdin/stdout, one message per line. Loaded files are kept in
+// memory between calls, so a query doesn't have to read them from the index again.
+//
+// Methods:
+// - index { subfolders?: [string] } -> { indexed: number }
+// - indexFile { file: string } -> { indexed: number }
+// - query { file: string, start: number, end: number, limit?: number, offset?: number }
+//   -> [{ path, score, occurrences }], ranked, limit defaults to file_count_threshold (0 for all)
+// - describe { file: string, start: number, end: number } -> [{ hash, title, body, author, date, url }]
+// - listSubdirs {} -> [string]
+// - status {} -> { workspace, state, loadedFiles, indexedFiles }
+// - shutdown {} -> null (and the daemon exits)
+//
+// While indexing, `progress` notifications are pushed: { file, indexed, total }.
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::Mutex;
+use tokio::sync::mpsc::{UnboundedSender, unbounded_channel};
+
+use crate::config_impl;
+use crate::contextgpt_structs::RequestTypeOptions;
+use crate::db::DB;
+use crate::git_command_algo;
+use crate::rpc::{self, Notification, Request, Response, RpcError};
+use crate::{Server, State};
+
+#[derive(Deserialize)]
+struct IndexParams {
+    #[serde(default)]
+    subfolders: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct FileParams {
+    file: String,
+}
+
+#[derive(Deserialize)]
+struct RangeParams {
+    file: String,
+    start: usize,
+    end: usize,
+}
+
+#[derive(Deserialize)]
+struct QueryParams {
+    file: String,
+    start: usize,
+    end: usize,
+    #[serde(default)]
+    limit: Option<usize>,
+    #[serde(default)]
+    offset: usize,
+}
+
+struct Daemon {
+    workspace_path: String,
+    server: Server,
+    // Canonical file path -> DB with the shards for that file already loaded.
+    loaded: HashMap<String, Arc<Mutex<DB>>>,
+    outgoing: UnboundedSender<String>,
+    indexed_files: usize,
+}
+
+impl Daemon {
+    fn send<T: serde::Serialize>(&self, message: &T) {
+        let message = serde_json::to_string(message).expect("Failed to serialize message");
+        // Only fails once the writer is gone, at which point there's nobody to tell.
+        let _ = self.outgoing.send(message);
+    }
+
+    fn resolve_file(&self, file: &str) -> Result<String, RpcError> {
+        let mut path = PathBuf::from(file);
+        if path.is_relative() {
+            path = Path::new(&self.workspace_path).join(path);
+        }
+        let path = path
+            .canonicalize()
+            .map_err(|e| RpcError::invalid_params(format!("Invalid file {}: {}", file, e)))?;
+        Ok(path.to_string_lossy().to_string())
+    }
+
+    // Lets other processes at the index until the next request.
+    async fn close_stores(&self) {
+        for db in self.loaded.values() {
+            db.lock().await.store.close();
+        }
+    }
+
+    async fn load_db(&mut self, file_path: &str) -> Arc<Mutex<DB>> {
+        if let Some(db) = self.loaded.get(file_path) {
+            return db.clone();
+        }
+        let db = Server::open_db(&self.workspace_path, file_path, &self.server.config).await;
+        self.loaded.insert(file_path.to_string(), db.clone());
+        db
+    }
+
+    // Runs the indexing through the regular server, forwarding progress notifications.
+    async fn run_indexing(
+        &mut self,
+        file_path: Option<String>,
+        request_type: Option<RequestTypeOptions>,
+        subfolders: Option<Vec<String>>,
+    ) -> Result<Value, RpcError> {
+        let total = match &file_path {
+            Some(_) => 1,
+            None => {
+                let path_filter = self
+                    .server
+                    .config
+                    .path_filter(&self.workspace_path)
+                    .unwrap_or_default();
+                git_command_algo::get_all_valid_files(
+                    self.workspace_path.clone(),
+                    Some(String::from(".gitignore")),
+                )
+                .iter()
+                .filter(|path| {
+                    path.strip_prefix(&self.workspace_path)
+                        .is_ok_and(|relative_path| path_filter.is_included(relative_path, false))
+                })
+                .count()
+            }
+        };
+        let (progress_tx, mut progress_rx) = unbounded_channel::<String>();
+        let outgoing = self.outgoing.clone();
+        let forwarder = tokio::spawn(async move {
+            // Files in sub-directories can be stored more than once, only count them once.
+            let mut indexed: HashSet<String> = HashSet::new();
+            while let Some(file) = progress_rx.recv().await {
+                if !indexed.insert(file.clone()) {
+                    continue;
+                }
+                let notification = Notification::new(
+                    "progress",
+                    json!({ "file": file, "indexed": indexed.len(), "total": total }),
+                );
+                let _ = outgoing.send(serde_json::to_string(&notification).unwrap());
+            }
+            indexed.len()
+        });
+
+        self.server.state = State::Starting;
+        let mut server = self.server.clone();
+        server.progress = Some(progress_tx);
+        let workspace_path = self.workspace_path.clone();
+        let outcome = tokio::spawn(async move {
+            server
+                .handle_server(
+                    workspace_path.as_str(),
+                    file_path,
+                    None,
+                    None,
+                    request_type,
+                    subfolders,
+                )
+                .await;
+        })
+        .await;
+        // The server (and the progress sender with it) is dropped by now.
+        let indexed = forwarder.await.unwrap_or(0);
+        self.indexed_files += indexed;
+
+        match outcome {
+            Ok(_) => {
+                self.server.state = State::Running;
+                Ok(json!({ "indexed": indexed }))
+            }
+            Err(e) => {
+                self.server.state = State::Failed;
+                Err(RpcError::internal(format!("Indexing failed: {}", e)))
+            }
+        }
+    }
+
+    async fn dispatch(&mut self, request: &Request) -> Result<Value, RpcError> {
+        match request.method.as_str() {
+            "index" => {
+                let params: IndexParams = rpc::parse_params(request.params.clone())?;
+                let result = self.run_indexing(None, None, params.subfolders).await;
+                // Anything could have changed on disk.
+                self.loaded.clear();
+                result
+            }
+            "indexFile" => {
+                let params: FileParams = rpc::parse_params(request.params.clone())?;
+                let file_path = self.resolve_file(&params.file)?;
+                let result = self
+                    .run_indexing(
+                        Some(file_path.clone()),
+                        Some(RequestTypeOptions::IndexFile),
+                        None,
+                    )
+                    .await;
+                self.loaded.remove(&file_path);
+                result
+            }
+            "query" => {
+                let params: QueryParams = rpc::parse_params(request.params.clone())?;
+                let file_path = self.resolve_file(&params.file)?;
+                let db = self.load_db(&file_path).await;
+                let related_files = tokio::spawn(async move {
+                    db.lock()
+                        .await
+                        .query(file_path, params.start, params.end)
+                        .await
+                })
+                .await
+                .map_err(|e| RpcError::internal(format!("Query failed: {}", e)))?;
+                let limit = params
+                    .limit
+                    .unwrap_or(self.server.config.file_count_threshold);
+                Ok(serde_json::to_value(config_impl::page_results(
+                    related_files,
+                    params.offset,
+                    limit,
+                ))
+                .unwrap())
+            }
+            "describe" => {
+                let params: RangeParams = rpc::parse_params(request.params.clone())?;
+                let file_path = self.resolve_file(&params.file)?;
+                let db = self.load_db(&file_path).await;
+                let descriptions = tokio::spawn(async move {
+                    db.lock()
+                        .await
+                        .query_descriptions(file_path, params.start, params.end)
+                        .await
+                })
+                .await
+                .map_err(|e| RpcError::internal(format!("Describe failed: {}", e)))?;
+                Ok(serde_json::to_value(descriptions).unwrap())
+            }
+            "listSubdirs" => Ok(json!(git_command_algo::get_all_valid_directories(
+                self.workspace_path.clone(),
+                Some(String::from(".gitignore")),
+            ))),
+            "status" => {
+                let mut loaded_files: Vec<&String> = self.loaded.keys().collect();
+                loaded_files.sort();
+                Ok(json!({
+                    "workspace": self.workspace_path,
+                    "state": format!("{:?}", self.server.state),
+                    "loadedFiles": loaded_files,
+                    "indexedFiles": self.indexed_files,
+                }))
+            }
+            method => Err(RpcError::method_not_found(method)),
+        }
+    }
+}
+
+pub async fn serve(workspace_path: String, server: Server) {
+    let (outgoing, mut outgoing_rx) = unbounded_channel::<String>();
+    // A single writer owns stdout so that responses and notifications never interleave.
+    let writer = tokio::spawn(async move {
+        let mut stdout = tokio::io::stdout();
+        while let Some(message) = outgoing_rx.recv().await {
+            if stdout.write_all(message.as_bytes()).await.is_err()
+                || stdout.write_all(b"\n").await.is_err()
+                || stdout.flush().await.is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    let mut daemon = Daemon {
+        workspace_path,
+        server,
+        loaded: HashMap::new(),
+        outgoing,
+        indexed_files: 0,
+    };
+    daemon.server.state = State::Running;
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request = match rpc::parse_request(&line) {
+            Ok(request) => request,
+            Err(response) => {
+                daemon.send(&response);
+                continue;
+            }
+        };
+        if request.method == "shutdown" {
+            if let Some(id) = request.id {
+                daemon.send(&Response::success(id, Value::Null));
+            }
+            break;
+        }
+        let result = daemon.dispatch(&request).await;
+        daemon.close_stores().await;
+        // Notifications (no id) never get a response.
+        if let Some(id) = request.id {
+            let response = match result {
+                Ok(value) => Response::success(id, value),
+                Err(error) => Response::failure(id, error),
+            };
+            daemon.send(&response);
+        }
+    }
+
+    daemon.server.state = State::Dead;
+    drop(daemon);
+    let _ = writer.await;
+}
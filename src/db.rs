@@ -1,128 +1,69 @@
 use crate::algo_loc;
-use crate::git_command_algo::get_latest_commit;
-use crate::git_command_algo::{get_commit_descriptions, get_commits_after, get_files_changed};
-use std::fs::OpenOptions;
-use std::io::Write;
+use crate::git_command_algo::{PathResolver, get_head_commit, get_latest_commit};
+use crate::git_command_algo::{describe_commits, get_commit_details, index_commits_since};
+use crate::embedded_store::{EmbeddedStore, INDEX_FILE_NAME};
+use crate::fs_utils::LOCK_FILE_NAME;
+use crate::git_backend::{GitBackend, backend};
+use crate::json_store::JsonStore;
+use crate::migrations::META_FILE_NAME;
+use crate::index_store::{FileLines, IndexStore, StoreError};
 use std::path::PathBuf;
-use std::{collections::HashMap, fs::File, path::Path};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{collections::HashMap, path::Path};
 
 use crate::algo_loc::perform_for_whole_file;
-use crate::config::MAX_ITEMS_IN_EACH_DB_FILE;
-use crate::contextgpt_structs::AuthorDetailsV2;
-use crate::{config, contextgpt_structs::AuthorDetails};
-
-type DBType = HashMap<String, HashMap<String, HashMap<u32, Vec<AuthorDetails>>>>;
-type DBTypeV2 = HashMap<usize, Vec<String>>;
-
-type MappingDBType = HashMap<String, Vec<u32>>;
+use crate::contextgpt_structs::{
+    AuthorDetailsV2, AuthorSummary, CommitDescription, CommitInfo, RelatedFile,
+};
+use crate::config_impl::Config;
+use crate::{config_impl, scoring};
+
+// Line number -> commit hashes, for a single file.
+type DBTypeV2 = FileLines;
+
+type CommitsDBType = HashMap<String, CommitInfo>;
+
+// How a file relates to the selected lines: the number of times it changed in the same
+// commits, and the total weight of those commits.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct CoChange {
+    pub occurrences: usize,
+    pub weight: f64,
+}
 
 #[allow(dead_code)]
 #[derive(Default, Clone)]
-pub struct DB {
-    pub index: u32,                // The line of code that you are at, right now? TODO:
+pub struct DB<S: IndexStore = EmbeddedStore> {
     pub folder_path: String, // Current folder path that this DB is processing, or the binary is running
-    pub curr_items: u32,     // TODO:
-    pub mapping_file_name: String, // This is for storing which file is in which folder/file? <-- TODO:
-    pub current_data: DBType, // The data that we have from the loaded DB into our inhouse member
     pub current_data_v2: DBTypeV2,
-    pub db_file_path: String,
-    pub mapping_file_path: String,
-    pub mapping_data: MappingDBType,
-    // pub mapping_data_v2: MappingDBTypeV2,
     pub curr_file_path: String,
     pub workspace_path: String,
-    pub indexing_file_name: String, // This is for storing the indexing metadata
+    pub commit_data: CommitsDBType, // Commit hash -> commit details, loaded from the index as needed
+    pub config: Config,
+    // Loaded on the first query (see load_path_resolver), renames don't change while a DB
+    // is open.
+    pub path_resolver: Option<PathResolver>,
+    // Where the lines are read from and stored to, set up by init_db.
+    pub store: S,
 }
 
 #[allow(dead_code)]
-impl DB {
-    pub fn read(&mut self) -> DBTypeV2 {
-        // let db_file_path = format!("{}/{}", self.folder_path, self.index);
-        if Path::new(self.db_file_path.as_str()).exists() {
-            let data_buffers = match std::fs::read_to_string(&self.db_file_path) {
-                Ok(s) => s,
-                Err(e) => {
-                    eprintln!("Error reading file: {}", e);
-                    return HashMap::new();
-                }
-            };
-            match serde_json::from_str(&data_buffers) {
-                Ok(data) => data,
-                Err(err) => {
-                    eprintln!("Failed to parse JSON: {}", err);
-                    HashMap::new()
-                }
-            }
-        } else {
-            eprintln!(
-                "The DB file doesn't exist for the given path: {}",
-                self.db_file_path
-            );
-            // TODO: Enable logging into a logging file and add two modes: debug and info
-            HashMap::new()
-        }
-    }
-
-    pub fn read_all(&mut self, valid_indices: Vec<u32>) -> DBTypeV2 {
-        let mut init_data: DBTypeV2 = HashMap::new();
-        // let folder_path = self.folder_path.clone();
-        // let workspace_path = self.workspace_path.clone();
-        // let curr_file_path = self.curr_file_path.clone();
-
-        // TODO: Clean this up, this is bad! valid_indices later on should just be a single file.
-        for &valid_index in valid_indices.iter() {
-            self.index = valid_index;
-
-            self.db_file_path = format!(
-                "{}{}{}.json",
-                self.folder_path,
-                std::path::MAIN_SEPARATOR,
-                self.index
-            );
-            let db_obj = Path::new(&self.db_file_path);
-            if !db_obj.exists() {
-                File::create(db_obj).expect("Couldn't find the DB file");
-                continue;
-                // return HashMap::new();
-            }
-            let current_data_v2 = self.read();
-            init_data.extend(current_data_v2.clone());
-        }
-        init_data
-    }
-
+impl DB<EmbeddedStore> {
     // Initialise the DB if it doesn't exist already
     pub fn init_db(&mut self, workspace_path: &str, curr_file_path: Option<&str>, cleanup: bool) {
-        // Strip C:\ from the self.folder_path value for Windows:
-        let mut folder_path_copy = self.folder_path.clone();
-        if cfg!(target_os = "windows") {
-            folder_path_copy = folder_path_copy.strip_prefix(r"C:\").unwrap().to_string();
-        }
-        let db_folder = format!(
-            "{}{}{}",
-            config::DB_FOLDER,
-            std::path::MAIN_SEPARATOR,
-            folder_path_copy
-        );
         self.workspace_path = String::from(workspace_path);
         self.curr_file_path = String::from(curr_file_path.unwrap_or(""));
-        // let mut main_folder_path = String::new();
 
-        if let Some(home) = simple_home_dir::home_dir() {
-            let folder_path = home.join(db_folder);
-
-            if let Some(path_str) = folder_path.to_str() {
-                self.folder_path = path_str.to_string();
-            } else {
-                eprintln!("Something went wrong while trying to get the string for the path");
-                return;
-            }
-        } else {
+        // The folder path is given as the workspace, the index lives in a folder keyed by it.
+        let Some(db_folder_path) = config_impl::db_folder_path(&self.folder_path, &self.config)
+        else {
             eprintln!("Failed to determine the home directory");
             return;
+        };
+        if let Some(legacy_folder_path) = config_impl::legacy_db_folder_path(&self.folder_path) {
+            move_legacy_index(&legacy_folder_path, &db_folder_path);
         }
-        self.index = 0;
-        self.curr_items = 0;
+        self.folder_path = db_folder_path.to_string_lossy().to_string();
         // Check if self.folder_path exists, cleanup if cleanup is required.
         if cleanup && Path::new(&self.folder_path).exists() {
             // Remove the folder and all its contents
@@ -134,150 +75,73 @@ impl DB {
         std::fs::create_dir_all(&self.folder_path)
             .unwrap_or_else(|_| panic!("Unable to create folder for: {}", self.folder_path));
 
-        // Search for the index
-        let mut db_file_index: Option<Vec<u32>> = None;
-        if curr_file_path.is_none() {
-            db_file_index = self.find_index(workspace_path);
-        } else {
+        self.store = EmbeddedStore::new(Path::new(&self.folder_path));
+        match self.store.upgrade() {
+            // Indexing the whole workspace writes it from scratch anyway.
+            Err(e @ StoreError::ReindexRequired(_)) if curr_file_path.is_none() => {
+                eprintln!("{}: {}, starting over", self.folder_path, e);
+                std::fs::remove_dir_all(&self.folder_path)
+                    .unwrap_or_else(|_| panic!("Unable to remove the folder: {}", self.folder_path));
+                std::fs::create_dir_all(&self.folder_path)
+                    .unwrap_or_else(|_| panic!("Unable to create folder for: {}", self.folder_path));
+            }
+            Err(e) => eprintln!("❌ {}: {}", self.folder_path, e),
+            Ok(()) => {}
+        }
+        // Nothing to load when indexing the whole workspace.
+        self.current_data_v2 = HashMap::new();
+        if let Some(curr_file_path) = curr_file_path {
             // convert curr_file_path to an absolute path:
-            let curr_file_path = PathBuf::from(curr_file_path.unwrap());
-            let curr_file_path = curr_file_path
+            let curr_file_path = PathBuf::from(curr_file_path)
                 .canonicalize()
                 .unwrap_or_else(|_| panic!("Unable to convert the path to absolute path"));
-            db_file_index = self.find_index(curr_file_path.as_path().to_str().unwrap());
-        }
-        if db_file_index.is_none() {
-            // No mapping yet - means no indexing hasn't happened yet.
-            self.current_data_v2 = HashMap::new();
-        } else {
-            // let db_file_index = self.find_index(curr_file_path.unwrap_or(""));
-            // Filename will be: <db_file_index>.json
-            let valid_indices = db_file_index.unwrap_or(vec![self.index]);
-            self.current_data_v2 = self.read_all(valid_indices.clone());
-        }
+            // The last line to query, `end_number` or the last line of the file when it's 0. None when the
+// file can't be read.
+fn last_line(file_path: &str, end_number: usize) -> Option<usize> {
+    if end_number != 0 {
+        return Some(end_number);
     }
+    Some(std::fs::read_to_string(file_path).ok()?.lines().count())
+}
 
-    fn read_mapping_file(&mut self) -> MappingDBType {
-        // Read the mapping file from the folder_path
-        self.mapping_file_name = "mapping.json".to_string();
-        let mapping_path = format!(
-            "{}{}{}",
-            self.folder_path,
-            std::path::MAIN_SEPARATOR,
-            self.mapping_file_name
-        );
-        self.mapping_file_path = String::from(mapping_path.clone());
-        let mapping_path_obj = Path::new(&mapping_path);
-        if !mapping_path_obj.exists() {
-            eprintln!("Mapping file does not exist at: {}", mapping_path);
-            self.mapping_data = HashMap::new();
-            self.db_file_path = format!(
-                "{}{}{}.json",
-                self.folder_path,
-                std::path::MAIN_SEPARATOR,
-                self.index
-            );
-            self.current_data = HashMap::new();
-            return HashMap::new();
+// Files are keyed by the absolute path.
+            self.load_file(&curr_file_path.to_string_lossy());
         }
-        let mapping_data = match std::fs::read_to_string(&mapping_path) {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("Error reading file: {}: {}", mapping_path, e);
-                return HashMap::new();
-            }
-        };
-        serde_json::from_str(mapping_data.as_str()).unwrap_or_else(|_| {
-            panic!(
-                "Unable to deserialize the mapping file, path: {}",
-                mapping_path
-            )
-        })
     }
+}
 
-    fn read_indexing_file(&mut self) -> HashMap<String, Vec<String>> {
-        // Read the indexing file from the folder_path
-        self.indexing_file_name = "indexing_metadata.json".to_string();
-        let indexing_path = format!(
-            "{}{}{}",
-            self.folder_path,
-            std::path::MAIN_SEPARATOR,
-            self.indexing_file_name
-        );
-        let indexing_path_obj = Path::new(&indexing_path);
-        if !indexing_path_obj.exists() {
-            // Does not exist: create a new file:
-            eprintln!(
-                "Indexing metadata file does not exist at: {}",
-                indexing_path
-            );
-            // Create an empty file
-            if let Err(e) = File::create(&indexing_path) {
-                eprintln!("Error creating file: {}: {}", indexing_path, e);
-            }
-            return HashMap::new();
-        }
-        let indexing_data = match std::fs::read_to_string(&indexing_path) {
-            Ok(s) => s,
+#[allow(dead_code)]
+impl<S: IndexStore> DB<S> {
+    // Where the history is read from, the git_backend config picks it.
+    fn git(&self) -> &'static dyn GitBackend {
+        backend(self.config.git_backend)
+    }
+
+    // Makes the given file the current one, with its lines from the store.
+    pub fn load_file(&mut self, file_path: &str) {
+        self.curr_file_path = file_path.to_string();
+        self.current_data_v2 = match self.store.get_file_lines(file_path) {
+            Ok(lines) => lines.unwrap_or_default(),
             Err(e) => {
-                eprintln!("Error reading file: {}: {}", indexing_path, e);
-                return HashMap::new();
+                eprintln!("Failed to read the index: {}", e);
+                HashMap::new()
             }
         };
-        serde_json::from_str(indexing_data.as_str()).unwrap_or_else(|_| {
-            panic!(
-                "Unable to deserialize the indexing metadata file, path: {}",
-                indexing_path
-            )
-        })
     }
 
-    pub fn find_index(&mut self, curr_file_path: &str) -> Option<Vec<u32>> {
-        self.mapping_data = self.read_mapping_file();
-        if self.mapping_data.is_empty() {
-            return None;
-        }
-        let indices = self.mapping_data.get(curr_file_path);
-        let mut mapping_json = self.mapping_data.clone();
-        if indices.is_none() {
-            // The mapping file is there but we just don't have the corresponding entry for it
-            // TODO: Store last available index for the DB
-            // self.index = self.index + 1;
-            self.index = self.get_available_index(&self.mapping_data);
-            self.db_file_path = format!(
-                "{}{}{}.json",
-                self.folder_path,
-                std::path::MAIN_SEPARATOR,
-                self.index
-            );
-            mapping_json.insert(curr_file_path.to_string(), vec![self.index]);
-            self.mapping_data = mapping_json.clone();
-            let init_mapping_string =
-                serde_json::to_string_pretty(&mapping_json).expect("Unable to create data");
-            let mut file = OpenOptions::new()
-                .write(true)
-                .append(false) // TODO: Would love to append here instead
-                .open(&self.mapping_file_path)
-                .unwrap();
-            writeln!(file, "{}", init_mapping_string)
-                .expect("Couldn't write to the mapping file, wow!");
-            return None;
-        }
-        indices.cloned()
+    pub fn is_indexed(&self, file_path: &str) -> bool {
+        self.store.has_file(file_path).unwrap_or_else(|e| {
+            eprintln!("Failed to read the index: {}", e);
+            false
+        })
     }
 
-    pub fn get_available_index(&self, mapping_json: &HashMap<String, Vec<u32>>) -> u32 {
-        mapping_json
-            .get("last_used_index")
-            .and_then(|v| v.first().copied())
-            .unwrap_or_else(|| {
-                let max_index = mapping_json
-                    .values()
-                    .flat_map(|v| v.iter().copied())
-                    .max()
-                    .unwrap_or(0);
-                max_index + 1
-            })
+    // Records that a file couldn't be indexed, `-t status` lists them.
+    pub fn mark_failed(&mut self, file_path: &str, reason: &str) {
+        eprintln!("❌ Failed to index {}: {}", file_path, reason);
+        if let Err(e) = self.store.mark_failed(file_path, reason) {
+            eprintln!("Failed to record the failure in the index: {}", e);
+        }
     }
 
     pub fn append_to_db(
@@ -286,12 +150,15 @@ impl DB {
         _: usize,
         all_data: HashMap<u32, AuthorDetailsV2>,
     ) {
+        // Lines of different files must never end up in the same map.
+        if self.curr_file_path != *configured_file_path && !self.current_data_v2.is_empty() {
+            self.store();
+        }
         self.curr_file_path = configured_file_path.clone();
         if all_data.is_empty() {
             return;
         }
 
-        self.curr_items += all_data.len() as u32; // Just track number of items
         for line_number in all_data.keys() {
             let single_detail = all_data.get(line_number).unwrap().clone();
             self.current_data_v2
@@ -301,62 +168,108 @@ impl DB {
         }
     }
 
-    pub fn _is_limit_crossed(&self) -> bool {
-        self.curr_items >= MAX_ITEMS_IN_EACH_DB_FILE
-    }
-
+    // Writes the lines of the current file, the commit it was indexed at and the details of
+    // its commits to the index.
     pub fn store(&mut self) {
         if self.current_data_v2.is_empty() {
             eprintln!("No data to store.");
             return;
         }
 
-        let db_file_path = format!(
-            "{}{}{}.json",
-            self.folder_path,
-            std::path::MAIN_SEPARATOR,
-            self.index
-        );
-
-        // Check if the file path already exists in the mapping data and if the index is already in the vector
-        let indices = self
-            .mapping_data
-            .entry(self.curr_file_path.clone())
-            .or_default();
-        if !indices.contains(&self.index) {
-            indices.push(self.index);
-        }
-        // Re-write the mapping file since data has changed:
-        self.index += 1; // increment index for the next file.
-        let output_string = serde_json::to_string(&self.current_data_v2);
-        let result_string = output_string.expect("Failed to serialize data");
-        if let Err(e) = std::fs::write(&db_file_path, result_string.clone()) {
-            eprintln!("❌ Failed writing DB file {}: {}", db_file_path, e);
-        } else {
-            println!("✅ Successfully stored shard: {}", db_file_path);
-        }
-
-        // Update mapping file
-        if let Ok(mut file) = File::create(&self.mapping_file_path) {
-            let mapping_string = serde_json::to_string_pretty(&self.mapping_data)
-                .expect("Failed to serialize mapping");
-            if let Err(e) = write!(file, "{}", mapping_string) {
-                eprintln!("❌ Failed writing mapping: {}", e);
+        // Find the last commit hash for the current file
+        let last_commit = get_latest_commit(self.git(), &self.curr_file_path);
+        let commit_hashes: Vec<String> = self.current_data_v2.values().flatten().cloned().collect();
+        let new_commits = match self.store.get_commits(&commit_hashes) {
+            Ok(stored) => {
+                let missing: Vec<String> = commit_hashes
+                    .into_iter()
+                    .filter(|commit_hash| !stored.contains_key(commit_hash))
+                    .collect();
+                get_commit_details(self.git(), &missing)
+            }
+            Err(e) => {
+                eprintln!("Failed to read commits from the index: {}", e);
+                HashMap::new()
+            }
+        };
+        // Commits first, so that the lines never point to commits the index doesn't know.
+        let stored = self.store.put_commits(&new_commits).and_then(|()| {
+            self.store.put_file_lines(
+                &self.curr_file_path,
+                &self.current_data_v2,
+                last_commit.as_deref(),
+            )
+        });
+        match stored {
+            Ok(()) => eprintln!("✅ Successfully stored: {}", self.curr_file_path),
+            Err(e) => {
+                let file_path = self.curr_file_path.clone();
+                self.mark_failed(&file_path, &format!("failed storing it: {}", e));
             }
-        } else {
-            eprintln!(
-                "❌ Failed to create mapping file: {}",
-                self.mapping_file_path
-            );
         }
+        self.commit_data.extend(new_commits);
 
-        // Find the last commit hash for the current file
-        let last_commit = get_latest_commit(&self.curr_file_path);
+        self.current_data_v2.clear(); // clear everything after storing
+    }
+
+    // Makes sure the details of the given commits are loaded, from the index or git. The ones
+    // from git are added to the index, so that git runs once per commit.
+    fn load_commit_data(&mut self, commit_hashes: &[String]) {
+        let missing: Vec<String> = commit_hashes
+            .iter()
+            .filter(|commit_hash| !self.commit_data.contains_key(*commit_hash))
+            .cloned()
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+        match self.store.get_commits(&missing) {
+            Ok(stored) => self.commit_data.extend(stored),
+            Err(e) => eprintln!("Failed to read commits from the index: {}", e),
+        }
+        // Files that aren't indexed yet, or indexes whose commit table was cleared by a
+        // migration, won't have these.
+        let missing: Vec<String> = missing
+            .into_iter()
+            .filter(|commit_hash| !self.commit_data.contains_key(commit_hash))
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+        let new_commits = get_commit_details(self.git(), &missing);
+        if let Err(e) = self.store.put_commits(&new_commits) {
+            eprintln!("Failed to write commits to the index: {}", e);
+        }
+        self.commit_data.extend(new_commits);
+    }
 
-        self.prepare_indexing_metadata(&self.curr_file_path.clone(), &last_commit);
+    // The commit the file was last indexed at, if it was.
+    pub fn last_indexed_commit(&self, file_path: &str) -> Option<String> {
+        match self.store.last_indexed_commit(file_path) {
+            Ok(last_indexed_commit) => last_indexed_commit,
+            Err(e) => {
+                eprintln!("Failed to read the indexing metadata: {}", e);
+                None
+            }
+        }
+    }
 
-        self.current_data_v2.clear(); // clear everything after storing
-        self.curr_items = 0; // reset
+    // Leaves out the commits that the config asks to ignore, by hash or by author.
+    fn retain_relevant_commits(&mut self, commit_hashes: &mut Vec<String>) {
+        if self.config.ignored_commits.is_empty() && self.config.ignored_authors.is_empty() {
+            return;
+        }
+        if !self.config.ignored_authors.is_empty() {
+            self.load_commit_data(commit_hashes);
+        }
+        let config = &self.config;
+        let commit_data = &self.commit_data;
+        commit_hashes.retain(|commit_hash| {
+            !config.is_ignored_commit(commit_hash)
+                && !commit_data
+                    .get(commit_hash)
+                    .is_some_and(|info| config.is_ignored_author(&info.author, &info.email))
+        });
     }
 
     pub fn raw_exists_and_return(
@@ -403,11 +316,39 @@ impl DB {
         (all_commit_hashes, uncovered_indices)
     }
 
+    // Groups consecutive lines that were last changed by the same commit (like git blame).
+    // Returns (start_line_number, end_line_number) pairs, both inclusive.
+    pub fn hunks(&self) -> Vec<(usize, usize)> {
+        let mut line_numbers: Vec<&usize> = self.current_data_v2.keys().collect();
+        line_numbers.sort();
+        let mut hunks: Vec<(usize, usize)> = vec![];
+        let mut current: Option<(usize, usize, &String)> = None;
+        for line_number in line_numbers {
+            let Some(last_commit) = self.current_data_v2[line_number].last() else {
+                continue;
+            };
+            current = match current {
+                Some((start, end, commit)) if end + 1 == *line_number && commit == last_commit => {
+                    Some((start, *line_number, commit))
+                }
+                Some((start, end, _)) => {
+                    hunks.push((start, end));
+                    Some((*line_number, *line_number, last_commit))
+                }
+                None => Some((*line_number, *line_number, last_commit)),
+            };
+        }
+        if let Some((start, end, _)) = current {
+            hunks.push((start, end));
+        }
+        hunks
+    }
+
     pub fn exists_and_return(
         &mut self,
         start_line_number: &usize,
         end_line_number: &usize,
-    ) -> (HashMap<String, usize>, Vec<u32>) {
+    ) -> (HashMap<String, CoChange>, Vec<u32>) {
         let mut uncovered_indices: Vec<u32> = vec![];
 
         // Find the "closest" maximum index to the given index.
@@ -415,7 +356,7 @@ impl DB {
         // Output you are looking for is of "7'"
 
         // Now get all the commit_hashes in the max_index entry.
-        let mut counter_for_paths: HashMap<String, usize> = HashMap::new();
+        let mut commit_hashes_in_range: Vec<String> = vec![];
         for i in *start_line_number..=*end_line_number {
             let mut max_index: Option<usize> = None;
             // Check if the exact index exists in our data
@@ -427,15 +368,7 @@ impl DB {
                     if let Some(commit_hashes) = self.current_data_v2.get(&index) {
                         // If the index is present, we can get the commit_hashes
                         // and add them to the counter_for_paths.
-                        for commit_hash in commit_hashes {
-                            // Compute contextual file paths using the commit hash.
-                            // We use git show for this.
-                            let relevant_file_paths = get_files_changed(commit_hash);
-                            // Add each file path and increment count if it already existed.
-                            for rel_path in relevant_file_paths.iter() {
-                                *counter_for_paths.entry(rel_path.clone()).or_insert(0) += 1;
-                            }
-                        }
+                        commit_hashes_in_range.extend(commit_hashes.clone());
                     }
                 }
                 None => {
@@ -444,66 +377,197 @@ impl DB {
                 }
             }
         }
+        self.retain_relevant_commits(&mut commit_hashes_in_range);
+        let counter_for_paths = self.count_files_changed(&commit_hashes_in_range);
         (counter_for_paths, uncovered_indices)
     }
 
-    fn update_last_indexed_commit(
-        &mut self,
-        file_path: &String,
-        commit_hash: &str,
-    ) -> Result<(), String> {
-        // Read the existing indexing metadata
-        let mut indexing_metadata = self.read_indexing_file();
-
-        // Update or create the entry for this file path
-        indexing_metadata
-            .entry(file_path.clone())
-            .or_insert_with(Vec::new)
-            .push(commit_hash.to_string());
-
-        // Write back to the indexing metadata file
-        let indexing_path = format!(
-            "{}{}{}",
-            self.folder_path,
-            std::path::MAIN_SEPARATOR,
-            self.indexing_file_name
-        );
-
-        // Serialize the updated metadata
-        let indexing_string = serde_json::to_string_pretty(&indexing_metadata)
-            .map_err(|e| format!("Failed to serialize indexing metadata: {}", e))?;
-
-        // Write to file
-        std::fs::write(&indexing_path, indexing_string)
-            .map_err(|e| format!("Failed to write indexing metadata: {}", e))?;
+    // Counts how often each file was changed by the given commits, one count per occurrence
+    // of a commit, and weighs them with the scoring model. The files changed come from the
+    // commit table, git only runs for commits it doesn't have yet.
+    fn count_files_changed(&mut self, commit_hashes: &[String]) -> HashMap<String, CoChange> {
+        let mut occurrences: HashMap<&String, usize> = HashMap::new();
+        for commit_hash in commit_hashes {
+            *occurrences.entry(commit_hash).or_insert(0) += 1;
+        }
+        let tracked_files = self.path_resolver().tracked_file_count();
+        self.load_commit_data(commit_hashes);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as i64)
+            .unwrap_or(0);
+        let mut counter_for_paths: HashMap<String, CoChange> = HashMap::new();
+        for (commit_hash, count) in occurrences {
+            // Unknown to git, nothing to count.
+            let Some(commit_info) = self.commit_data.get(commit_hash) else {
+                continue;
+            };
+            let age_days = scoring::age_in_days(commit_info.timestamp, now);
+            let weight = scoring::commit_weight(
+                &self.config,
+                commit_info.files_touched,
+                tracked_files,
+                age_days,
+            );
+            // Add each file path and increment count if it already existed.
+            for rel_path in commit_info.files_changed.iter() {
+                let co_change = counter_for_paths.entry(rel_path.clone()).or_default();
+                co_change.occurrences += count;
+                co_change.weight += weight * count as f64;
+            }
+        }
+        counter_for_paths
+    }
 
-        Ok(())
+    // Highest score first, ties broken by occurrences then path so that the order is stable
+    // across runs. Paths are resolved to where the files live now, so that renamed files
+    // count once.
+    fn rank_related_files(&mut self, counter_for_paths: HashMap<String, CoChange>) -> Vec<RelatedFile> {
+        let path_resolver = self.path_resolver();
+        let mut by_current_path: HashMap<String, RelatedFile> = HashMap::new();
+        for (path, co_change) in counter_for_paths {
+            let (current_path, renamed_from, deleted) = path_resolver.resolve(&path);
+            let related_file = by_current_path
+                .entry(current_path.clone())
+                .or_insert(RelatedFile {
+                    path: current_path,
+                    deleted,
+                    ..Default::default()
+                });
+            // The raw weight for now, normalized once everything is summed up.
+            related_file.score += co_change.weight;
+            related_file.occurrences += co_change.occurrences;
+            // Keep the same old name whatever the HashMap order was.
+            if let Some(renamed_from) = renamed_from
+                && related_file
+                    .renamed_from
+                    .as_ref()
+                    .is_none_or(|existing| renamed_from < *existing)
+            {
+                related_file.renamed_from = Some(renamed_from);
+            }
+        }
+        let mut related_files: Vec<RelatedFile> = by_current_path.into_values().collect();
+        let max_weight = related_files
+            .iter()
+            .map(|related_file| related_file.score)
+            .fold(0.0, f64::max);
+        for related_file in related_files.iter_mut() {
+            related_file.score = scoring::normalize(related_file.score, max_weight);
+        }
+        related_files.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| b.occurrences.cmp(&a.occurrences))
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        related_files
+    }
+
+    fn path_resolver(&mut self) -> &PathResolver {
+        let path_resolver = match self.path_resolver.take() {
+            Some(path_resolver) => path_resolver,
+            None => self.load_path_resolver(),
+        };
+        self.path_resolver.insert(path_resolver)
     }
 
-    fn prepare_indexing_metadata(&mut self, file_path: &String, last_commit_hash: &Option<String>) {
-        // If we have a valid commit hash, update the indexing metadata
-        if let Some(commit) = last_commit_hash {
-            if let Err(e) = self.update_last_indexed_commit(file_path, commit) {
-                eprintln!("Failed to update indexing metadata: {}", e);
+    // The resolver of the repository at HEAD. The one in the index is used as is when it was
+    // built at HEAD, and brought up to it when HEAD moved on since, so that only the first
+    // query (or indexing) walks the whole history. The result is stored for the next ones.
+    pub fn load_path_resolver(&mut self) -> PathResolver {
+        let Some(head_commit) = get_head_commit(self.git()) else {
+            return PathResolver::from_repo(self.git());
+        };
+        let stored = self.store.get_path_resolver().unwrap_or_else(|e| {
+            eprintln!("Failed to read the renames from the index: {}", e);
+            None
+        });
+        let path_resolver = match stored {
+            Some((commit_hash, path_resolver)) if commit_hash == head_commit => {
+                return path_resolver;
             }
+            Some((commit_hash, mut path_resolver)) => {
+                if path_resolver.catch_up(self.git(), &commit_hash) {
+                    path_resolver
+                } else {
+                    PathResolver::from_repo(self.git())
+                }
+            }
+            None => PathResolver::from_repo(self.git()),
+        };
+        if let Err(e) = self.store.put_path_resolver(&head_commit, &path_resolver) {
+            eprintln!("Failed to store the renames in the index: {}", e);
         }
+        path_resolver
     }
 
-    pub async fn query(&mut self, file_path: String, start_number: usize, end_number: usize) {
-        let mut end_line_number = end_number;
-        if end_number == 0 {
-            // Means, cover the whole file.
-            // end_number should be the last line number of the file.
-            end_line_number = std::fs::read_to_string(&file_path)
-                .unwrap_or_else(|_| panic!("Unable to read the file: {}", file_path))
-                .lines()
-                .count();
+    // Applies the commits that changed the file since it was indexed to its stored lines, and
+    // stores the result with the commit it's now indexed at. When they can't be applied, the
+    // file is indexed from scratch instead. Returns whether the lines were stored.
+    pub async fn catch_up(&mut self, file_path: &str) -> bool {
+        let file_path = absolute_path(file_path);
+        let file_path = file_path.as_str();
+        let Some(last_indexed_commit) = self.last_indexed_commit(file_path) else {
+            return false;
+        };
+        let Some(recent_commit) = get_latest_commit(self.git(), file_path) else {
+            return false;
+        };
+        if last_indexed_commit == recent_commit {
+            return false;
         }
+        if self.curr_file_path != file_path {
+            self.load_file(file_path);
+        }
+        let lines = match index_commits_since(
+            self.git(),
+            file_path,
+            &last_indexed_commit,
+            &self.current_data_v2,
+            self.config.merge_policy,
+        ) {
+            Some(lines) => lines,
+            None => {
+                perform_for_whole_file(file_path.to_string(), false, None, None, &self.config).await
+            }
+        };
+        self.replace_file_lines(file_path, lines);
+        true
+    }
+
+    // Stores the lines of the file in place of the ones it had, with the commit it's now
+    // indexed at, and loads them back for the query.
+    fn replace_file_lines(&mut self, file_path: &str, lines: HashMap<u32, AuthorDetailsV2>) {
+        self.curr_file_path = file_path.to_string();
+        self.current_data_v2.clear();
+        self.append_to_db(&file_path.to_string(), 0, lines);
+        self.store();
+        // Storing clears the lines.
+        self.load_file(file_path);
+    }
+
+    pub async fn query(
+        &mut self,
+        file_path: String,
+        start_number: usize,
+        end_number: usize,
+    ) -> Vec<RelatedFile> {
+        let Some(end_line_number) = last_line(&file_path, end_number) else {
+            eprintln!("Unable to read the file: {}", file_path);
+            return vec![];
+        };
         if self.current_data_v2.is_empty() {
             // No data to query - means no indexing has happened yet.
             // Let's treat this as a binary and perform operation.
-            let output =
-                algo_loc::perform_for_whole_file(file_path.clone(), false, None, None).await;
+            let output = algo_loc::perform_for_whole_file(
+                file_path.clone(),
+                false,
+                None,
+                None,
+                &self.config,
+            )
+            .await;
             let mut commit_hashes = vec![];
             for line_number in output.keys() {
                 let struct_detail = output.get(line_number).unwrap();
@@ -514,124 +578,242 @@ impl DB {
                     commit_hashes.extend(struct_detail.commit_hashes.clone());
                 }
             }
+            eprintln!("Commit hashes found: {:?}", commit_hashes);
+            // The next queries only catch up with the commits after this one.
+            self.replace_file_lines(&absolute_path(&file_path), output);
             // Now iterate through the commit hashes:
-            let mut counter_for_paths: HashMap<String, usize> = HashMap::new();
-            for commit_hash in commit_hashes.iter() {
-                // Compute contextual file paths using the commit hash.
-                // We use git show for this.
-                let relevant_file_paths = get_files_changed(commit_hash);
-                // Add each file path and increment count if it already existed.
-                for rel_path in relevant_file_paths.iter() {
-                    *counter_for_paths.entry(rel_path.clone()).or_insert(0) += 1;
-                }
-            }
-            println!("Commit hashes found: {:?}", commit_hashes);
-            // Write the last commit hash to the index metadata.
-            let last_commit_hash = commit_hashes.last().unwrap().to_string();
-            self.prepare_indexing_metadata(&file_path, &Some(last_commit_hash));
-            for (path, count) in counter_for_paths.iter() {
-                println!("{} - {} occurrences", path, count);
-            }
+            self.retain_relevant_commits(&mut commit_hashes);
+            let counter_for_paths = self.count_files_changed(&commit_hashes);
+            self.rank_related_files(counter_for_paths)
         } else {
-            // Generally - check first if the last indexed commit is the same as the current one.
-            // If it is, then we can just return the data from the DB.
-            let recent_commit = get_latest_commit(&file_path).unwrap();
-            // Read the mapping file first from self.mapping_file_path
-            let indexing_metadata = self.read_indexing_file();
-            let last_indexing_data =
-                indexing_metadata
-                    .get(&file_path.clone())
-                    .unwrap_or_else(|| {
-                        panic!("No indexing metadata found for the file: {}", file_path);
-                    });
-            let last_indexed_commit = last_indexing_data.last().cloned();
-            if last_indexed_commit.is_some() {
-                if last_indexed_commit.clone().unwrap().eq(&recent_commit) {
-                    // No need to index again, just return the data from the DB.
-                    // eprintln!("No new commits to index, returning existing data.");
-                } else {
-                    // Index the new commits and update the DB.
-                    // First get the new commits that have not been indexed yet.
-                    let commits_to_index = get_commits_after(last_indexed_commit.unwrap());
-                    // Index these commits first.
-                    perform_for_whole_file(file_path.clone(), false, Some(commits_to_index), None)
-                        .await;
-                }
-            }
+            // Bring the lines up to date with the commits since the file was indexed.
+            self.catch_up(&file_path).await;
 
             let (relevant_paths_with_counter, _uncovered_indices) =
                 self.exists_and_return(&start_number, &end_line_number);
 
-            for (path, count) in relevant_paths_with_counter.iter() {
-                println!("{} - {} occurrences", path, count);
+            self.rank_related_files(relevant_paths_with_counter)
+        }
+    }
+
+    // The related files of each hunk of the file (see hunks), ranked like query does. The file
+    // catches up once for all of them, the hunks are then ranked from the loaded lines. Files
+    // that aren't indexed have no hunks.
+    pub async fn query_hunks(
+        &mut self,
+        file_path: &str,
+    ) -> Vec<((usize, usize), Vec<RelatedFile>)> {
+        if self.current_data_v2.is_empty() {
+            return vec![];
+        }
+        // Bring the lines up to date with the commits since the file was indexed.
+        self.catch_up(file_path).await;
+        // Read the commits of every hunk from the index at once.
+        let commit_hashes: Vec<String> = self.current_data_v2.values().flatten().cloned().collect();
+        self.load_commit_data(&commit_hashes);
+        self.hunks()
+            .into_iter()
+            .map(|(start_number, end_number)| {
+                let (relevant_paths_with_counter, _uncovered_indices) =
+                    self.exists_and_return(&start_number, &end_number);
+                (
+                    (start_number, end_number),
+                    self.rank_related_files(relevant_paths_with_counter),
+                )
+            })
+            .collect()
+    }
+
+    // Ranks everyone who touched the given lines by number of lines, then number of commits.
+    fn summarize_authors(
+        lines_with_commits: Vec<Vec<String>>,
+        commit_data: &CommitsDBType,
+    ) -> Vec<AuthorSummary> {
+        struct Touches<'a> {
+            lines: usize,
+            commits: Vec<&'a String>,
+            first: &'a CommitInfo,
+            last: &'a CommitInfo,
+        }
+        // (author, email) -> everything they touched in the range.
+        let mut per_author: HashMap<(&String, &String), Touches> = HashMap::new();
+        for commit_hashes in lines_with_commits.iter() {
+            let mut authors_on_line: Vec<(&String, &String)> = vec![];
+            for commit_hash in commit_hashes.iter() {
+                let Some(commit_info) = commit_data.get(commit_hash) else {
+                    continue;
+                };
+                let key = (&commit_info.author, &commit_info.email);
+                let touches = per_author.entry(key).or_insert(Touches {
+                    lines: 0,
+                    commits: vec![],
+                    first: commit_info,
+                    last: commit_info,
+                });
+                if !touches.commits.contains(&commit_hash) {
+                    touches.commits.push(commit_hash);
+                }
+                if commit_info.timestamp < touches.first.timestamp {
+                    touches.first = commit_info;
+                }
+                if commit_info.timestamp > touches.last.timestamp {
+                    touches.last = commit_info;
+                }
+                if !authors_on_line.contains(&key) {
+                    authors_on_line.push(key);
+                }
+            }
+            for key in authors_on_line {
+                per_author.get_mut(&key).unwrap().lines += 1;
             }
         }
+        let mut summaries: Vec<AuthorSummary> = per_author
+            .into_iter()
+            .map(|((author, email), touches)| AuthorSummary {
+                author: author.clone(),
+                email: email.clone(),
+                lines: touches.lines,
+                commits: touches.commits.len(),
+                first_touch: touches.first.date.clone(),
+                last_touch: touches.last.date.clone(),
+            })
+            .collect();
+        summaries.sort_by(|a, b| {
+            b.lines
+                .cmp(&a.lines)
+                .then(b.commits.cmp(&a.commits))
+                .then(a.author.cmp(&b.author))
+                .then(a.email.cmp(&b.email))
+        });
+        summaries
     }
 
-    pub async fn query_descriptions(
+    pub async fn query_authors(
         &mut self,
         file_path: String,
         start_number: usize,
         end_number: usize,
-    ) {
-        let mut end_line_number = end_number;
-        if end_number == 0 {
-            // Means, cover the whole file.
-            // end_number should be the last line number of the file.
-            end_line_number = std::fs::read_to_string(&file_path)
-                .unwrap_or_else(|_| panic!("Unable to read the file: {}", file_path))
-                .lines()
-                .count();
-        }
+    ) -> Vec<AuthorSummary> {
+        let Some(end_line_number) = last_line(&file_path, end_number) else {
+            eprintln!("Unable to read the file: {}", file_path);
+            return vec![];
+        };
+        let mut lines_with_commits: Vec<Vec<String>> = vec![];
         if self.current_data_v2.is_empty() {
             // No data to query - means no indexing has happened yet.
             // Let's treat this as a binary and perform the operation ourselves:
-            let output =
-                algo_loc::perform_for_whole_file(file_path.clone(), false, None, None).await;
-            let mut commit_hashes = vec![];
-            for line_number in output.keys() {
-                // Check if struct_details' line number comes b/w start_number and end_number:
-                let struct_detail = output.get(line_number).unwrap();
+            let output = algo_loc::perform_for_whole_file(
+                file_path.clone(),
+                false,
+                None,
+                None,
+                &self.config,
+            )
+            .await;
+            for struct_detail in output.values() {
                 if struct_detail.line_number >= start_number
                     && struct_detail.line_number <= end_line_number
                 {
-                    commit_hashes.extend(struct_detail.commit_hashes.clone());
+                    lines_with_commits.push(struct_detail.commit_hashes.clone());
                 }
             }
-            // Get commit descriptions for these hashes
-            let out = get_commit_descriptions(commit_hashes);
-            println!("{:?}", out);
+            // The next queries only catch up with the commits after this one.
+            self.replace_file_lines(&absolute_path(&file_path), output);
         } else {
-            // Generally - check first if the last indexed commit is the same as the current one.
-            // If it is, then we can just return the data from the DB.
-            let recent_commit = get_latest_commit(&file_path).unwrap();
-            // Read the mapping file first from self.mapping_file_path
-            let indexing_metadata = self.read_indexing_file();
-            let last_indexing_data =
-                indexing_metadata
-                    .get(&file_path.clone())
-                    .unwrap_or_else(|| {
-                        panic!("No indexing metadata found for the file: {}", file_path);
-                    });
-            let last_indexed_commit = last_indexing_data.last().cloned();
-            if last_indexed_commit.is_some() {
-                if last_indexed_commit.clone().unwrap().eq(&recent_commit) {
-                    // No need to index again, just return the data from the DB.
-                    // eprintln!("No new commits to index, returning existing data.");
-                } else {
-                    // Index the new commits and update the DB.
-                    // First get the new commits that have not been indexed yet.
-                    let commits_to_index = get_commits_after(last_indexed_commit.unwrap());
-                    // Index these commits first.
-                    perform_for_whole_file(file_path.clone(), false, Some(commits_to_index), None)
-                        .await;
+            // Bring the lines up to date with the commits since the file was indexed.
+            self.catch_up(&file_path).await;
+            for line_number in start_number..=end_line_number {
+                if let Some(commit_hashes) = self.current_data_v2.get(&line_number) {
+                    lines_with_commits.push(commit_hashes.clone());
                 }
             }
-            let (commit_hashes, _uncovered_indices) =
-                self.raw_exists_and_return(&start_number, &end_line_number);
+        }
+        for commit_hashes in lines_with_commits.iter_mut() {
+            self.retain_relevant_commits(commit_hashes);
+        }
+        let all_commit_hashes: Vec<String> = lines_with_commits.iter().flatten().cloned().collect();
+        self.load_commit_data(&all_commit_hashes);
+        Self::summarize_authors(lines_with_commits, &self.commit_data)
+    }
 
-            let out = get_commit_descriptions(commit_hashes);
-            println!("{:?}", out);
+    pub async fn query_descriptions(
+        &mut self,
+        file_path: String,
+        start_number: usize,
+        end_number: usize,
+    ) -> Vec<CommitDescription> {
+        let Some(end_line_number) = last_line(&file_path, end_number) else {
+            eprintln!("Unable to read the file: {}", file_path);
+            return vec![];
+        };
+        if self.current_data_v2.is_empty() {
+            // No data to query - means no indexing has happened yet.
+            // Let's treat this as a binary and perform the operation ourselves:
+            let output = algo_loc::perform_for_whole_file(
+                file_path.clone(),
+                false,
+                None,
+                None,
+                &self.config,
+            )
+            .await;
+            // The next queries read the stored lines, and only catch up with the commits after
+            // this one.
+            self.replace_file_lines(&absolute_path(&file_path), output);
+        } else {
+            // Bring the lines up to date with the commits since the file was indexed.
+            self.catch_up(&file_path).await;
         }
+        let (mut commit_hashes, _uncovered_indices) =
+            self.raw_exists_and_return(&start_number, &end_line_number);
+
+        self.retain_relevant_commits(&mut commit_hashes);
+        self.load_commit_data(&commit_hashes);
+        describe_commits(self.git(), &commit_hashes, &self.commit_data)
+    }
+}
+
+// The last line to query, `end_number` or the last line of the file when it's 0. None when the
+// file can't be read.
+fn last_line(file_path: &str, end_number: usize) -> Option<usize> {
+    if end_number != 0 {
+        return Some(end_number);
     }
-}
\ No newline at end of file
+    Some(std::fs::read_to_string(file_path).ok()?.lines().count())
+}
+
+// Files are keyed by the absolute path, the ones that can't be resolved by the given one.
+fn absolute_path(file_path: &str) -> String {
+    match PathBuf::from(file_path).canonicalize() {
+        Ok(file_path) => file_path.to_string_lossy().to_string(),
+        Err(_) => file_path.to_string(),
+    }
+}
+
+// Moves the index of a workspace from where versions before the workspace keys put it. Only
+// the files are moved, folders in there are the indices of other workspaces.
+fn move_legacy_index(legacy_folder_path: &Path, db_folder_path: &Path) {
+    let has_index = legacy_folder_path.join(INDEX_FILE_NAME).exists()
+        || legacy_folder_path.join(META_FILE_NAME).exists()
+        || JsonStore::new(legacy_folder_path).exists();
+    if db_folder_path.exists() || !has_index {
+        return;
+    }
+    eprintln!(
+        "Moving the index from {} to {}",
+        legacy_folder_path.display(),
+        db_folder_path.display()
+    );
+    let moved = std::fs::create_dir_all(db_folder_path).and_then(|()| {
+        for entry in std::fs::read_dir(legacy_folder_path)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() && entry.file_name() != LOCK_FILE_NAME {
+                std::fs::rename(entry.path(), db_folder_path.join(entry.file_name()))?;
+            }
+        }
+        Ok(())
+    });
+    if let Err(e) = moved {
+        eprintln!("Failed to move the index: {}", e);
+    }
+}
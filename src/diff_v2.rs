@@ -1,5 +1,7 @@
 use std::{collections::HashMap, str};
 
+use crate::git_backend::{GitBackend, GitError, GitStream};
+
 #[derive(Debug, Clone)]
 pub struct LineDetail {
     pub content: String,
@@ -71,8 +73,8 @@ pub enum DiffCases {
     NoneFound,
 }
 
-pub fn read_content(
-    all_lines: &mut str::Lines,
+pub fn read_content<S: AsRef<str>>(
+    all_lines: &mut impl Iterator<Item = S>,
     deleted_line_count: u32,
     added_line_count: u32,
     map_to_fill: &mut HashMap<u32, Vec<LineDetail>>,
@@ -84,13 +86,14 @@ pub fn read_content(
     // entry.
     for _ in 0..deleted_line_count {
         if let Some(line) = all_lines.next() {
-            deleted_content.push(line.to_string());
+            deleted_content.push(line.as_ref().to_string());
         }
     }
     let mut added_content = vec![];
     for idx in 0..added_line_count {
         if let Some(line) = all_lines.next() {
             // Strip line by stripping off '+'
+            let line = line.as_ref();
             let line = line.strip_prefix('+').unwrap_or(line);
             added_content.push(line.to_string());
             if let Some(start_line_number) = start_line_number_if_to_add {
@@ -583,9 +586,9 @@ pub fn fetch_line_numbers(line: String) -> Option<(LineChange, LineChange)> {
     }
 }
 
-fn parse_diff(
+fn parse_diff<S: AsRef<str>>(
     commit_hash: String,
-    commit_diff: String,
+    mut all_lines: impl Iterator<Item = S>,
     map: &mut HashMap<u32, Vec<LineDetail>>,
     file_name: &str,
 ) -> Result<(), String> {
@@ -599,14 +602,13 @@ fn parse_diff(
     // -50,3 +48,0 -> a few lines were deleted.
     // -159 +96 -> a single line was replaced with another single line.
     // -169,0 +104,3 -> new lines were added.
-    let mut all_lines = commit_diff.lines();
     while true {
         let line = all_lines.next();
         if line.is_none() {
             break;
         }
         let line = line.unwrap();
-        let line = line.trim();
+        let line = line.as_ref().trim();
         let mut line_before: Option<LineChange> = None;
         let mut line_after: Option<LineChange> = None;
         let mut category: Option<DiffCases> = None;
@@ -620,7 +622,8 @@ fn parse_diff(
         //     }
         // }
         if line.starts_with("@@@") {
-            // merge commit - skip!
+            // Combined diff of a merge. Merges are always diffed against a single parent (see
+            // extract_commit_hashes_with_rename), so this is not expected.
             break;
         }
         if line.starts_with("@@") {
@@ -780,38 +783,273 @@ fn parse_diff(
 }
 
 pub fn extract_commit_hashes(
-    parent_commit_hash: &String,
+    git: &dyn GitBackend,
+    parent_commit_hash: &str,
+    commit_hash: &str,
+    map: &mut HashMap<u32, Vec<LineDetail>>,
+    file_name: &str,
+) {
+    extract_commit_hashes_with_rename(git, parent_commit_hash, commit_hash, map, file_name, None);
+}
+
+// Same as extract_commit_hashes, where the file was called `previous_file_name` in the
+// parent commit. The diff then maps the lines of the old file onto the renamed one.
+pub fn extract_commit_hashes_with_rename(
+    git: &dyn GitBackend,
+    parent_commit_hash: &str,
     commit_hash: &str,
     map: &mut HashMap<u32, Vec<LineDetail>>,
     file_name: &str,
+    previous_file_name: Option<&str>,
 ) {
-    let mut output: std::process::Output;
-    if parent_commit_hash.is_empty() {
-        // Call git show --unified=0 for the commit_hash and extract line->[commit_hash...] list.
-        output = std::process::Command::new("git")
-            .arg("show")
-            .arg("--unified=0")
-            .arg(commit_hash)
-            .arg("--")
-            .arg(file_name)
-            .output()
-            .expect("Failed to execute command");
+    let patch = if parent_commit_hash.is_empty() {
+        // The commit's own patch, merges against their first parent instead of a combined diff.
+        git.show(commit_hash, file_name)
     } else {
-        // perform git diff b/w the parnt commit and the commit_hash.
-        output = std::process::Command::new("git")
-            .arg("diff")
-            .arg(format!("{}..{}", parent_commit_hash, commit_hash))
-            .arg("--unified=0")
-            .arg("--")
-            .arg(file_name)
-            .output()
-            .expect("Failed to execute command");
-    }
-    if output.status.success() {
-        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        // The patch between the parent commit and the commit_hash.
+        git.diff(parent_commit_hash, commit_hash, file_name, previous_file_name)
+    };
+    match patch {
         // Pass the commit diff and reorder the map accordingly.
-        let _ = parse_diff(commit_hash.to_string(), stdout, map, file_name);
-    } else {
-        eprintln!("Error: {}", String::from_utf8_lossy(&output.stderr));
+        Ok(patch) => {
+            let _ = parse_diff(commit_hash.to_string(), patch.lines(), map, file_name);
+        }
+        Err(e) => eprintln!("Error: {}", e),
+    }
+}
+
+// The patches of a `git log -p --unified=0 --src-prefix=a/ --dst-prefix=b/` run, replayed commit by
+// commit while git prints them. The log has to start every commit with a `%x00%H` line,
+// optionally followed by the parents (`%x00%H %P`). Whether git printed the whole patch is only
+// known once it's read, see finish.
+pub struct PatchStream {
+    stream: GitStream,
+    // The line read ahead, when it belongs to the next file or commit.
+    peeked: Option<String>,
+}
+
+// A file in a patch. The old path is None for added files, the new one for deleted files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchFile {
+    pub old_path: Option<String>,
+    pub new_path: Option<String>,
+}
+
+// Where a commit's patch, or the patch of a file in it, ends.
+fn is_patch_boundary(line: &str) -> bool {
+    line.starts_with('\0') || line.starts_with("diff --git ")
+}
+
+impl PatchStream {
+    pub fn new(git: &dyn GitBackend, args: &[&str]) -> Option<PatchStream> {
+        git.log_stream(args)
+            .inspect_err(|e| eprintln!("Failed to read the history: {}", e))
+            .ok()
+            .map(PatchStream::from_stream)
     }
-}
\ No newline at end of file
+
+    // The patch from `parent` to `commit`, without any commit line.
+    pub fn diff(git: &dyn GitBackend, parent: &str, commit: &str) -> Option<PatchStream> {
+        git.diff_stream(parent, commit)
+            .inspect_err(|e| eprintln!("Error: {}", e))
+            .ok()
+            .map(PatchStream::from_stream)
+    }
+
+    fn from_stream(stream: GitStream) -> PatchStream {
+        PatchStream {
+            stream,
+            peeked: None,
+        }
+    }
+
+    // Reads what's left of the patch, fails when git didn't print all of it.
+    pub fn finish(self) -> Result<(), GitError> {
+        self.stream.finish()
+    }
+
+    // The next line if it's accepted, it's read again next time otherwise.
+    fn next_line_if(&mut self, accept: impl FnOnce(&String) -> bool) -> Option<String> {
+        let line = match self.peeked.take() {
+            Some(line) => line,
+            None => self.stream.next()?,
+        };
+        if accept(&line) {
+            return Some(line);
+        }
+        self.peeked = Some(line);
+        None
+    }
+
+    // Skips to the next commit of the log, with its parents when the log has them.
+    pub fn next_commit(&mut self) -> Option<(String, Vec<String>)> {
+        let line = std::iter::from_fn(|| self.peeked.take().or_else(|| self.stream.next()))
+            .find(|line| line.starts_with('\0'))?;
+        let mut hashes = line[1..].split_whitespace().map(|hash| hash.to_string());
+        Some((hashes.next().unwrap_or_default(), hashes.collect()))
+    }
+
+    // Skips to the next file in the patch of the current commit, None after the last one.
+    pub fn next_file(&mut self) -> Option<PatchFile> {
+        let line = loop {
+            let line = self.next_line_if(|line| !line.starts_with('\0'))?;
+            if line.starts_with("diff --git ") {
+                break line;
+            }
+        };
+        // Names with spaces can only be told apart from `diff --git a/<path> b/<path>` when
+        // they're the same, renames, additions and deletions say it again below.
+        let path = line.strip_prefix("diff --git a/").and_then(|paths| {
+            let length = paths.len().checked_sub(3)? / 2;
+            let (old_path, new_path) = (paths.get(..length)?, paths.get(length..)?);
+            (new_path.strip_prefix(" b/")? == old_path).then(|| old_path.to_string())
+        });
+        let mut file = PatchFile {
+            old_path: path.clone(),
+            new_path: path,
+        };
+        while let Some(line) =
+            self.next_line_if(|line| !line.starts_with("@@") && !is_patch_boundary(line))
+        {
+            if let Some(path) = line.strip_prefix("--- ") {
+                file.old_path = patch_path(path, "a/");
+            } else if let Some(path) = line.strip_prefix("+++ ") {
+                file.new_path = patch_path(path, "b/");
+            } else if let Some(path) = line.strip_prefix("rename from ") {
+                file.old_path = Some(path.to_string());
+            } else if let Some(path) = line.strip_prefix("rename to ") {
+                file.new_path = Some(path.to_string());
+            } else if line.starts_with("new file mode ") {
+                file.old_path = None;
+            } else if line.starts_with("deleted file mode ") {
+                file.new_path = None;
+            }
+        }
+        Some(file)
+    }
+
+    // Replays the hunks of the current file onto the map, see extract_commit_hashes.
+    pub fn extract_file(&mut self, commit_hash: &str, map: &mut HashMap<u32, Vec<LineDetail>>) {
+        let hunks = std::iter::from_fn(|| self.next_line_if(|line| !is_patch_boundary(line)));
+        let _ = parse_diff(commit_hash.to_string(), hunks, map, "");
+    }
+
+    // The hunks of the current file, as (before, after) line ranges.
+    pub fn file_hunks(&mut self) -> Vec<(LineChange, LineChange)> {
+        std::iter::from_fn(|| self.next_line_if(|line| !is_patch_boundary(line)))
+            .filter_map(|line| hunk_line_numbers(&line))
+            .collect()
+    }
+
+    // Same as extract_commit_hashes, with the patch of `commit_hash` in the log. `file_name` is
+    // the path in the commit, relative to the top of the repository. Commits have to come in the
+    // order of the log, returns false when it has no patch of the file for this one.
+    pub fn extract_commit_hashes(
+        &mut self,
+        commit_hash: &str,
+        map: &mut HashMap<u32, Vec<LineDetail>>,
+        file_name: &str,
+    ) -> bool {
+        if !std::iter::from_fn(|| self.next_commit()).any(|(hash, _)| hash == commit_hash) {
+            return false;
+        }
+        let mut extracted = false;
+        while let Some(file) = self.next_file() {
+            if file.new_path.or(file.old_path).as_deref() == Some(file_name) {
+                self.extract_file(commit_hash, map);
+                extracted = true;
+            }
+        }
+        extracted
+    }
+}
+
+// The path of a `---`/`+++` line of a patch, git ends the ones with spaces with a tab. Quoted
+// paths don't match, their commits are diffed on their own instead.
+fn patch_path(path: &str, prefix: &str) -> Option<String> {
+    let path = path.strip_suffix('\t').unwrap_or(path);
+    path.strip_prefix(prefix).map(|path| path.to_string())
+}
+
+// The line ranges of a `@@ -<before> +<after> @@` line.
+fn hunk_line_numbers(line: &str) -> Option<(LineChange, LineChange)> {
+    let line = line.strip_prefix("@@ ")?;
+    let line = line.split_once("@@")?.0.trim();
+    fetch_line_numbers(line.to_string())
+}
+
+// Hunks of `git diff parent..commit` for the file, as (before, after) line ranges.
+fn diff_hunks(
+    git: &dyn GitBackend,
+    parent_commit_hash: &str,
+    commit_hash: &str,
+    file_name: &str,
+    previous_file_name: Option<&str>,
+) -> Option<Vec<(LineChange, LineChange)>> {
+    let stdout = git
+        .diff(parent_commit_hash, commit_hash, file_name, previous_file_name)
+        .inspect_err(|e| eprintln!("Error: {}", e))
+        .ok()?;
+    Some(stdout.lines().filter_map(hunk_line_numbers).collect())
+}
+
+// The line `line_number` of the new file had in the old one, None if the diff changed it.
+fn line_before_diff(hunks: &[(LineChange, LineChange)], line_number: u32) -> Option<u32> {
+    let mut offset: i64 = 0;
+    for (before, after) in hunks {
+        if after.change_count == 0 {
+            // Only deletions, these happened after line `start_line_number`.
+            if after.start_line_number >= line_number {
+                break;
+            }
+        } else if line_number < after.start_line_number {
+            break;
+        } else if line_number < after.start_line_number + after.change_count {
+            return None;
+        }
+        offset += before.change_count as i64 - after.change_count as i64;
+    }
+    u32::try_from(line_number as i64 + offset).ok()
+}
+
+// Once a merge commit was diffed against its first parent, all the lines it brought in from
+// another parent are attributed to the merge. Give the lines that are unchanged compared to
+// `parent_commit_hash` the history they have there instead, so the merge only keeps the lines
+// it changed compared to every parent.
+pub fn carry_over_parent_history(
+    git: &dyn GitBackend,
+    parent_commit_hash: &str,
+    commit_hash: &str,
+    map: &mut HashMap<u32, Vec<LineDetail>>,
+    parent_map: &HashMap<u32, Vec<LineDetail>>,
+    file_name: &str,
+    previous_file_name: Option<&str>,
+) {
+    let Some(hunks) = diff_hunks(git, parent_commit_hash, commit_hash, file_name, previous_file_name)
+    else {
+        return;
+    };
+    carry_over_parent_hunks(&hunks, commit_hash, map, parent_map);
+}
+
+// Same as carry_over_parent_history, with the hunks of the diff from the parent.
+pub fn carry_over_parent_hunks(
+    hunks: &[(LineChange, LineChange)],
+    commit_hash: &str,
+    map: &mut HashMap<u32, Vec<LineDetail>>,
+    parent_map: &HashMap<u32, Vec<LineDetail>>,
+) {
+    for (line_number, line_details) in map.iter_mut() {
+        let last_commit_hash = line_details
+            .first()
+            .and_then(|line_detail| line_detail.commit_hashes.last());
+        if last_commit_hash.map(|hash| hash.as_str()) != Some(commit_hash) {
+            continue;
+        }
+        if let Some(parent_line_number) = line_before_diff(hunks, *line_number)
+            && let Some(parent_line_details) = parent_map.get(&parent_line_number)
+        {
+            *line_details = parent_line_details.clone();
+        }
+    }
+}
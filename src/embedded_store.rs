@@ -0,0 +1,612 @@
+// The index of a workspace, kept in a single embedded database (redb) in its DB folder.
+//
+// Tables, all values are JSON:
+// - files: file path -> line number -> commit hashes (full ones)
+// - indexed_commits: file path -> the commit the file was indexed at, in a list (older
+//   versions kept every one, the last one is the latest)
+// - commits: commit hash -> author, date, message and files changed
+// - failed_files: file path -> why it couldn't be indexed, until it's stored
+// - path_resolver: a single entry, the commit it was built at -> renames and files at it
+//
+// Every update is a transaction, and a lookup only reads the entries it asks for.
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::time::{Duration, Instant};
+
+use redb::{Database, ReadableTable, TableDefinition, TableHandle, WriteTransaction};
+use serde::de::DeserializeOwned;
+
+use crate::contextgpt_structs::{CommitInfo, GcReport};
+use crate::fs_utils::{LOCK_TIMEOUT, folder_size, is_temp_file, lock_folder};
+use crate::git_backend::HashExpander;
+use crate::git_command_algo::PathResolver;
+use crate::index_store::{FileLines, IndexStore, StoreError};
+use crate::json_store::JsonStore;
+use crate::migrations;
+
+pub const INDEX_FILE_NAME: &str = "index.redb";
+
+const FILES: TableDefinition<&str, &[u8]> = TableDefinition::new("files");
+const INDEXED_COMMITS: TableDefinition<&str, &[u8]> = TableDefinition::new("indexed_commits");
+const COMMITS: TableDefinition<&str, &[u8]> = TableDefinition::new("commits");
+const FAILED_FILES: TableDefinition<&str, &str> = TableDefinition::new("failed_files");
+const PATH_RESOLVER: TableDefinition<&str, &[u8]> = TableDefinition::new("path_resolver");
+
+// How long a process keeps the database open, other processes wait at most this long plus
+// the call in progress.
+const HOLD_TIME: Duration = Duration::from_secs(1);
+// How long a process waits before it opens the database again after closing it, so that the
+// ones waiting for the lock get it first.
+const YIELD_TIME: Duration = Duration::from_millis(50);
+
+#[derive(Default, Clone)]
+pub struct EmbeddedStore {
+    folder_path: PathBuf,
+    // Shared with every other store of the folder in this process.
+    shared: Arc<Mutex<SharedIndex>>,
+}
+
+// The database of a folder, as the stores of this process share it.
+#[derive(Default)]
+struct SharedIndex {
+    // Opened on first use, closed HOLD_TIME later.
+    open: Option<Arc<OpenIndex>>,
+    closed_at: Option<Instant>,
+}
+
+// The database, while it's open.
+struct OpenIndex {
+    database: Database,
+    // Only one process has the database open at a time (redb locks the file too), the others
+    // wait until it's closed. Released on drop.
+    _lock: File,
+}
+
+// The databases of this process by folder. redb opens a file only once, so every store of
+// the same folder shares it.
+static SHARED_INDEXES: Mutex<BTreeMap<PathBuf, Weak<Mutex<SharedIndex>>>> =
+    Mutex::new(BTreeMap::new());
+
+impl SharedIndex {
+    // The database closes once the calls using it are done, the next call opens it again.
+    fn close(&mut self) {
+        if self.open.take().is_some() {
+            self.closed_at = Some(Instant::now());
+        }
+    }
+}
+
+impl EmbeddedStore {
+    pub fn new(folder_path: &Path) -> EmbeddedStore {
+        let mut shared_indexes = SHARED_INDEXES
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let shared = match shared_indexes.get(folder_path).and_then(Weak::upgrade) {
+            Some(shared) => shared,
+            None => {
+                let shared = Arc::default();
+                shared_indexes.insert(folder_path.to_path_buf(), Arc::downgrade(&shared));
+                shared
+            }
+        };
+        EmbeddedStore {
+            folder_path: folder_path.to_path_buf(),
+            shared,
+        }
+    }
+
+    // The open database. Opening it upgrades indexes of older versions, and creates it if
+    // needed.
+    fn index(&self) -> Result<Arc<OpenIndex>, StoreError> {
+        let mut shared = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(index) = shared.open.as_ref() {
+            return Ok(index.clone());
+        }
+        if let Some(closed_at) = shared.closed_at {
+            std::thread::sleep(YIELD_TIME.saturating_sub(closed_at.elapsed()));
+        }
+        let index = Arc::new(open_index(&self.folder_path)?);
+        shared.open = Some(index.clone());
+        // Gives the other processes a turn, unless it's closed by then.
+        let (shared, opened) = (Arc::downgrade(&self.shared), Arc::downgrade(&index));
+        std::thread::spawn(move || {
+            std::thread::sleep(HOLD_TIME);
+            let Some(shared) = shared.upgrade() else {
+                return;
+            };
+            let mut shared = shared.lock().unwrap_or_else(PoisonError::into_inner);
+            if shared
+                .open
+                .as_ref()
+                .is_some_and(|open| std::ptr::eq(Arc::as_ptr(open), opened.as_ptr()))
+            {
+                shared.close();
+            }
+        });
+        Ok(index)
+    }
+
+    // Closes the database, the next call opens it again. Long-lived processes close it
+    // between requests, so that other processes don't wait on them.
+    pub fn close(&self) {
+        self.shared
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .close();
+    }
+
+    // Brings the index up to date with this version, see migrations.
+    pub fn upgrade(&self) -> Result<(), StoreError> {
+        self.index().map(|_| ())
+    }
+
+    // Number of tables and of pages allocated in the database file, what the shards used to
+    // be to the JSON layout.
+    pub fn stats(&self) -> Result<(usize, u64), StoreError> {
+        let index = self.index()?;
+        // Only write transactions can tell how many pages are allocated, nothing is written.
+        let txn = index.database.begin_write()?;
+        let tables = txn.list_tables()?.count();
+        let pages = txn.stats()?.allocated_pages();
+        txn.abort()?;
+        Ok((tables, pages))
+    }
+
+    // Drops the files that is_deleted says are gone, with the commits only they pointed to
+    // and stray entries, then compacts the database and removes leftover files.
+    pub fn gc(&self, is_deleted: impl Fn(&str) -> bool) -> Result<GcReport, StoreError> {
+        let mut report = GcReport {
+            size_before: folder_size(&self.folder_path),
+            ..Default::default()
+        };
+        let mut index = self.index()?;
+        let txn = index.database.begin_write()?;
+        {
+            let mut files = txn.open_table(FILES)?;
+            let mut kept_files: HashSet<String> = HashSet::new();
+            let mut used_commits: HashSet<String> = HashSet::new();
+            let mut deleted_files: Vec<String> = vec![];
+            for entry in files.iter()? {
+                let (file_path, lines) = entry?;
+                let file_path = file_path.value().to_string();
+                if is_deleted(&file_path) {
+                    deleted_files.push(file_path);
+                    continue;
+                }
+                let lines: FileLines = decode(&file_path, lines.value()).unwrap_or_default();
+                used_commits.extend(lines.into_values().flatten());
+                kept_files.insert(file_path);
+            }
+            for file_path in deleted_files.iter() {
+                files.remove(file_path.as_str())?;
+            }
+            report.removed_files = deleted_files.len();
+
+            let mut indexed_commits = txn.open_table(INDEXED_COMMITS)?;
+            let mut stray_files: Vec<String> = vec![];
+            for entry in indexed_commits.iter()? {
+                let (file_path, _) = entry?;
+                if !kept_files.contains(file_path.value()) {
+                    stray_files.push(file_path.value().to_string());
+                }
+            }
+            for file_path in stray_files.iter() {
+                indexed_commits.remove(file_path.as_str())?;
+            }
+
+            let mut failed_files = txn.open_table(FAILED_FILES)?;
+            let mut deleted_failures: Vec<String> = vec![];
+            for entry in failed_files.iter()? {
+                let (file_path, _) = entry?;
+                if is_deleted(file_path.value()) {
+                    deleted_failures.push(file_path.value().to_string());
+                }
+            }
+            for file_path in deleted_failures.iter() {
+                failed_files.remove(file_path.as_str())?;
+            }
+
+            let mut commits = txn.open_table(COMMITS)?;
+            let mut unused_commits: Vec<String> = vec![];
+            for entry in commits.iter()? {
+                let (commit_hash, _) = entry?;
+                if !used_commits.contains(commit_hash.value()) {
+                    unused_commits.push(commit_hash.value().to_string());
+                }
+            }
+            for commit_hash in unused_commits.iter() {
+                commits.remove(commit_hash.as_str())?;
+            }
+            report.removed_commits = unused_commits.len();
+        }
+        txn.commit()?;
+        // Compacting needs the only handle on the database.
+        let mut shared = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+        shared.close();
+        match Arc::get_mut(&mut index) {
+            Some(index) => index.database.compact().map(|_| ())?,
+            None => eprintln!("Skipping compaction, the index is in use elsewhere in this process"),
+        }
+        drop(shared);
+
+        // Still under the lock, so that no one is halfway through writing a temporary file.
+        let mut leftovers = JsonStore::new(&self.folder_path).layout_paths();
+        for entry in std::fs::read_dir(&self.folder_path)?.flatten() {
+            if is_temp_file(&entry.file_name().to_string_lossy()) {
+                leftovers.push(entry.path());
+            }
+        }
+        for leftover in leftovers {
+            match std::fs::remove_file(&leftover) {
+                Ok(()) => report.removed_leftovers += 1,
+                Err(e) => eprintln!("Failed to delete {}: {}", leftover.display(), e),
+            }
+        }
+        report.size_after = folder_size(&self.folder_path);
+        Ok(report)
+    }
+}
+
+// Takes the folder lock, upgrades the index and opens it. The tables are created along with
+// the database, and by versions that predate one of them.
+fn open_index(folder_path: &Path) -> Result<OpenIndex, StoreError> {
+    let lock = lock_folder(folder_path, LOCK_TIMEOUT)?;
+    migrations::upgrade(folder_path)?;
+    let database = Database::create(folder_path.join(INDEX_FILE_NAME))?;
+    let existing_tables: HashSet<String> = database
+        .begin_read()?
+        .list_tables()?
+        .map(|table| table.name().to_string())
+        .collect();
+    let table_names = [
+        FILES.name(),
+        INDEXED_COMMITS.name(),
+        COMMITS.name(),
+        FAILED_FILES.name(),
+        PATH_RESOLVER.name(),
+    ];
+    if !table_names
+        .iter()
+        .all(|table_name| existing_tables.contains(*table_name))
+    {
+        // So that readers never have to deal with missing ones.
+        let txn = database.begin_write()?;
+        txn.open_table(FILES)?;
+        txn.open_table(INDEXED_COMMITS)?;
+        txn.open_table(COMMITS)?;
+        txn.open_table(FAILED_FILES)?;
+        txn.open_table(PATH_RESOLVER)?;
+        txn.commit()?;
+    }
+    Ok(OpenIndex {
+        database,
+        _lock: lock,
+    })
+}
+
+// Moves an index in the JSON layout into a new database, then deletes the JSON files. Call
+// with the lock held.
+pub fn import_json_layout(folder_path: &Path) -> Result<(), StoreError> {
+    let json_store = JsonStore::new(folder_path);
+    let json_index = json_store.read_all();
+    let database = Database::create(folder_path.join(INDEX_FILE_NAME))?;
+    let txn = database.begin_write()?;
+    {
+        let mut table = txn.open_table(FILES)?;
+        for (file_path, lines) in json_index.files.iter() {
+            table.insert(file_path.as_str(), encode(lines).as_slice())?;
+        }
+        let mut table = txn.open_table(INDEXED_COMMITS)?;
+        for (file_path, indexed_commits) in json_index.indexed_commits.iter() {
+            table.insert(file_path.as_str(), encode(indexed_commits).as_slice())?;
+        }
+        let mut table = txn.open_table(COMMITS)?;
+        for (commit_hash, commit_info) in json_index.commits.iter() {
+            table.insert(commit_hash.as_str(), encode(commit_info).as_slice())?;
+        }
+    }
+    txn.commit()?;
+    for json_path in json_index.paths {
+        if let Err(e) = std::fs::remove_file(&json_path) {
+            eprintln!("Failed to delete {}: {}", json_path.display(), e);
+        }
+    }
+    Ok(())
+}
+
+// Drops every entry of the commit table, they're fetched from git again when needed. Call
+// with the lock held.
+pub fn clear_commits(folder_path: &Path) -> Result<(), StoreError> {
+    let database = Database::create(folder_path.join(INDEX_FILE_NAME))?;
+    let txn = database.begin_write()?;
+    txn.delete_table(COMMITS)?;
+    txn.open_table(COMMITS)?;
+    txn.commit()?;
+    Ok(())
+}
+
+// Replaces the abbreviated commit hashes of older versions with full ones. Files with a hash
+// that no longer resolves (gone, or shared by several commits by now) are dropped so that
+// they get indexed again, and so are the commits no file points to. Call with the lock held.
+pub fn expand_commit_hashes(folder_path: &Path) -> Result<(), StoreError> {
+    let database = Database::create(folder_path.join(INDEX_FILE_NAME))?;
+    let txn = database.begin_write()?;
+    {
+        let mut expander = HashExpander::default();
+        let mut full_hashes: HashMap<String, String> = HashMap::new();
+        let mut files = txn.open_table(FILES)?;
+        let mut indexed_commits = txn.open_table(INDEXED_COMMITS)?;
+        let mut file_paths: BTreeSet<String> = BTreeSet::new();
+        for entry in files.iter()? {
+            file_paths.insert(entry?.0.value().to_string());
+        }
+        for entry in indexed_commits.iter()? {
+            file_paths.insert(entry?.0.value().to_string());
+        }
+        for file_path in file_paths.iter() {
+            let lines: Option<FileLines> = files
+                .get(file_path.as_str())?
+                .and_then(|lines| decode(file_path, lines.value()));
+            let file_commits: Option<Vec<String>> = indexed_commits
+                .get(file_path.as_str())?
+                .and_then(|commits| decode(file_path, commits.value()));
+            let mut expand = |commit_hash: &String| {
+                let full_hash = expander.expand(file_path, commit_hash)?;
+                full_hashes.insert(commit_hash.clone(), full_hash.clone());
+                Some(full_hash)
+            };
+            // None inside when a hash didn't resolve.
+            let expanded_lines: Option<Option<FileLines>> = lines.map(|lines| {
+                lines
+                    .into_iter()
+                    .map(|(line, commit_hashes)| {
+                        let commit_hashes: Option<Vec<String>> =
+                            commit_hashes.iter().map(&mut expand).collect();
+                        Some((line, commit_hashes?))
+                    })
+                    .collect()
+            });
+            let expanded_commits: Option<Option<Vec<String>>> =
+                file_commits.map(|file_commits| file_commits.iter().map(&mut expand).collect());
+            if matches!(expanded_lines, Some(None)) || matches!(expanded_commits, Some(None)) {
+                eprintln!(
+                    "Dropping {}, some of its commits are gone or ambiguous",
+                    file_path
+                );
+                files.remove(file_path.as_str())?;
+                indexed_commits.remove(file_path.as_str())?;
+                continue;
+            }
+            if let Some(Some(lines)) = expanded_lines {
+                files.insert(file_path.as_str(), encode(&lines).as_slice())?;
+            }
+            if let Some(Some(file_commits)) = expanded_commits {
+                indexed_commits.insert(file_path.as_str(), encode(&file_commits).as_slice())?;
+            }
+        }
+
+        let mut commits = txn.open_table(COMMITS)?;
+        let mut expanded_commits: Vec<(String, Vec<u8>)> = vec![];
+        for entry in commits.iter()? {
+            let (commit_hash, commit_info) = entry?;
+            if let Some(full_hash) = full_hashes.get(commit_hash.value()) {
+                expanded_commits.push((full_hash.clone(), commit_info.value().to_vec()));
+            }
+        }
+        commits.retain(|_, _| false)?;
+        for (commit_hash, commit_info) in expanded_commits.iter() {
+            commits.insert(commit_hash.as_str(), commit_info.as_slice())?;
+        }
+    }
+    txn.commit()?;
+    Ok(())
+}
+
+impl IndexStore for EmbeddedStore {
+    fn get_file_lines(&self, file_path: &str) -> Result<Option<FileLines>, StoreError> {
+        let index = self.index()?;
+        let txn = index.database.begin_read()?;
+        let table = txn.open_table(FILES)?;
+        let lines = table.get(file_path)?;
+        Ok(lines.and_then(|lines| decode(file_path, lines.value())))
+    }
+
+    fn has_file(&self, file_path: &str) -> Result<bool, StoreError> {
+        let index = self.index()?;
+        let txn = index.database.begin_read()?;
+        let table = txn.open_table(FILES)?;
+        Ok(table.get(file_path)?.is_some())
+    }
+
+    fn put_file_lines(
+        &mut self,
+        file_path: &str,
+        lines: &FileLines,
+        indexed_commit: Option<&str>,
+    ) -> Result<(), StoreError> {
+        let index = self.index()?;
+        let txn = index.database.begin_write()?;
+        txn.open_table(FILES)?
+            .insert(file_path, encode(lines).as_slice())?;
+        txn.open_table(FAILED_FILES)?.remove(file_path)?;
+        if let Some(indexed_commit) = indexed_commit {
+            put_indexed_commit(&txn, file_path, indexed_commit)?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    fn put_files(
+        &mut self,
+        files: &[(String, FileLines, Option<String>)],
+    ) -> Result<(), StoreError> {
+        let index = self.index()?;
+        let txn = index.database.begin_write()?;
+        {
+            let mut table = txn.open_table(FILES)?;
+            let mut failed_files = txn.open_table(FAILED_FILES)?;
+            for (file_path, lines, _) in files {
+                table.insert(file_path.as_str(), encode(lines).as_slice())?;
+                failed_files.remove(file_path.as_str())?;
+            }
+        }
+        for (file_path, _, indexed_commit) in files {
+            if let Some(indexed_commit) = indexed_commit {
+                put_indexed_commit(&txn, file_path, indexed_commit)?;
+            }
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    fn last_indexed_commit(&self, file_path: &str) -> Result<Option<String>, StoreError> {
+        let index = self.index()?;
+        let txn = index.database.begin_read()?;
+        let table = txn.open_table(INDEXED_COMMITS)?;
+        let indexed_commits: Option<Vec<String>> = table
+            .get(file_path)?
+            .and_then(|commits| decode(file_path, commits.value()));
+        Ok(indexed_commits.and_then(|commits| commits.last().cloned()))
+    }
+
+    fn set_indexed_commit(&mut self, file_path: &str, commit_hash: &str) -> Result<(), StoreError> {
+        let index = self.index()?;
+        let txn = index.database.begin_write()?;
+        put_indexed_commit(&txn, file_path, commit_hash)?;
+        txn.commit()?;
+        Ok(())
+    }
+
+    fn list_files(&self) -> Result<Vec<String>, StoreError> {
+        let index = self.index()?;
+        let txn = index.database.begin_read()?;
+        let table = txn.open_table(FILES)?;
+        let mut file_paths = vec![];
+        for entry in table.iter()? {
+            let (file_path, _) = entry?;
+            file_paths.push(file_path.value().to_string());
+        }
+        Ok(file_paths)
+    }
+
+    fn remove_file(&mut self, file_path: &str) -> Result<(), StoreError> {
+        let index = self.index()?;
+        let txn = index.database.begin_write()?;
+        txn.open_table(FILES)?.remove(file_path)?;
+        txn.open_table(INDEXED_COMMITS)?.remove(file_path)?;
+        txn.open_table(FAILED_FILES)?.remove(file_path)?;
+        txn.commit()?;
+        Ok(())
+    }
+
+    fn mark_failed(&mut self, file_path: &str, reason: &str) -> Result<(), StoreError> {
+        let index = self.index()?;
+        let txn = index.database.begin_write()?;
+        txn.open_table(FAILED_FILES)?.insert(file_path, reason)?;
+        txn.commit()?;
+        Ok(())
+    }
+
+    fn failed_files(&self) -> Result<BTreeMap<String, String>, StoreError> {
+        let index = self.index()?;
+        let txn = index.database.begin_read()?;
+        let table = txn.open_table(FAILED_FILES)?;
+        let mut failed_files = BTreeMap::new();
+        for entry in table.iter()? {
+            let (file_path, reason) = entry?;
+            failed_files.insert(file_path.value().to_string(), reason.value().to_string());
+        }
+        Ok(failed_files)
+    }
+
+    fn get_commits(
+        &self,
+        commit_hashes: &[String],
+    ) -> Result<HashMap<String, CommitInfo>, StoreError> {
+        let index = self.index()?;
+        let txn = index.database.begin_read()?;
+        let table = txn.open_table(COMMITS)?;
+        let mut commits = HashMap::new();
+        for commit_hash in commit_hashes {
+            if commits.contains_key(commit_hash) {
+                continue;
+            }
+            if let Some(commit_info) = table.get(commit_hash.as_str())?
+                && let Some(commit_info) = decode(commit_hash, commit_info.value())
+            {
+                commits.insert(commit_hash.clone(), commit_info);
+            }
+        }
+        Ok(commits)
+    }
+
+    fn put_commits(&mut self, commits: &HashMap<String, CommitInfo>) -> Result<(), StoreError> {
+        if commits.is_empty() {
+            return Ok(());
+        }
+        let index = self.index()?;
+        let txn = index.database.begin_write()?;
+        {
+            let mut table = txn.open_table(COMMITS)?;
+            for (commit_hash, commit_info) in commits {
+                table.insert(commit_hash.as_str(), encode(commit_info).as_slice())?;
+            }
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    fn get_path_resolver(&self) -> Result<Option<(String, PathResolver)>, StoreError> {
+        let index = self.index()?;
+        let txn = index.database.begin_read()?;
+        let table = txn.open_table(PATH_RESOLVER)?;
+        let Some(entry) = table.iter()?.next() else {
+            return Ok(None);
+        };
+        let (commit_hash, path_resolver) = entry?;
+        let commit_hash = commit_hash.value().to_string();
+        Ok(decode(&commit_hash, path_resolver.value())
+            .map(|path_resolver| (commit_hash, path_resolver)))
+    }
+
+    fn put_path_resolver(
+        &mut self,
+        commit_hash: &str,
+        path_resolver: &PathResolver,
+    ) -> Result<(), StoreError> {
+        let index = self.index()?;
+        let txn = index.database.begin_write()?;
+        {
+            let mut table = txn.open_table(PATH_RESOLVER)?;
+            table.retain(|_, _| false)?;
+            table.insert(commit_hash, encode(path_resolver).as_slice())?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+}
+
+// Only the latest commit is kept, the history of a file would grow with every re-index.
+fn put_indexed_commit(
+    txn: &WriteTransaction,
+    file_path: &str,
+    commit_hash: &str,
+) -> Result<(), StoreError> {
+    let indexed_commits = vec![commit_hash.to_string()];
+    txn.open_table(INDEXED_COMMITS)?
+        .insert(file_path, encode(&indexed_commits).as_slice())?;
+    Ok(())
+}
+
+fn encode<T: serde::Serialize>(value: &T) -> Vec<u8> {
+    serde_json::to_vec(value).expect("Failed to serialize index entry")
+}
+
+// A corrupt entry only means that the file gets indexed again.
+fn decode<T: DeserializeOwned>(key: &str, bytes: &[u8]) -> Option<T> {
+    serde_json::from_slice(bytes)
+        .inspect_err(|e| eprintln!("Skipping corrupt index entry for {}: {}", key, e))
+        .ok()
+}
@@ -0,0 +1,79 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+pub const LOCK_FILE_NAME: &str = ".lock";
+
+// How long to wait for another process to release the lock before giving up.
+pub const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Writes through a temporary file in the same folder and renames it over the target, so a
+// crash never leaves a half-written file behind.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let file_name = path
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let temp_path = path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()));
+    let result = (|| {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        std::fs::rename(&temp_path, path)
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+// Waits until this process holds the lock file in the given folder, fails with TimedOut when
+// another process still holds it after `timeout`. The lock is released when the returned file
+// is dropped, or when the process dies.
+pub fn lock_folder(folder_path: &Path, timeout: Duration) -> std::io::Result<File> {
+    let lock_path = folder_path.join(LOCK_FILE_NAME);
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)?;
+    let started = Instant::now();
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(file),
+            Err(TryLockError::WouldBlock) if started.elapsed() < timeout => {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            Err(TryLockError::WouldBlock) => {
+                return Err(std::io::Error::new(
+                    ErrorKind::TimedOut,
+                    format!(
+                        "another process still holds {} after {:?}",
+                        lock_path.display(),
+                        timeout
+                    ),
+                ));
+            }
+            Err(TryLockError::Error(e)) => return Err(e),
+        }
+    }
+}
+
+// Total size of the files directly in the folder.
+pub fn folder_size(folder_path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(folder_path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+// Temporary files write_atomic leaves behind when the process dies halfway.
+pub fn is_temp_file(file_name: &str) -> bool {
+    file_name.starts_with('.') && file_name.ends_with(".tmp")
+}
@@ -0,0 +1,806 @@
+// Where the history of the repository is read from. Indexing and queries go through a
+// GitBackend for log, diff, show, rev-list and the files and commits of the repository, the
+// git_backend config picks one:
+// - GitCli (default): runs the git binary, one process per call.
+// - Libgit2: reads the repository in-process with libgit2, for the lookups (files, commits,
+//   ancestry, the latest commit of a path), commit details and patches. `log` and
+//   `log_stream` still run git with this backend, it has to be installed either way: history
+//   walks take git's options (--follow, parent rewriting, --topo-order, --diff-merges, formats)
+//   that libgit2 has no equivalent for, and reimplementing them is out of scope. Indexing
+//   reads most of its patches from those walks, so this backend doesn't make it faster.
+//
+// Both run in the current directory, like git does.
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::thread::JoinHandle;
+
+use git2::{
+    Commit, DiffFindOptions, DiffFormat, DiffOptions, ObjectType, Oid, Repository, Sort, Tree,
+    TreeWalkMode, TreeWalkResult,
+};
+
+use crate::config_impl::GitBackendKind;
+use crate::contextgpt_structs::CommitInfo;
+
+// Separators that can't show up in names or commit messages.
+pub const RECORD_SEPARATOR: char = '\x1e';
+pub const FIELD_SEPARATOR: char = '\x1f';
+
+#[derive(Debug)]
+pub enum GitError {
+    Io(std::io::Error),
+    // git failed, with what it printed.
+    Command(String),
+    Library(git2::Error),
+    OutsideRepository(String),
+}
+
+impl From<std::io::Error> for GitError {
+    fn from(error: std::io::Error) -> Self {
+        GitError::Io(error)
+    }
+}
+
+impl From<git2::Error> for GitError {
+    fn from(error: git2::Error) -> Self {
+        GitError::Library(error)
+    }
+}
+
+impl std::fmt::Display for GitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GitError::Io(error) => write!(f, "failed to run git: {}", error),
+            GitError::Command(stderr) => write!(f, "git failed: {}", stderr),
+            GitError::Library(error) => error.fmt(f),
+            GitError::OutsideRepository(path) => write!(f, "{} is outside of the repository", path),
+        }
+    }
+}
+
+// Paths are either relative to the current directory, absolute, or `:(top)` pathspecs
+// relative to the top of the repository.
+pub trait GitBackend: Send + Sync {
+    // What `git log <args>` prints.
+    fn log(&self, args: &[&str]) -> Result<String, GitError>;
+
+    // What `git log <args>` prints, line by line while git is still walking the history.
+    fn log_stream(&self, args: &[&str]) -> Result<GitStream, GitError>;
+
+    // Full hash of the last commit that changed the path, None if none did.
+    fn latest_commit(&self, path: &str) -> Result<Option<String>, GitError>;
+
+    // Zero context patch of the path from `parent` to `commit`, like `git diff -M`. When the
+    // file was renamed, `previous_path` is its name in `parent`.
+    fn diff(
+        &self,
+        parent: &str,
+        commit: &str,
+        path: &str,
+        previous_path: Option<&str>,
+    ) -> Result<String, GitError>;
+
+    // Zero context patch of every file from `parent` to `commit`, like `git diff -M`, line by
+    // line while git is still diffing. Paths start with a/ and b/ whatever the git config says.
+    fn diff_stream(&self, parent: &str, commit: &str) -> Result<GitStream, GitError>;
+
+    // Zero context patch of the path in the commit, merges against their first parent.
+    fn show(&self, commit: &str, path: &str) -> Result<String, GitError>;
+
+    // Details of the commits, in the same order. Fails when any of them is unknown.
+    fn commit_details(&self, commit_hashes: &[&String]) -> Result<Vec<CommitInfo>, GitError>;
+
+    // Full hashes of the commits reachable from `until` but not from `since`, newest first.
+    fn rev_list(&self, since: &str, until: &str) -> Result<Vec<String>, GitError>;
+
+    // Paths of all the files at HEAD, relative to the top of the repository.
+    fn tracked_files(&self) -> Result<Vec<String>, GitError>;
+
+    // Full hash of the commit checked out.
+    fn head_commit(&self) -> Result<String, GitError>;
+
+    // Whether the repository has the commit. False when it can't be read either.
+    fn commit_exists(&self, commit_hash: &str) -> bool;
+
+    // Whether `descendant` is `commit_hash` or one of its descendants. False when either
+    // is unknown.
+    fn is_ancestor(&self, commit_hash: &str, descendant: &str) -> bool;
+
+    // Whether the commit has the path, relative to the top of the repository. False when the
+    // commit is unknown.
+    fn path_exists(&self, commit_hash: &str, path: &str) -> bool;
+
+    // Top of the working tree.
+    fn repo_root(&self) -> Result<PathBuf, GitError>;
+
+    // URL of the remote.
+    fn remote_url(&self, name: &str) -> Result<String, GitError>;
+}
+
+// The backend the git_backend config picks.
+pub fn backend(kind: GitBackendKind) -> &'static dyn GitBackend {
+    match kind {
+        GitBackendKind::Git => &GitCli,
+        GitBackendKind::Libgit2 => &Libgit2,
+    }
+}
+
+// Lines of a running git command, without their line endings. Stops at the first read error,
+// git is killed if it's dropped before the end. Whether git succeeded is only known once it
+// exited, see finish.
+pub struct GitStream {
+    source: StreamSource,
+}
+
+enum StreamSource {
+    Git {
+        child: Child,
+        stdout: BufReader<ChildStdout>,
+        // What git prints to stderr, read on the side so that git never waits on a full pipe.
+        stderr: Option<JoinHandle<String>>,
+    },
+    // Read in-process already, with why it's empty when reading it failed.
+    Lines(std::vec::IntoIter<String>, Option<GitError>),
+}
+
+impl GitStream {
+    fn from_output(output: Result<String, GitError>) -> GitStream {
+        let (output, error) = match output {
+            Ok(output) => (output, None),
+            Err(error) => (String::new(), Some(error)),
+        };
+        let lines: Vec<String> = output.lines().map(|line| line.to_string()).collect();
+        GitStream {
+            source: StreamSource::Lines(lines.into_iter(), error),
+        }
+    }
+
+    // Reads what's left of the output and waits for git, fails with what git printed when it
+    // didn't succeed.
+    pub fn finish(mut self) -> Result<(), GitError> {
+        match &mut self.source {
+            StreamSource::Git {
+                child,
+                stdout,
+                stderr,
+            } => {
+                std::io::copy(stdout, &mut std::io::sink())?;
+                let status = child.wait()?;
+                let stderr = stderr
+                    .take()
+                    .and_then(|stderr| stderr.join().ok())
+                    .unwrap_or_default();
+                if !status.success() {
+                    return Err(GitError::Command(stderr.trim().to_string()));
+                }
+                Ok(())
+            }
+            StreamSource::Lines(_, error) => error.take().map_or(Ok(()), Err),
+        }
+    }
+}
+
+impl Iterator for GitStream {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        let stdout = match &mut self.source {
+            StreamSource::Git { stdout, .. } => stdout,
+            StreamSource::Lines(lines, _) => return lines.next(),
+        };
+        let mut line = vec![];
+        match stdout.read_until(b'\n', &mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => {
+                if line.ends_with(b"\n") {
+                    line.pop();
+                }
+                Some(String::from_utf8_lossy(&line).to_string())
+            }
+        }
+    }
+}
+
+impl Drop for GitStream {
+    fn drop(&mut self) {
+        if let StreamSource::Git { child, .. } = &mut self.source {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+pub struct GitCli;
+
+impl GitCli {
+    fn run<S: AsRef<std::ffi::OsStr>>(args: &[S]) -> Result<String, GitError> {
+        let output = Command::new("git")
+            // Paths are printed as they are, not quoted and escaped.
+            .args(["-c", "core.quotePath=false"])
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()?;
+        if !output.status.success() {
+            return Err(GitError::Command(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    fn spawn(args: &[&str]) -> Result<GitStream, GitError> {
+        let mut child = Command::new("git")
+            .args(["-c", "core.quotePath=false"])
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let mut stderr = child.stderr.take().expect("stderr is piped");
+        let stderr = std::thread::spawn(move || {
+            let mut stderr_buf = String::new();
+            let _ = stderr.read_to_string(&mut stderr_buf);
+            stderr_buf
+        });
+        Ok(GitStream {
+            source: StreamSource::Git {
+                child,
+                stdout: BufReader::new(stdout),
+                stderr: Some(stderr),
+            },
+        })
+    }
+}
+
+fn parse_commit_info(record: &str) -> Option<CommitInfo> {
+    // Format: <author name>, <author email>, <author date, ISO 8601>, <author date, unix>,
+    // <subject>, <body>, then the files changed, one per line.
+    let parts: Vec<&str> = record.split(FIELD_SEPARATOR).collect();
+    if parts.len() != 7 {
+        return None;
+    }
+    let files_changed: Vec<String> = parts[6]
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| line.to_string())
+        .collect();
+    Some(CommitInfo {
+        author: parts[0].to_string(),
+        email: parts[1].to_string(),
+        date: parts[2].to_string(),
+        timestamp: parts[3].trim().parse::<i64>().unwrap_or(0),
+        subject: parts[4].to_string(),
+        body: parts[5].trim().to_string(),
+        files_touched: files_changed.len(),
+        files_changed,
+    })
+}
+
+impl GitBackend for GitCli {
+    fn log(&self, args: &[&str]) -> Result<String, GitError> {
+        let mut log_args = vec!["log"];
+        log_args.extend(args);
+        GitCli::run(&log_args)
+    }
+
+    fn log_stream(&self, args: &[&str]) -> Result<GitStream, GitError> {
+        let mut log_args = vec!["log"];
+        log_args.extend(args);
+        GitCli::spawn(&log_args)
+    }
+
+    fn latest_commit(&self, path: &str) -> Result<Option<String>, GitError> {
+        let commit_hash = GitCli::run(&["log", "-1", "--pretty=format:%H", "--", path])?;
+        let commit_hash = commit_hash.trim();
+        Ok((!commit_hash.is_empty()).then(|| commit_hash.to_string()))
+    }
+
+    fn diff(
+        &self,
+        parent: &str,
+        commit: &str,
+        path: &str,
+        previous_path: Option<&str>,
+    ) -> Result<String, GitError> {
+        let range = format!("{}..{}", parent, commit);
+        let mut args = vec!["diff", &range, "--unified=0", "-M", "--"];
+        args.extend(previous_path);
+        args.push(path);
+        GitCli::run(&args)
+    }
+
+    fn diff_stream(&self, parent: &str, commit: &str) -> Result<GitStream, GitError> {
+        GitCli::spawn(&[
+            "diff",
+            "--unified=0",
+            "-M",
+            "--src-prefix=a/",
+            "--dst-prefix=b/",
+            parent,
+            commit,
+        ])
+    }
+
+    fn show(&self, commit: &str, path: &str) -> Result<String, GitError> {
+        GitCli::run(&[
+            "show",
+            "--unified=0",
+            "--diff-merges=first-parent",
+            commit,
+            "--",
+            path,
+        ])
+    }
+
+    fn commit_details(&self, commit_hashes: &[&String]) -> Result<Vec<CommitInfo>, GitError> {
+        let mut args: Vec<&str> = vec![
+            "show",
+            "--name-only",
+            "--format=%x1e%an%x1f%ae%x1f%aI%x1f%at%x1f%s%x1f%b%x1f",
+        ];
+        args.extend(commit_hashes.iter().map(|commit_hash| commit_hash.as_str()));
+        let stdout_buf = GitCli::run(&args)?;
+        // git show prints the commits in the order they were given.
+        let commit_infos: Option<Vec<CommitInfo>> = stdout_buf
+            .split(RECORD_SEPARATOR)
+            .skip(1)
+            .map(parse_commit_info)
+            .collect();
+        match commit_infos {
+            Some(commit_infos) if commit_infos.len() == commit_hashes.len() => Ok(commit_infos),
+            _ => Err(GitError::Command(String::from(
+                "unexpected output of git show",
+            ))),
+        }
+    }
+
+    fn rev_list(&self, since: &str, until: &str) -> Result<Vec<String>, GitError> {
+        let stdout_buf = GitCli::run(&["rev-list", &format!("{}..{}", since, until)])?;
+        Ok(stdout_buf.lines().map(|line| line.to_string()).collect())
+    }
+
+    fn tracked_files(&self) -> Result<Vec<String>, GitError> {
+        let stdout_buf = GitCli::run(&["ls-tree", "-r", "--name-only", "--full-tree", "HEAD"])?;
+        Ok(stdout_buf.lines().map(|line| line.to_string()).collect())
+    }
+
+    fn head_commit(&self) -> Result<String, GitError> {
+        Ok(GitCli::run(&["rev-parse", "HEAD"])?.trim().to_string())
+    }
+
+    fn commit_exists(&self, commit_hash: &str) -> bool {
+        GitCli::run(&["cat-file", "-e", &format!("{}^{{commit}}", commit_hash)]).is_ok()
+    }
+
+    fn is_ancestor(&self, commit_hash: &str, descendant: &str) -> bool {
+        GitCli::run(&["merge-base", "--is-ancestor", commit_hash, descendant]).is_ok()
+    }
+
+    fn path_exists(&self, commit_hash: &str, path: &str) -> bool {
+        GitCli::run(&["cat-file", "-e", &format!("{}:{}", commit_hash, path)]).is_ok()
+    }
+
+    fn repo_root(&self) -> Result<PathBuf, GitError> {
+        let repo_root = GitCli::run(&["rev-parse", "--show-toplevel"])?;
+        Ok(PathBuf::from(repo_root.trim()))
+    }
+
+    fn remote_url(&self, name: &str) -> Result<String, GitError> {
+        Ok(GitCli::run(&["remote", "get-url", name])?
+            .trim()
+            .to_string())
+    }
+}
+
+pub struct Libgit2;
+
+thread_local! {
+    // Repositories can't be shared between threads, each one opens its own. Reopened when
+    // the current directory changes.
+    static REPOSITORY: RefCell<Option<(PathBuf, Repository)>> = const { RefCell::new(None) };
+}
+
+fn with_repository<T>(f: impl FnOnce(&Repository) -> Result<T, GitError>) -> Result<T, GitError> {
+    let current_dir = std::env::current_dir()?;
+    REPOSITORY.with(|repository| {
+        let mut repository = repository.borrow_mut();
+        if repository
+            .as_ref()
+            .is_none_or(|(repository_dir, _)| *repository_dir != current_dir)
+        {
+            *repository = Some((current_dir.clone(), Repository::discover(&current_dir)?));
+        }
+        f(&repository.as_ref().unwrap().1)
+    })
+}
+
+// The last commit that changed the path, like `git log -1 -- <path>`: the newest commits are
+// read first, and a merge that has the path as it is in one of its parents only leads to that
+// parent, like git's history simplification. Changes the merge left out don't count.
+fn latest_commit(
+    repository: &Repository,
+    head: Commit,
+    path: &Path,
+) -> Result<Option<String>, GitError> {
+    let entry = |commit: &Commit| -> Result<Option<(Oid, i32)>, GitError> {
+        let entry = commit.tree()?.get_path(path).ok();
+        Ok(entry.map(|entry| (entry.id(), entry.filemode())))
+    };
+    // Commits to read next, the newest first, in the order they were queued when they have
+    // the same time like git does.
+    let mut pending = BinaryHeap::from([(head.time().seconds(), Reverse(0), head.id())]);
+    let mut queued = HashSet::from([head.id()]);
+    while let Some((_, _, commit_id)) = pending.pop() {
+        let commit = repository.find_commit(commit_id)?;
+        let commit_entry = entry(&commit)?;
+        let parents: Vec<Commit> = commit.parents().collect();
+        let mut same_parent = None;
+        for parent in parents.iter() {
+            if entry(parent)? == commit_entry {
+                same_parent = Some(parent);
+                break;
+            }
+        }
+        let Some(parent) = same_parent else {
+            if parents.is_empty() && commit_entry.is_none() {
+                continue;
+            }
+            return Ok(Some(commit_id.to_string()));
+        };
+        if queued.insert(parent.id()) {
+            pending.push((parent.time().seconds(), Reverse(queued.len()), parent.id()));
+        }
+    }
+    Ok(None)
+}
+
+// The path relative to the top of the repository.
+fn repo_path(repository: &Repository, path: &str) -> Result<PathBuf, GitError> {
+    if let Some(top_path) = path.strip_prefix(":(top)") {
+        return Ok(PathBuf::from(top_path));
+    }
+    let outside = || GitError::OutsideRepository(path.to_string());
+    let workdir = repository.workdir().ok_or_else(outside)?;
+    let workdir = std::fs::canonicalize(workdir).unwrap_or(workdir.to_path_buf());
+    let absolute_path = std::env::current_dir()?.join(path);
+    // The file may be gone by now.
+    let absolute_path = std::fs::canonicalize(&absolute_path).unwrap_or(absolute_path);
+    absolute_path
+        .strip_prefix(&workdir)
+        .map(Path::to_path_buf)
+        .map_err(|_| outside())
+}
+
+fn parent_trees<'repo>(commit: &Commit<'repo>) -> Result<Vec<Tree<'repo>>, GitError> {
+    commit
+        .parents()
+        .map(|parent| parent.tree().map_err(GitError::from))
+        .collect()
+}
+
+// Same output as git for the given paths, with zero lines of context.
+fn patch(
+    repository: &Repository,
+    old_tree: Option<&Tree>,
+    new_tree: &Tree,
+    paths: &[PathBuf],
+    find_renames: bool,
+) -> Result<String, GitError> {
+    let mut options = DiffOptions::new();
+    options.context_lines(0).disable_pathspec_match(true);
+    for path in paths {
+        options.pathspec(path);
+    }
+    let mut diff = repository.diff_tree_to_tree(old_tree, Some(new_tree), Some(&mut options))?;
+    if find_renames {
+        diff.find_similar(Some(DiffFindOptions::new().renames(true)))?;
+    }
+    let mut patch = String::new();
+    diff.print(DiffFormat::Patch, |_, _, line| {
+        if matches!(line.origin(), '+' | '-' | ' ') {
+            patch.push(line.origin());
+        }
+        patch.push_str(&String::from_utf8_lossy(line.content()));
+        true
+    })?;
+    Ok(patch)
+}
+
+// Paths changed by the commit, like `git show --name-only`: merges only list the files
+// that differ from every parent. Without find_renames, the old paths of renamed files are
+// listed too.
+fn files_changed(
+    repository: &Repository,
+    commit: &Commit,
+    find_renames: bool,
+) -> Result<Vec<String>, GitError> {
+    let tree = commit.tree()?;
+    let parent_trees = parent_trees(commit)?;
+    let changed_paths = |parent_tree: Option<&Tree>, find_renames: bool| {
+        let mut diff = repository.diff_tree_to_tree(parent_tree, Some(&tree), None)?;
+        if find_renames {
+            diff.find_similar(Some(DiffFindOptions::new().renames(true)))?;
+        }
+        let paths: Vec<String> = diff
+            .deltas()
+            .filter_map(|delta| {
+                delta
+                    .new_file()
+                    .path()
+                    .map(|path| path.to_string_lossy().to_string())
+            })
+            .collect();
+        Ok::<Vec<String>, GitError>(paths)
+    };
+    match parent_trees.as_slice() {
+        [] => changed_paths(None, false),
+        [parent_tree] => changed_paths(Some(parent_tree), find_renames),
+        [first_parent_tree, other_parent_trees @ ..] => {
+            let mut paths = changed_paths(Some(first_parent_tree), false)?;
+            for parent_tree in other_parent_trees {
+                let other_paths: HashSet<String> = changed_paths(Some(parent_tree), false)?
+                    .into_iter()
+                    .collect();
+                paths.retain(|path| other_paths.contains(path));
+            }
+            Ok(paths)
+        }
+    }
+}
+
+// `git log --format=%aI`, e.g. 2024-01-01T10:00:00+05:30.
+fn iso_8601(seconds: i64, offset_minutes: i32) -> String {
+    let local_seconds = seconds + offset_minutes as i64 * 60;
+    let days = local_seconds.div_euclid(86400);
+    let seconds_of_day = local_seconds.rem_euclid(86400);
+    // Civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    let sign = if offset_minutes < 0 { '-' } else { '+' };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60,
+        seconds_of_day % 60,
+        sign,
+        offset_minutes.abs() / 60,
+        offset_minutes.abs() % 60
+    )
+}
+
+impl GitBackend for Libgit2 {
+    // History walks run git, see the top of the file.
+    fn log(&self, args: &[&str]) -> Result<String, GitError> {
+        GitCli.log(args)
+    }
+
+    fn log_stream(&self, args: &[&str]) -> Result<GitStream, GitError> {
+        GitCli.log_stream(args)
+    }
+
+    fn diff_stream(&self, parent: &str, commit: &str) -> Result<GitStream, GitError> {
+        // Read at once, failures show once the stream is read like with git.
+        let patch = with_repository(|repository| {
+            let parent_tree = repository.revparse_single(parent)?.peel_to_tree()?;
+            let tree = repository.revparse_single(commit)?.peel_to_tree()?;
+            patch(repository, Some(&parent_tree), &tree, &[], true)
+        });
+        Ok(GitStream::from_output(patch))
+    }
+
+    fn latest_commit(&self, path: &str) -> Result<Option<String>, GitError> {
+        with_repository(|repository| {
+            let path = repo_path(repository, path)?;
+            latest_commit(repository, repository.head()?.peel_to_commit()?, &path)
+        })
+    }
+
+    fn diff(
+        &self,
+        parent: &str,
+        commit: &str,
+        path: &str,
+        previous_path: Option<&str>,
+    ) -> Result<String, GitError> {
+        with_repository(|repository| {
+            let parent_tree = repository.revparse_single(parent)?.peel_to_tree()?;
+            let tree = repository.revparse_single(commit)?.peel_to_tree()?;
+            let mut paths = vec![];
+            if let Some(previous_path) = previous_path {
+                paths.push(repo_path(repository, previous_path)?);
+            }
+            paths.push(repo_path(repository, path)?);
+            patch(repository, Some(&parent_tree), &tree, &paths, true)
+        })
+    }
+
+    fn show(&self, commit: &str, path: &str) -> Result<String, GitError> {
+        with_repository(|repository| {
+            let commit = repository.revparse_single(commit)?.peel_to_commit()?;
+            let first_parent_tree = parent_trees(&commit)?.into_iter().next();
+            patch(
+                repository,
+                first_parent_tree.as_ref(),
+                &commit.tree()?,
+                &[repo_path(repository, path)?],
+                false,
+            )
+        })
+    }
+
+    fn commit_details(&self, commit_hashes: &[&String]) -> Result<Vec<CommitInfo>, GitError> {
+        with_repository(|repository| {
+            let mut commit_infos = vec![];
+            for commit_hash in commit_hashes {
+                let commit = repository.revparse_single(commit_hash)?.peel_to_commit()?;
+                let author = commit.author();
+                let files_changed = files_changed(repository, &commit, true)?;
+                commit_infos.push(CommitInfo {
+                    author: String::from_utf8_lossy(author.name_bytes()).to_string(),
+                    email: String::from_utf8_lossy(author.email_bytes()).to_string(),
+                    date: iso_8601(author.when().seconds(), author.when().offset_minutes()),
+                    timestamp: author.when().seconds(),
+                    subject: commit
+                        .summary_bytes()
+                        .map(|subject| String::from_utf8_lossy(subject).to_string())
+                        .unwrap_or_default(),
+                    body: commit
+                        .body_bytes()
+                        .map(|body| String::from_utf8_lossy(body).trim().to_string())
+                        .unwrap_or_default(),
+                    files_touched: files_changed.len(),
+                    files_changed,
+                });
+            }
+            Ok(commit_infos)
+        })
+    }
+
+    fn rev_list(&self, since: &str, until: &str) -> Result<Vec<String>, GitError> {
+        with_repository(|repository| {
+            let mut revwalk = repository.revwalk()?;
+            revwalk.push(repository.revparse_single(until)?.peel_to_commit()?.id())?;
+            revwalk.hide(repository.revparse_single(since)?.peel_to_commit()?.id())?;
+            // Children before their parents, even when they were committed the same second.
+            revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+            revwalk
+                .map(|commit_id| Ok(commit_id?.to_string()))
+                .collect()
+        })
+    }
+
+    fn tracked_files(&self) -> Result<Vec<String>, GitError> {
+        with_repository(|repository| {
+            let tree = repository.head()?.peel_to_tree()?;
+            let mut tracked_files = vec![];
+            tree.walk(TreeWalkMode::PreOrder, |directory, entry| {
+                if entry.kind() != Some(ObjectType::Tree) {
+                    let name = String::from_utf8_lossy(entry.name_bytes());
+                    tracked_files.push(format!("{}{}", directory, name));
+                }
+                TreeWalkResult::Ok
+            })?;
+            Ok(tracked_files)
+        })
+    }
+
+    fn head_commit(&self) -> Result<String, GitError> {
+        with_repository(|repository| Ok(repository.head()?.peel_to_commit()?.id().to_string()))
+    }
+
+    fn commit_exists(&self, commit_hash: &str) -> bool {
+        with_repository(|repository| {
+            Ok(repository
+                .revparse_single(commit_hash)
+                .and_then(|object| object.peel_to_commit())
+                .is_ok())
+        })
+        .unwrap_or(false)
+    }
+
+    fn is_ancestor(&self, commit_hash: &str, descendant: &str) -> bool {
+        with_repository(|repository| {
+            let commit_id = repository
+                .revparse_single(commit_hash)?
+                .peel_to_commit()?
+                .id();
+            let descendant_id = repository
+                .revparse_single(descendant)?
+                .peel_to_commit()?
+                .id();
+            Ok(commit_id == descendant_id
+                || repository.graph_descendant_of(descendant_id, commit_id)?)
+        })
+        .unwrap_or(false)
+    }
+
+    fn path_exists(&self, commit_hash: &str, path: &str) -> bool {
+        with_repository(|repository| {
+            let tree = repository.revparse_single(commit_hash)?.peel_to_tree()?;
+            Ok(tree.get_path(Path::new(path)).is_ok())
+        })
+        .unwrap_or(false)
+    }
+
+    fn repo_root(&self) -> Result<PathBuf, GitError> {
+        with_repository(|repository| {
+            let workdir = repository
+                .workdir()
+                .ok_or_else(|| GitError::Command(String::from("bare repository")))?;
+            // Without the trailing separator, like git prints it.
+            Ok(workdir.components().collect())
+        })
+    }
+
+    fn remote_url(&self, name: &str) -> Result<String, GitError> {
+        with_repository(|repository| {
+            let remote = repository.find_remote(name)?;
+            Ok(String::from_utf8_lossy(remote.url_bytes()).to_string())
+        })
+    }
+}
+
+// Resolves the abbreviated commit hashes of indexes written by older versions, in the
+// repository that holds each file.
+#[derive(Default)]
+pub struct HashExpander {
+    // The repository of each directory asked about, None outside of one.
+    directories: HashMap<PathBuf, Option<usize>>,
+    repositories: Vec<Repository>,
+    expanded: HashMap<(usize, String), Option<String>>,
+}
+
+impl HashExpander {
+    // The full hash, None if no commit or several commits start with it.
+    pub fn expand(&mut self, file_path: &str, commit_hash: &str) -> Option<String> {
+        let directory = Path::new(file_path).parent()?.to_path_buf();
+        let repository_index = match self.directories.get(&directory) {
+            Some(repository_index) => *repository_index,
+            None => {
+                let repository_index = Repository::discover(&directory).ok().map(|repository| {
+                    let workdir = repository.workdir().map(Path::to_path_buf);
+                    // Directories of the same repository share it.
+                    self.repositories
+                        .iter()
+                        .position(|known| known.workdir().map(Path::to_path_buf) == workdir)
+                        .unwrap_or_else(|| {
+                            self.repositories.push(repository);
+                            self.repositories.len() - 1
+                        })
+                });
+                self.directories.insert(directory, repository_index);
+                repository_index
+            }
+        }?;
+        let repository = &self.repositories[repository_index];
+        self.expanded
+            .entry((repository_index, commit_hash.to_string()))
+            .or_insert_with(|| {
+                repository
+                    .find_commit_by_prefix(commit_hash)
+                    .ok()
+                    .map(|commit| commit.id().to_string())
+            })
+            .clone()
+    }
+}
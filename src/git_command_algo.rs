@@ -1,22 +1,25 @@
 use ignore::Walk;
 use ignore::gitignore::GitignoreBuilder;
+use serde::{Deserialize, Serialize};
 
-use crate::{contextgpt_structs::AuthorDetailsV2, diff_v2};
+use crate::config_impl::MergePolicy;
+use crate::contextgpt_structs::{AuthorDetailsV2, CommitDescription, CommitInfo};
+use crate::diff_v2;
+use crate::git_backend::{GitBackend, RECORD_SEPARATOR};
+use crate::index_store::FileLines;
 
-use crate::git_command_algo;
+use std::borrow::Borrow;
 use std::collections::{HashMap, HashSet};
-use std::process::{Command, Stdio};
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
-pub fn print_all_valid_directories(
+pub fn get_all_valid_directories(
     workspace_dir: String,
     gitignore_file_name: Option<String>,
-) {
-    // Prints all the valid files to stdout - used by plugins
-    // optionally to get files that are to be indexed.
-    // if gitignore_file_name.is_none() {
-    //     println!("None.");
-    //     return;
-    // }
+) -> Vec<String> {
+    // Returns all the valid directories (relative to the workspace) - used by plugins
+    // optionally to pick the folders that are to be indexed.
     let gitignore_file_name = gitignore_file_name.unwrap_or(String::from(".gitignore"));
     let mut gitignore_builder = GitignoreBuilder::new(workspace_dir.clone());
     gitignore_builder.add(gitignore_file_name);
@@ -29,26 +32,17 @@ pub fn print_all_valid_directories(
                 let path = entry.path();
                 if path.is_file() {
                     continue;
-                    // // Check if the file is ignored
-                    // if gitignore.matched(path, false).is_ignore() {
-                    //     continue;
-                    // }
-                    // // Print the file path -- it's valid!
-                    // println!("{}", path.display());
-                } else {
-                    // Check if the whole dir is ignored:
-                    if gitignore.matched(path, true).is_ignore() {
-                        // Skip the directory.
-                        continue;
-                    }
-                    // Print the relative path only:
-                    let rel_path = path.strip_prefix(workspace_dir.clone());
-                    if rel_path.is_ok() {
-                        let relative_path = rel_path.clone().unwrap();
-                        if !relative_path.to_path_buf().to_string_lossy().is_empty() {
-                            all_paths.push(relative_path.display().to_string());
-                        }
-                    }
+                }
+                // Check if the whole dir is ignored:
+                if gitignore.matched(path, true).is_ignore() {
+                    // Skip the directory.
+                    continue;
+                }
+                // Store the relative path only:
+                if let Ok(relative_path) = path.strip_prefix(workspace_dir.clone())
+                    && !relative_path.to_string_lossy().is_empty()
+                {
+                    all_paths.push(relative_path.display().to_string());
                 }
             }
             Err(err) => {
@@ -56,38 +50,33 @@ pub fn print_all_valid_directories(
             }
         }
     }
-    println!("{:?}", all_paths);
+    all_paths
 }
 
-pub fn print_all_valid_files(workspace_dir: String, gitignore_file_name: Option<String>) -> () {
-    // Prints all the valid files to stdout - used by plugins
-    // optionally to get files that are to be indexed.
-    // if gitignore_file_name.is_none() {
-    //     println!("None.");
-    //     return;
-    // }
+pub fn print_all_valid_directories(workspace_dir: String, gitignore_file_name: Option<String>) {
+    // Prints all the valid directories to stdout - used by plugins.
+    println!(
+        "{:?}",
+        get_all_valid_directories(workspace_dir, gitignore_file_name)
+    );
+}
+
+pub fn get_all_valid_files(
+    workspace_dir: String,
+    gitignore_file_name: Option<String>,
+) -> Vec<PathBuf> {
+    // All the files of the workspace that .gitignore doesn't leave out.
     let gitignore_file_name = gitignore_file_name.unwrap_or(String::from(".gitignore"));
     let mut gitignore_builder = GitignoreBuilder::new(workspace_dir.clone());
     gitignore_builder.add(gitignore_file_name);
     let gitignore = gitignore_builder.build().expect("Failed");
-    // Iterate through all the files in the workspace_dir:
+    let mut all_files: Vec<PathBuf> = vec![];
     for walk_entry in Walk::new(workspace_dir.clone()) {
         match walk_entry {
             Ok(entry) => {
                 let path = entry.path();
-                if path.is_file() {
-                    // Check if the file is ignored
-                    if gitignore.matched(path, false).is_ignore() {
-                        continue;
-                    }
-                    // Print the file path -- it's valid!
-                    println!("{}", path.display());
-                } else {
-                    // Check if the whole dir is ignored:
-                    if gitignore.matched(path, true).is_ignore() {
-                        // Skip the directory.
-                        continue;
-                    }
+                if path.is_file() && !gitignore.matched(path, false).is_ignore() {
+                    all_files.push(path.to_path_buf());
                 }
             }
             Err(err) => {
@@ -95,85 +84,200 @@ pub fn print_all_valid_files(workspace_dir: String, gitignore_file_name: Option<
             }
         }
     }
+    all_files
 }
 
-pub fn get_files_changed(commit_hash: &str) -> Vec<String> {
-    // Use git show (minimal) API to find "all the files" changed in the given commit hash.
-    // git show --pretty="" --name-only <commit_hash>
-    let mut command = Command::new("git");
-    let c_hash = commit_hash;
-    command.args(["show", "--pretty=", "--name-only", c_hash]);
-    let output = command
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .unwrap();
-    let stdout_buf = String::from_utf8(output.stdout).unwrap();
-    let mut files_changed: Vec<String> = Vec::new();
-    for line in stdout_buf.lines() {
-        files_changed.push(line.to_string());
+fn build_author_details(
+    origin_file_path: &str,
+    map: &HashMap<u32, Vec<diff_v2::LineDetail>>,
+) -> HashMap<u32, AuthorDetailsV2> {
+    let mut auth_details_map: HashMap<u32, AuthorDetailsV2> = HashMap::new();
+    for (line_number, line_detail) in map.iter() {
+        let author_details = AuthorDetailsV2 {
+            origin_file_path: origin_file_path.to_string(),
+            line_number: *line_number as usize,
+            commit_hashes: line_detail[0].commit_hashes.clone(),
+        };
+        auth_details_map.insert(*line_number, author_details);
     }
-    files_changed
+    auth_details_map
 }
 
+// Number of commits passed to a single `git show` call.
+const COMMITS_PER_GIT_CALL: usize = 256;
+
+pub fn get_commit_details(
+    git: &dyn GitBackend,
+    commit_hashes: &[String],
+) -> HashMap<String, CommitInfo> {
+    // Author, date, message and files changed for each of the given commits, using as few
+    // git calls as possible.
+    let mut unique_hashes: Vec<&String> = vec![];
+    let mut seen: HashSet<&String> = HashSet::new();
+    for commit_hash in commit_hashes {
+        if seen.insert(commit_hash) {
+            unique_hashes.push(commit_hash);
+        }
+    }
+    let mut commit_details: HashMap<String, CommitInfo> = HashMap::new();
+    for chunk in unique_hashes.chunks(COMMITS_PER_GIT_CALL) {
+        // git show prints the commits in the order they were given.
+        match git.commit_details(chunk) {
+            Ok(infos) => {
+                for (commit_hash, info) in chunk.iter().zip(infos) {
+                    commit_details.insert(commit_hash.to_string(), info);
+                }
+            }
+            Err(_) => {
+                // A single unknown commit fails the whole call, retry one by one.
+                for commit_hash in chunk {
+                    if let Ok(mut infos) = git.commit_details(&[*commit_hash])
+                        && let Some(info) = infos.pop()
+                    {
+                        commit_details.insert(commit_hash.to_string(), info);
+                    }
+                }
+            }
+        }
+    }
+    commit_details
+}
 
-pub async fn index_some_commits(
+// Replays the given commits, oldest first. With the lines the file had at the commit it was
+// indexed at, they're replayed on top of those, leaving out the commits that were indexed
+// already. Otherwise from an empty file, the first commit with its own patch.
+pub fn index_some_commits(
+    git: &dyn GitBackend,
     origin_file_path: String,
     commits_to_index: Vec<String>,
+    indexed_lines: Option<(String, FileLines)>,
 ) -> HashMap<u32, AuthorDetailsV2> {
-    // For now - this is not parallelized, TODO: @krshrimali.
-    // First get all the commit hashes that ever touched the given file path.
-    let mut map: HashMap<u32, Vec<diff_v2::LineDetail>> = HashMap::new();
-    let mut parent_commit_hash: String = String::from("");
+    let (mut map, mut parent_commit_hash, commits_to_index) = match indexed_lines {
+        Some((indexed_commit, lines)) => {
+            let new_commits: HashSet<String> = git
+                .rev_list(&indexed_commit, "HEAD")
+                .unwrap_or_else(|e| {
+                    eprintln!("Failed to list the commits after {}: {}", indexed_commit, e);
+                    Vec::new()
+                })
+                .into_iter()
+                .collect();
+            let commits_to_index: Vec<String> = commits_to_index
+                .into_iter()
+                .filter(|commit_hash| new_commits.contains(commit_hash))
+                .collect();
+            (line_details(&lines), indexed_commit, commits_to_index)
+        }
+        None => (HashMap::new(), String::new(), commits_to_index),
+    };
     for commit_hash in commits_to_index.iter() {
-        diff_v2::extract_commit_hashes(&parent_commit_hash, commit_hash, &mut map, origin_file_path.as_str());
+        diff_v2::extract_commit_hashes(
+            git,
+            &parent_commit_hash,
+            commit_hash,
+            &mut map,
+            origin_file_path.as_str(),
+        );
         parent_commit_hash = commit_hash.clone();
     }
     // Map has populated "relevant commit hashes" for each line.
-    // Now use those commit hashes to find the most relevant files for each line.
-    let mut auth_details_map: HashMap<u32, AuthorDetailsV2> = HashMap::new();
-    let mut sorted_keys: Vec<u32> = map.keys().copied().collect();
-    sorted_keys.sort();
-    for line_number in sorted_keys.iter() {
-        let line_detail = map.get(line_number).unwrap();
-        // author_full_name is a TODO.
-        let author_details = AuthorDetailsV2 {
-            origin_file_path: origin_file_path.clone(),
-            line_number: *line_number as usize,
-            commit_hashes: line_detail[0].commit_hashes.clone(),
-            author_full_name: Vec::new(),
-        };
-        auth_details_map.insert(*line_number, author_details.clone());
+    build_author_details(&origin_file_path, &map)
+}
+
+// Stored lines, to replay commits on. The contents of the lines don't matter to the diffs,
+// only where they are.
+fn line_details(lines: &FileLines) -> HashMap<u32, Vec<diff_v2::LineDetail>> {
+    lines
+        .iter()
+        .map(|(line_number, commit_hashes)| {
+            let line_detail = diff_v2::LineDetail {
+                content: String::new(),
+                commit_hashes: commit_hashes.clone(),
+            };
+            (*line_number as u32, vec![line_detail])
+        })
+        .collect()
+}
+
+// Replays the commits that changed the file since the one it was indexed at, oldest first,
+// on top of the lines it had back then. Each commit is diffed against the one replayed
+// before it, merges against their first parent. None when that wouldn't give the lines
+// indexing from scratch gives: the indexed commit left the history of HEAD, the file had
+// another name back then, or merges have to be replayed with all their parents.
+pub fn index_commits_since(
+    git: &dyn GitBackend,
+    file_path: &str,
+    indexed_commit: &str,
+    lines: &FileLines,
+    merge_policy: MergePolicy,
+) -> Option<HashMap<u32, AuthorDetailsV2>> {
+    if !git.is_ancestor(indexed_commit, "HEAD") {
+        return None;
     }
-    auth_details_map
+    let stdout_buf = run_git_log(
+        git,
+        &[
+            "--reverse",
+            "--first-parent",
+            "--diff-merges=first-parent",
+            "--name-only",
+            "--pretty=format:%x00%H %P",
+            &format!("{}..HEAD", indexed_commit),
+            "--",
+            file_path,
+        ],
+    );
+    let mut commit_hashes: Vec<String> = vec![];
+    let mut path: Option<String> = None;
+    for line in stdout_buf.lines() {
+        if let Some(commit_line) = line.strip_prefix('\0') {
+            let mut hashes = commit_line.split(' ');
+            commit_hashes.extend(hashes.next().map(|commit_hash| commit_hash.to_string()));
+            if hashes.count() > 1 && merge_policy != MergePolicy::FirstParent {
+                return None;
+            }
+        } else if !line.is_empty() {
+            path.get_or_insert(line.to_string());
+        }
+    }
+    if let Some(path) = &path
+        && !git.path_exists(indexed_commit, path)
+    {
+        return None;
+    }
+
+    let mut map = line_details(lines);
+    let Some(path) = path else {
+        return Some(build_author_details(file_path, &map));
+    };
+    let mut parent_commit_hash = indexed_commit.to_string();
+    for commit_hash in commit_hashes {
+        diff_v2::extract_commit_hashes(
+            git,
+            &parent_commit_hash,
+            &commit_hash,
+            &mut map,
+            &pathspec(&path),
+        );
+        parent_commit_hash = commit_hash;
+    }
+    Some(build_author_details(file_path, &map))
 }
 
-pub async fn extract_details_parallel(file_path: String) -> HashMap<u32, AuthorDetailsV2> {
+pub fn extract_details_with_merge_policy(
+    git: &dyn GitBackend,
+    file_path: String,
+    merge_policy: MergePolicy,
+) -> HashMap<u32, AuthorDetailsV2> {
     // For now - this is not parallelized, TODO: @krshrimali.
     // First get all the commit hashes that ever touched the given file path.
-    let commit_hashes = git_command_algo::get_all_commits_for_file(file_path.clone());
-    let mut map: HashMap<u32, Vec<diff_v2::LineDetail>> = HashMap::new();
-    let mut parent_commit_hash: String = String::from("");
-    for commit_hash in commit_hashes.iter() {
-        diff_v2::extract_commit_hashes(&parent_commit_hash, commit_hash, &mut map, file_path.as_str());
-        parent_commit_hash = commit_hash.clone();
-    }
+    let map = match merge_policy {
+        MergePolicy::FirstParent => index_first_parent_history(git, &file_path),
+        MergePolicy::AllParents => index_all_parents_history(git, &file_path, false),
+        MergePolicy::Skip => index_all_parents_history(git, &file_path, true),
+    };
     // Map has populated "relevant commit hashes" for each line.
-    // Now use those commit hashes to find the most relevant files for each line.
-    let mut auth_details_map: HashMap<u32, AuthorDetailsV2> = HashMap::new();
-    let mut sorted_keys: Vec<u32> = map.keys().copied().collect();
-    sorted_keys.sort();
-    for line_number in sorted_keys.iter() {
-        let line_detail = map.get(line_number).unwrap();
-        // author_full_name is a TODO.
-        let author_details = AuthorDetailsV2 {
-            origin_file_path: file_path.clone(),
-            line_number: *line_number as usize,
-            commit_hashes: line_detail[0].commit_hashes.clone(),
-            author_full_name: Vec::new(),
-        };
-        auth_details_map.insert(*line_number, author_details.clone());
-    }
+    let auth_details_map = build_author_details(&file_path, &map);
     // FIXME: @krshrimali - Remove this once proper testing is done.
     // let mut total_count = 0;
     // let mut failed_count = 0;
@@ -263,176 +367,852 @@ pub async fn extract_details_parallel(file_path: String) -> HashMap<u32, AuthorD
     auth_details_map
 }
 
-pub fn get_all_commits_for_file(file_path: String) -> Vec<String> {
-    let mut command = Command::new("git");
-    command.args([
-        "log",
-        "--pretty=format:%h",
+fn pathspec(path: &str) -> String {
+    format!(":(top){}", path)
+}
+
+// The patches of every commit that touched one of the file's names, oldest first, read from a
+// single `git log -p` instead of running git once per commit. `options` pick the commits, they
+// have to walk the history like the log the commits to replay came from.
+fn patch_stream<'a>(
+    git: &dyn GitBackend,
+    options: &[&str],
+    paths: impl Iterator<Item = &'a String>,
+) -> Option<diff_v2::PatchStream> {
+    let mut pathspecs: Vec<String> = paths.map(|path| pathspec(path)).collect();
+    pathspecs.sort();
+    pathspecs.dedup();
+    if pathspecs.is_empty() {
+        return None;
+    }
+    let mut args = vec![
         "--reverse",
-        "--",
-        file_path.as_str(),
-    ]);
-    let output = command
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .unwrap();
-    let stdout_buf = String::from_utf8(output.stdout).unwrap();
-    let mut commits: Vec<String> = Vec::new();
-    for line in stdout_buf.lines() {
-        commits.push(line.to_string());
-    }
-    // Ensure commits contains git blame output as well for each line.
-    // This is to ensure that we have the commit hashes in the order they were made.
-    if commits.is_empty() {
-        // If no commits found, return an empty vector.
-        return commits;
-    }
-    // Add the last commit hash as well, which is the current state of the file.
-    let mut command = Command::new("git");
-    command.args(["log", "--pretty=format:%h", "--", file_path.as_str()]);
-    let output = command
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .unwrap();
-    let stdout_buf = String::from_utf8(output.stdout).unwrap();
-    // For each line number - create another hashmap.
-    let mut last_commit_map: HashMap<usize, String> = HashMap::new();
-    for (idx, line) in stdout_buf.lines().enumerate() {
-        let commit_hash = line.to_string();
-        last_commit_map.insert(idx, commit_hash.clone());
-    }
-    // Now iterate through last_commit_map and check if it is in commits.
-    for (_, commit_hash) in last_commit_map.iter() {
-        if !commits.contains(commit_hash) {
-            commits.push(commit_hash.clone());
-        }
-    }
-    commits
-}
-
-fn get_commit_base_url() -> Option<String> {
-    if let Ok(output) = Command::new("git")
-        .args(["remote", "get-url", "origin"])
-        .output()
-    {
-        if output.status.success() {
-            if let Ok(url) = String::from_utf8(output.stdout) {
-                let url = url.trim();
-                // Handle GitHub URLs (both HTTPS and SSH)
-                if url.starts_with("git@github.com:") {
-                    let path = url.strip_prefix("git@github.com:").unwrap();
-                    // Optionally strip ".git" if present
-                    let path = path.strip_suffix(".git").unwrap_or(path);
-                    return Some(format!("https://github.com/{}/commit/", path));
-                } else if url.starts_with("https://github.com/") {
-                    let path = url.strip_prefix("https://github.com/").unwrap();
-                    // Optionally strip ".git" if present
-                    let path = path.strip_suffix(".git").unwrap_or(path);
-                    return Some(format!("https://github.com/{}/commit/", path));
+        "-M",
+        "-p",
+        "--unified=0",
+        "--src-prefix=a/",
+        "--dst-prefix=b/",
+        "--pretty=format:%x00%H",
+    ];
+    args.extend(options);
+    args.push("--");
+    args.extend(pathspecs.iter().map(|pathspec| pathspec.as_str()));
+    diff_v2::PatchStream::new(git, &args)
+}
+
+// Replays the first-parent history of the file, merges are diffed against their first parent.
+fn index_first_parent_history(
+    git: &dyn GitBackend,
+    file_path: &str,
+) -> HashMap<u32, Vec<diff_v2::LineDetail>> {
+    let file_history = get_file_history(git, file_path);
+    let mut patches = patch_stream(
+        git,
+        &["--first-parent", "--diff-merges=first-parent"],
+        file_history.iter().map(|(_, path)| path),
+    );
+    let mut map: HashMap<u32, Vec<diff_v2::LineDetail>> = HashMap::new();
+    let mut parent_commit_hash: String = String::from("");
+    let mut parent_path: Option<String> = None;
+    for (commit_hash, path) in file_history.iter() {
+        let streamed = patches
+            .as_mut()
+            .is_some_and(|patches| patches.extract_commit_hashes(commit_hash, &mut map, path));
+        // The history follows renames, so diff the file under the name it had back then.
+        let previous_pathspec = parent_path
+            .as_ref()
+            .filter(|parent_path| *parent_path != path)
+            .map(|parent_path| pathspec(parent_path));
+        if !streamed {
+            diff_v2::extract_commit_hashes_with_rename(
+                git,
+                &parent_commit_hash,
+                commit_hash,
+                &mut map,
+                pathspec(path).as_str(),
+                previous_pathspec.as_deref(),
+            );
+        }
+        parent_commit_hash = commit_hash.clone();
+        parent_path = Some(path.clone());
+    }
+    // Commits the log missed were diffed on their own, the map is complete either way.
+    finish_patches(patches);
+    map
+}
+
+fn finish_patches(patches: Option<diff_v2::PatchStream>) {
+    if let Some(Err(e)) = patches.map(|patches| patches.finish()) {
+        eprintln!("Failed to read the history: {}", e);
+    }
+}
+
+// Replays every branch of the file's history. Each commit starts from the lines of its first
+// parent, merges then take back the history of the lines they didn't change compared to the
+// other parents.
+fn index_all_parents_history(
+    git: &dyn GitBackend,
+    file_path: &str,
+    skip_merges: bool,
+) -> HashMap<u32, Vec<diff_v2::LineDetail>> {
+    let file_paths = get_file_paths(git, file_path);
+    let file_history = get_file_graph(git, &file_paths);
+    // Merges are diffed against their other parents one at a time, these aren't in the log.
+    let mut patches = patch_stream(
+        git,
+        &["--topo-order", "--parents", "--diff-merges=first-parent"],
+        file_paths.iter(),
+    );
+    // Maps are only kept around until all the children of a commit were replayed.
+    let mut children_left: HashMap<&str, usize> = HashMap::new();
+    for revision in file_history.iter() {
+        for parent in revision.parents.iter() {
+            *children_left.entry(parent.as_str()).or_default() += 1;
+        }
+    }
+    let mut maps: HashMap<&str, HashMap<u32, Vec<diff_v2::LineDetail>>> = HashMap::new();
+    let mut paths: HashMap<&str, String> = HashMap::new();
+    let mut merges: HashSet<&str> = HashSet::new();
+    let mut last_map: HashMap<u32, Vec<diff_v2::LineDetail>> = HashMap::new();
+    let current_path = file_history
+        .iter()
+        .rev()
+        .find_map(|revision| revision.path.clone())
+        .unwrap_or_default();
+    for revision in file_history.iter() {
+        let commit_hash = revision.commit_hash.as_str();
+        // Parents outside of the file's history can't be replayed, start from scratch then.
+        let parents: Vec<&str> = revision
+            .parents
+            .iter()
+            .map(|parent| parent.as_str())
+            .filter(|parent| maps.contains_key(parent))
+            .collect();
+        let path = match &revision.path {
+            Some(path) => path.clone(),
+            None => merge_path(git, commit_hash, &parents, &paths).unwrap_or(current_path.clone()),
+        };
+        let previous_pathspec = |parent: &str| {
+            paths
+                .get(parent)
+                .filter(|parent_path| **parent_path != path)
+                .map(|parent_path| pathspec(parent_path))
+        };
+        let mut map = match parents.first() {
+            Some(first_parent) => maps[first_parent].clone(),
+            None => HashMap::new(),
+        };
+        // The log diffs against the first parent, same as starting from scratch without one.
+        let first_parent_replayed =
+            parents.first().copied() == revision.parents.first().map(String::as_str);
+        let streamed = (parents.is_empty() || first_parent_replayed)
+            && patches
+                .as_mut()
+                .is_some_and(|patches| patches.extract_commit_hashes(commit_hash, &mut map, &path));
+        if !streamed {
+            diff_v2::extract_commit_hashes_with_rename(
+                git,
+                &parents.first().map(|parent| parent.to_string()).unwrap_or_default(),
+                commit_hash,
+                &mut map,
+                pathspec(&path).as_str(),
+                parents
+                    .first()
+                    .and_then(|parent| previous_pathspec(parent))
+                    .as_deref(),
+            );
+        }
+        for parent in parents.iter().skip(1) {
+            diff_v2::carry_over_parent_history(
+                git,
+                parent,
+                commit_hash,
+                &mut map,
+                &maps[parent],
+                pathspec(&path).as_str(),
+                previous_pathspec(parent).as_deref(),
+            );
+        }
+        if revision.parents.len() > 1 {
+            merges.insert(commit_hash);
+        }
+        for parent in revision.parents.iter() {
+            if let Some(children) = children_left.get_mut(parent.as_str()) {
+                *children -= 1;
+                if *children == 0 {
+                    maps.remove(parent.as_str());
                 }
             }
         }
+        if children_left.contains_key(commit_hash) {
+            maps.insert(commit_hash, map.clone());
+        }
+        paths.insert(commit_hash, path);
+        last_map = map;
     }
-    None
+    finish_patches(patches);
+    if skip_merges {
+        drop_merges(&mut last_map, &merges);
+    }
+    last_map
 }
 
-pub fn get_commit_descriptions(commit_hashes: Vec<String>) -> Vec<Vec<String>> {
-    let mut output_vec = Vec::new();
-    let mut visited_commits = HashSet::new();
+// Leaves the merges out of the history of the lines, for MergePolicy::Skip.
+fn drop_merges<S: Borrow<str> + Eq + Hash>(
+    map: &mut HashMap<u32, Vec<diff_v2::LineDetail>>,
+    merges: &HashSet<S>,
+) {
+    for line_details in map.values_mut() {
+        for line_detail in line_details.iter_mut() {
+            // Lines that only ever changed in merges keep them, there's nothing else to show.
+            if line_detail
+                .commit_hashes
+                .iter()
+                .any(|commit_hash| !merges.contains(commit_hash.as_str()))
+            {
+                line_detail
+                    .commit_hashes
+                    .retain(|commit_hash| !merges.contains(commit_hash.as_str()));
+            }
+        }
+    }
+}
 
-    let base_url = get_commit_base_url();
+// The lines of a file while replaying the history of the whole repository. Branches share
+// them until one of the branches changes the file.
+type SharedLines = Rc<HashMap<u32, Vec<diff_v2::LineDetail>>>;
 
-    for commit_hash in commit_hashes.iter() {
-        if visited_commits.contains(commit_hash) {
+// Replays the history of the whole repository in a single walk, for the given files (absolute
+// paths). Each commit is read once for all the files it touched instead of once per file, at
+// the cost of keeping the lines of every file in the repository around during the walk.
+pub fn extract_repository_details(
+    git: &dyn GitBackend,
+    file_paths: &[String],
+    merge_policy: MergePolicy,
+) -> HashMap<String, HashMap<u32, AuthorDetailsV2>> {
+    let Some(repo_root) = get_repo_root(git) else {
+        return HashMap::new();
+    };
+    let repository_lines = index_repository_history(git, merge_policy);
+    let maps: Vec<(&String, &SharedLines)> = file_paths
+        .iter()
+        .filter_map(|file_path| {
+            let relative_path = Path::new(file_path).strip_prefix(&repo_root).ok()?;
+            Some((file_path, repository_lines.get(relative_path.to_str()?)?))
+        })
+        .collect();
+    maps.into_iter()
+        .map(|(file_path, map)| {
+            (
+                file_path.clone(),
+                build_author_details(file_path, map),
+            )
+        })
+        .collect()
+}
+
+// The lines of every file in the repository at HEAD, keyed by their path relative to the top
+// of the repository. The history is walked the way index_first_parent_history and
+// index_all_parents_history walk the one of a single file.
+fn index_repository_history(
+    git: &dyn GitBackend,
+    merge_policy: MergePolicy,
+) -> HashMap<String, SharedLines> {
+    let first_parent_only = merge_policy == MergePolicy::FirstParent;
+    let mut walk_options = vec!["--reverse", "--topo-order"];
+    if first_parent_only {
+        walk_options.push("--first-parent");
+    }
+    // Files of a commit are only kept around until all of its children were replayed.
+    let mut children_left: HashMap<String, usize> = HashMap::new();
+    let graph_args = [walk_options.as_slice(), &["--pretty=format:%H %P"]].concat();
+    for line in run_git_log(git, &graph_args).lines() {
+        let parents = line.split_whitespace().skip(1);
+        let parent_count = if first_parent_only { 1 } else { usize::MAX };
+        for parent in parents.take(parent_count) {
+            *children_left.entry(parent.to_string()).or_default() += 1;
+        }
+    }
+    let patch_args = [
+        walk_options.as_slice(),
+        &[
+            "-M",
+            "-p",
+            "--unified=0",
+            "--src-prefix=a/",
+            "--dst-prefix=b/",
+            "--diff-merges=first-parent",
+            "--pretty=format:%x00%H %P",
+        ],
+    ]
+    .concat();
+    let Some(mut patches) = diff_v2::PatchStream::new(git, &patch_args) else {
+        return HashMap::new();
+    };
+    let mut files_at: HashMap<String, HashMap<String, SharedLines>> = HashMap::new();
+    let mut merges: HashSet<String> = HashSet::new();
+    let mut head_files: HashMap<String, SharedLines> = HashMap::new();
+    while let Some((commit_hash, mut parents)) = patches.next_commit() {
+        if first_parent_only {
+            parents.truncate(1);
+        }
+        // Each commit starts from the files of its first parent.
+        let mut files = match parents.first() {
+            Some(parent) if children_left.get(parent) == Some(&1) => {
+                files_at.remove(parent).unwrap_or_default()
+            }
+            Some(parent) => files_at.get(parent).cloned().unwrap_or_default(),
+            None => HashMap::new(),
+        };
+        let mut changed_files: HashSet<String> = HashSet::new();
+        while let Some(file) = patches.next_file() {
+            let Some(new_path) = file.new_path else {
+                if let Some(old_path) = file.old_path {
+                    files.remove(&old_path);
+                }
+                continue;
+            };
+            // Renamed files take their lines along, added ones start from scratch.
+            let mut lines = file
+                .old_path
+                .and_then(|old_path| files.remove(&old_path))
+                .unwrap_or_default();
+            patches.extract_file(&commit_hash, Rc::make_mut(&mut lines));
+            files.insert(new_path.clone(), lines);
+            changed_files.insert(new_path);
+        }
+        if parents.len() > 1 {
+            for parent in parents.iter().skip(1) {
+                if let Some(parent_files) = files_at.get(parent) {
+                    carry_over_merged_lines(
+                        git,
+                        &commit_hash,
+                        parent,
+                        &mut files,
+                        parent_files,
+                        &changed_files,
+                    );
+                }
+            }
+            merges.insert(commit_hash.clone());
+        }
+        for parent in parents.iter() {
+            if let Some(children) = children_left.get_mut(parent) {
+                *children -= 1;
+                if *children == 0 {
+                    files_at.remove(parent);
+                }
+            }
+        }
+        // Everything but HEAD has children.
+        if children_left.contains_key(&commit_hash) {
+            files_at.insert(commit_hash, files);
+        } else {
+            head_files = files;
+        }
+    }
+    // Commits after the ones git printed would be missing, nothing is better than a wrong index.
+    if let Err(e) = patches.finish() {
+        eprintln!("Failed to read the history: {}", e);
+        return HashMap::new();
+    }
+    if merge_policy == MergePolicy::Skip {
+        for lines in head_files.values_mut() {
+            drop_merges(Rc::make_mut(lines), &merges);
+        }
+    }
+    head_files
+}
+
+// The files a merge changed compared to its first parent have all the lines it brought in
+// attributed to it. Give the ones that are unchanged compared to `parent` their history there,
+// see diff_v2::carry_over_parent_history.
+fn carry_over_merged_lines(
+    git: &dyn GitBackend,
+    commit_hash: &str,
+    parent: &str,
+    files: &mut HashMap<String, SharedLines>,
+    parent_files: &HashMap<String, SharedLines>,
+    changed_files: &HashSet<String>,
+) {
+    if changed_files.is_empty() {
+        return;
+    }
+    let Some(mut patches) = diff_v2::PatchStream::diff(git, parent, commit_hash) else {
+        return;
+    };
+    // Files that aren't in the diff are the same as in the parent.
+    let mut unchanged_files = changed_files.clone();
+    while let Some(file) = patches.next_file() {
+        let Some(new_path) = file.new_path.filter(|path| changed_files.contains(path)) else {
             continue;
+        };
+        unchanged_files.remove(&new_path);
+        if let Some(old_path) = file.old_path
+            && let Some(parent_lines) = parent_files.get(&old_path)
+            && let Some(lines) = files.get_mut(&new_path)
+        {
+            let hunks = patches.file_hunks();
+            diff_v2::carry_over_parent_hunks(&hunks, commit_hash, Rc::make_mut(lines), parent_lines);
+        }
+    }
+    // Files after the ones git printed aren't unchanged, they're unknown.
+    if let Err(e) = patches.finish() {
+        eprintln!("Error: {}", e);
+        return;
+    }
+    for path in unchanged_files {
+        if let Some(parent_lines) = parent_files.get(&path)
+            && let Some(lines) = files.get_mut(&path)
+        {
+            diff_v2::carry_over_parent_hunks(&[], commit_hash, Rc::make_mut(lines), parent_lines);
         }
+    }
+}
 
-        if let Ok(output) = Command::new("git")
-            .args([
-                "show",
-                "-s",
-                "--format=%s%n%b%n--AUTHOR--%n%an%n--DATE--%n%cd",
-                "--date=local",
-                commit_hash,
-            ])
-            .output()
+// Merges don't list the file, it keeps its name unless the branches disagree on it.
+fn merge_path(
+    git: &dyn GitBackend,
+    commit_hash: &str,
+    parents: &[&str],
+    paths: &HashMap<&str, String>,
+) -> Option<String> {
+    let parent_paths: Vec<&String> = parents.iter().filter_map(|parent| paths.get(parent)).collect();
+    if parent_paths.windows(2).all(|pair| pair[0] == pair[1]) {
+        return parent_paths.first().map(|path| path.to_string());
+    }
+    parent_paths
+        .into_iter()
+        .find(|path| git.path_exists(commit_hash, path))
+        .cloned()
+}
+
+// All the commits that touched the file following the first parent of merges, oldest first,
+// along with the path the file had in each of them (relative to the top of the repository).
+// Follows renames.
+pub fn get_file_history(git: &dyn GitBackend, file_path: &str) -> Vec<(String, String)> {
+    // --follow doesn't work with --reverse, so this is newest first.
+    let stdout_buf = run_git_log(
+        git,
+        &[
+            "--follow",
+            "--first-parent",
+            "--name-only",
+            "--pretty=format:%x00%H",
+            "--",
+            file_path,
+        ],
+    );
+    let mut history: Vec<(String, Option<String>)> = vec![];
+    for line in stdout_buf.lines() {
+        if let Some(commit_hash) = line.strip_prefix('\0') {
+            history.push((commit_hash.to_string(), None));
+        } else if !line.is_empty()
+            && let Some((_, path)) = history.last_mut()
         {
-            if output.status.success() {
-                visited_commits.insert(commit_hash.clone());
-                if let Ok(output_str) = String::from_utf8(output.stdout) {
-                    let sections: Vec<&str> = output_str.split("\n--AUTHOR--\n").collect();
-                    if sections.len() == 2 {
-                        let message = sections[0].trim();
-                        let mut lines = message.lines();
-                        let commit_title = lines.next().unwrap_or("").trim().to_string();
-                        let commit_description =
-                            lines.collect::<Vec<_>>().join("\n").trim().to_string();
-
-                        let parts: Vec<&str> = sections[1].split("\n--DATE--\n").collect();
-                        if parts.len() == 2 {
-                            let author_name = parts[0].trim().to_string();
-                            let commit_datetime = parts[1].trim().to_string();
-
-                            let commit_url = base_url
-                                .as_ref()
-                                .map(|url| format!("{}{}", url, commit_hash))
-                                .unwrap_or_else(|| "".to_string());
-
-                            output_vec.push(vec![
-                                commit_title,
-                                commit_description,
-                                author_name,
-                                commit_datetime,
-                                commit_url,
-                            ]);
-                        }
-                    }
-                }
+            path.get_or_insert(line.to_string());
+        }
+    }
+    // Merges don't list the file, they keep the name it has in the newer commits.
+    let mut current_path = history
+        .iter()
+        .find_map(|(_, path)| path.clone())
+        .unwrap_or_default();
+    let mut file_history: Vec<(String, String)> = history
+        .into_iter()
+        .map(|(commit_hash, path)| {
+            if let Some(path) = path {
+                current_path = path;
             }
+            (commit_hash, current_path.clone())
+        })
+        .collect();
+    file_history.reverse();
+    file_history
+}
+
+// A commit in the history of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileRevision {
+    pub commit_hash: String,
+    // Only the parents that are part of the file's history.
+    pub parents: Vec<String>,
+    // Path of the file in this commit, relative to the top of the repository. Merges don't have
+    // one.
+    pub path: Option<String>,
+}
+
+// Every name the file ever had, relative to the top of the repository. --follow can't be
+// combined with parent rewriting, so the graph is walked with all of them instead.
+pub fn get_file_paths(git: &dyn GitBackend, file_path: &str) -> Vec<String> {
+    let mut paths: Vec<String> = get_file_history(git, file_path)
+        .into_iter()
+        .map(|(_, path)| path)
+        .collect();
+    paths.extend(
+        run_git_log(
+            git,
+            &["--follow", "--name-only", "--pretty=format:", "--", file_path],
+        )
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| line.to_string()),
+    );
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+// All the commits that touched the file on any branch, parents before their children.
+// Follows renames, `paths` are all the names the file had (see get_file_paths).
+pub fn get_file_graph(git: &dyn GitBackend, paths: &[String]) -> Vec<FileRevision> {
+    if paths.is_empty() {
+        // Untracked, without any pathspec git would list the whole repository.
+        return vec![];
+    }
+    let mut args: Vec<String> = [
+        "--topo-order",
+        "--parents",
+        "-M",
+        "--name-status",
+        "--pretty=format:%x00%H %P",
+        "--",
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect();
+    args.extend(paths.iter().map(|path| pathspec(path)));
+    let stdout_buf = run_git_log(git, &args);
+    let mut file_graph: Vec<FileRevision> = vec![];
+    for line in stdout_buf.lines() {
+        if let Some(commit_line) = line.strip_prefix('\0') {
+            // --parents rewrites the parents to the closest ones that touched the file.
+            let mut hashes = commit_line.split_whitespace().map(|hash| hash.to_string());
+            file_graph.push(FileRevision {
+                commit_hash: hashes.next().unwrap_or_default(),
+                parents: hashes.collect(),
+                path: None,
+            });
+        } else if let Some(revision) = file_graph.last_mut() {
+            // <status>\t<path>, or <status>\t<old path>\t<new path> for renames.
+            let parts: Vec<&str> = line.split('\t').collect();
+            if parts.len() < 2 {
+                continue;
+            }
+            // Prefer the name the file has after the commit, over one it was deleted from.
+            if revision.path.is_none() || !parts[0].starts_with('D') {
+                revision.path = parts.last().map(|path| path.to_string());
+            }
+        }
+    }
+    file_graph.reverse();
+    file_graph
+}
+
+fn run_git_log<S: AsRef<str>>(git: &dyn GitBackend, args: &[S]) -> String {
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_ref()).collect();
+    git.log(&args).unwrap_or_else(|e| {
+        eprintln!("Failed to read the history: {}", e);
+        String::new()
+    })
+}
+
+// Maps paths that show up in old commits to where those files live now. Kept in the index
+// along with the commit it was built at, see DB::load_path_resolver.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PathResolver {
+    // Old path -> new path, one entry per rename.
+    renames: HashMap<String, String>,
+    // Everything that exists at HEAD.
+    tracked_files: HashSet<String>,
+}
+
+impl PathResolver {
+    pub fn new(renames: HashMap<String, String>, tracked_files: HashSet<String>) -> PathResolver {
+        PathResolver {
+            renames,
+            tracked_files,
+        }
+    }
+
+    pub fn from_repo(git: &dyn GitBackend) -> PathResolver {
+        PathResolver::new(get_renames(git, None), get_tracked_files(git))
+    }
+
+    // Brings a resolver built at the given commit up to HEAD, only the commits since then are
+    // read. False when the commit isn't in the history of HEAD anymore (e.g. after a rebase),
+    // the resolver has to be built from scratch then.
+    pub fn catch_up(&mut self, git: &dyn GitBackend, commit_hash: &str) -> bool {
+        if !git.is_ancestor(commit_hash, "HEAD") {
+            return false;
+        }
+        // Newer renames win over the older ones.
+        self.renames.extend(get_renames(git, Some(commit_hash)));
+        self.tracked_files = get_tracked_files(git);
+        true
+    }
+
+    pub fn tracked_file_count(&self) -> usize {
+        self.tracked_files.len()
+    }
+
+    // Returns the current path, the path it was renamed from (if it was) and whether it's
+    // gone from HEAD.
+    pub fn resolve(&self, path: &str) -> (String, Option<String>, bool) {
+        let mut current_path = path.to_string();
+        let mut seen: HashSet<String> = HashSet::new();
+        // A file can be renamed more than once, and names can come back (a -> b -> a).
+        while !self.tracked_files.contains(&current_path)
+            && seen.insert(current_path.clone())
+            && let Some(new_path) = self.renames.get(&current_path)
+        {
+            current_path = new_path.clone();
+        }
+        let renamed_from = (current_path != path).then(|| path.to_string());
+        let deleted = !self.tracked_files.contains(&current_path);
+        (current_path, renamed_from, deleted)
+    }
+}
+
+// Every rename in the history, or in the commits after the given one, old path -> new path.
+// The most recent one wins.
+pub fn get_renames(git: &dyn GitBackend, since: Option<&str>) -> HashMap<String, String> {
+    let mut args = vec![
+        "-M".to_string(),
+        "--diff-filter=R".to_string(),
+        "--name-status".to_string(),
+        "--pretty=format:".to_string(),
+    ];
+    if let Some(since) = since {
+        args.push(format!("{}..HEAD", since));
+    }
+    let stdout_buf = run_git_log(git, &args);
+    let mut renames: HashMap<String, String> = HashMap::new();
+    for line in stdout_buf.lines() {
+        // R<similarity>\t<old path>\t<new path>
+        let parts: Vec<&str> = line.split('\t').collect();
+        if parts.len() == 3 && parts[0].starts_with('R') {
+            renames
+                .entry(parts[1].to_string())
+                .or_insert(parts[2].to_string());
+        }
+    }
+    renames
+}
+
+// Paths (relative to the top of the repository) of all the files at HEAD.
+pub fn get_tracked_files(git: &dyn GitBackend) -> HashSet<String> {
+    match git.tracked_files() {
+        Ok(tracked_files) => tracked_files.into_iter().collect(),
+        Err(e) => {
+            eprintln!("Failed to list the files at HEAD: {}", e);
+            HashSet::new()
+        }
+    }
+}
+
+fn get_commit_base_url(git: &dyn GitBackend) -> Option<String> {
+    if let Ok(url) = git.remote_url("origin") {
+        let url = url.trim();
+        // Handle GitHub URLs (both HTTPS and SSH)
+        if url.starts_with("git@github.com:") {
+            let path = url.strip_prefix("git@github.com:").unwrap();
+            // Optionally strip ".git" if present
+            let path = path.strip_suffix(".git").unwrap_or(path);
+            return Some(format!("https://github.com/{}/commit/", path));
+        } else if url.starts_with("https://github.com/") {
+            let path = url.strip_prefix("https://github.com/").unwrap();
+            // Optionally strip ".git" if present
+            let path = path.strip_suffix(".git").unwrap_or(path);
+            return Some(format!("https://github.com/{}/commit/", path));
         }
     }
+    None
+}
+
+// One description per distinct commit, in the given order. Commits without details are
+// left out.
+pub fn describe_commits(
+    git: &dyn GitBackend,
+    commit_hashes: &[String],
+    commit_details: &HashMap<String, CommitInfo>,
+) -> Vec<CommitDescription> {
+    let base_url = get_commit_base_url(git);
+    let mut visited_commits = HashSet::new();
+    let mut output_vec = Vec::new();
+    for commit_hash in commit_hashes.iter() {
+        if !visited_commits.insert(commit_hash) {
+            continue;
+        }
+        let Some(commit_info) = commit_details.get(commit_hash) else {
+            continue;
+        };
+        let commit_url = base_url
+            .as_ref()
+            .map(|url| format!("{}{}", url, commit_hash))
+            .unwrap_or_default();
+        output_vec.push(CommitDescription {
+            hash: commit_hash.clone(),
+            title: commit_info.subject.clone(),
+            body: commit_info.body.clone(),
+            author: commit_info.author.clone(),
+            date: commit_info.date.clone(),
+            url: commit_url,
+        });
+    }
     output_vec
 }
 
-pub fn get_latest_commit(file_path: &String) -> Option<String> {
+// Full hash of the commit checked out in the repository.
+pub fn get_head_commit(git: &dyn GitBackend) -> Option<String> {
+    git.head_commit()
+        .ok()
+        .filter(|commit_hash| !commit_hash.is_empty())
+}
+
+pub fn commit_exists(git: &dyn GitBackend, commit_hash: &str) -> bool {
+    git.commit_exists(commit_hash)
+}
+
+pub fn get_latest_commit(git: &dyn GitBackend, file_path: &str) -> Option<String> {
     // Get the latest commit hash for the given file path.
-    let mut command = Command::new("git");
-    command.args(["log", "-1", "--pretty=format:%h", "--", file_path.as_str()]);
-    let output = command
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .unwrap();
-    if output.status.success() {
-        if let Ok(commit_hash) = String::from_utf8(output.stdout) {
-            let commit_hash = commit_hash.trim().to_string();
-            if !commit_hash.is_empty() {
-                return Some(commit_hash);
+    git.latest_commit(file_path).ok().flatten()
+}
+
+// The latest commit of every file at HEAD, keyed by absolute path, in a single walk of the
+// history. Same as get_latest_commit for each of them: a merge that has the file as it is in
+// one of its parents only leads to the first such parent, so changes a merge left out don't
+// count even when they are newer.
+pub fn get_latest_commits(git: &dyn GitBackend) -> HashMap<String, String> {
+    let mut latest_commits = HashMap::new();
+    let (Some(repo_root), Ok(tracked_files)) = (get_repo_root(git), git.tracked_files()) else {
+        return latest_commits;
+    };
+    // Children before their parents. Merges show once for each parent they differ from.
+    let stdout_buf = run_git_log(
+        git,
+        &[
+            "-m",
+            "--no-renames",
+            "--topo-order",
+            "--name-only",
+            "--format=%x1e%H %T %P",
+            "HEAD",
+        ],
+    );
+    let mut commits: Vec<HistoryCommit> = vec![];
+    for record in stdout_buf.split(RECORD_SEPARATOR).skip(1) {
+        let mut lines = record.lines();
+        let Some(header) = lines.next() else {
+            continue;
+        };
+        let mut hashes = header.split_whitespace().map(str::to_string);
+        let (Some(commit_hash), Some(tree)) = (hashes.next(), hashes.next()) else {
+            continue;
+        };
+        let changes: HashSet<String> = lines
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect();
+        match commits.last_mut() {
+            Some(commit) if commit.hash == commit_hash => commit.changes.push(changes),
+            _ => commits.push(HistoryCommit {
+                hash: commit_hash,
+                tree,
+                parents: hashes.collect(),
+                changes: vec![changes],
+            }),
+        }
+    }
+    let trees: HashMap<String, String> = commits
+        .iter()
+        .map(|commit| (commit.hash.clone(), commit.tree.clone()))
+        .collect();
+
+    // The files whose latest commit is still to be found, by the commit they are the same in.
+    let mut pending: HashMap<String, HashSet<String>> = HashMap::new();
+    if let Some(head) = commits.first() {
+        pending.insert(head.hash.clone(), tracked_files.into_iter().collect());
+    }
+    for commit in commits.iter() {
+        let Some(mut file_names) = pending.remove(&commit.hash) else {
+            continue;
+        };
+        let parent_changes = commit.parent_changes(&trees);
+        let Some((first_parent, first_changes)) = commit.parents.iter().zip(&parent_changes).next()
+        else {
+            // Everything in the first commit was added there.
+            for file_name in file_names {
+                latest_commits.insert(file_name, commit.hash.clone());
             }
+            continue;
+        };
+        for file_name in first_changes {
+            if !file_names.remove(file_name) {
+                continue;
+            }
+            let same_parent = commit
+                .parents
+                .iter()
+                .zip(&parent_changes)
+                .find(|(_, changes)| !changes.contains(file_name));
+            match same_parent {
+                Some((parent, _)) => {
+                    pending
+                        .entry(parent.clone())
+                        .or_default()
+                        .insert(file_name.clone());
+                }
+                None => {
+                    latest_commits.insert(file_name.clone(), commit.hash.clone());
+                }
+            }
+        }
+        let parent_file_names = pending.entry(first_parent.clone()).or_default();
+        if parent_file_names.len() < file_names.len() {
+            std::mem::swap(parent_file_names, &mut file_names);
         }
+        parent_file_names.extend(file_names);
     }
-    None
+    latest_commits
+        .into_iter()
+        .map(|(file_name, commit_hash)| {
+            (
+                repo_root.join(file_name).to_string_lossy().to_string(),
+                commit_hash,
+            )
+        })
+        .collect()
 }
 
-pub fn get_commits_after(last_indexed_commit: String) -> Vec<String> {
-    // Get all the commits after the last indexed commit.
-    // If last_indexed_commit is None, return all commits.
-    // If recent_commit is None, return all commits after last_indexed_commit.
-    let mut command = Command::new("git");
-    command.args(["rev-list", &last_indexed_commit, "..", "HEAD"]);
-
-    let output = command
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .unwrap();
+// A commit in the output of get_latest_commits' walk.
+struct HistoryCommit {
+    hash: String,
+    tree: String,
+    parents: Vec<String>,
+    // The files changed compared to each parent it differs from, in the order of the parents.
+    changes: Vec<HashSet<String>>,
+}
 
-    if output.status.success() {
-        let stdout_buf = String::from_utf8(output.stdout).unwrap();
-        return stdout_buf.lines().map(|s| s.to_string()).collect();
+impl HistoryCommit {
+    // The files changed compared to each of the parents. Git leaves out the parents that have
+    // the same tree, the changes it printed go to the others in order.
+    fn parent_changes(&self, trees: &HashMap<String, String>) -> Vec<HashSet<String>> {
+        if self.parents.is_empty() {
+            return self.changes.iter().take(1).cloned().collect();
+        }
+        let mut changes = self.changes.iter().filter(|changes| !changes.is_empty());
+        self.parents
+            .iter()
+            .map(|parent| match trees.get(parent) {
+                Some(tree) if *tree == self.tree => HashSet::new(),
+                _ => changes.next().cloned().unwrap_or_default(),
+            })
+            .collect()
     }
+}
 
-    Vec::new()
-}
\ No newline at end of file
+pub fn get_repo_root(git: &dyn GitBackend) -> Option<PathBuf> {
+    let repo_root = git.repo_root().ok()?;
+    std::fs::canonicalize(&repo_root).ok().or(Some(repo_root))
+}
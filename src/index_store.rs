@@ -0,0 +1,233 @@
+// Where the index of a workspace is kept. DB only talks to this trait, the backends are:
+// - EmbeddedStore (default): a single embedded database file in the DB folder.
+// - JsonStore: numbered JSON shards plus mapping.json, the layout of older versions.
+// - MemoryStore: plain maps, nothing touches the disk (for tests).
+use std::collections::{BTreeMap, HashMap};
+
+use crate::contextgpt_structs::CommitInfo;
+use crate::git_command_algo::PathResolver;
+
+// Line number -> commit hashes, for a single file.
+pub type FileLines = HashMap<usize, Vec<String>>;
+
+#[derive(Debug)]
+pub enum StoreError {
+    // Boxed since redb's errors are large.
+    Database(Box<redb::Error>),
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    // The index was written by a version that can't be upgraded from, with the reason.
+    ReindexRequired(String),
+}
+
+macro_rules! database_errors {
+    ($($error:ty),*) => {
+        $(
+            impl From<$error> for StoreError {
+                fn from(error: $error) -> Self {
+                    StoreError::Database(Box::new(error.into()))
+                }
+            }
+        )*
+    }
+}
+
+database_errors!(
+    redb::Error,
+    redb::DatabaseError,
+    redb::TransactionError,
+    redb::TableError,
+    redb::StorageError,
+    redb::CommitError,
+    redb::CompactionError
+);
+
+impl From<std::io::Error> for StoreError {
+    fn from(error: std::io::Error) -> Self {
+        StoreError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(error: serde_json::Error) -> Self {
+        StoreError::Json(error)
+    }
+}
+
+impl std::fmt::Display for StoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoreError::Database(error) => error.fmt(f),
+            StoreError::Io(error) => error.fmt(f),
+            StoreError::Json(error) => error.fmt(f),
+            StoreError::ReindexRequired(reason) => write!(
+                f,
+                "the index can't be upgraded ({}), please re-index the workspace",
+                reason
+            ),
+        }
+    }
+}
+
+pub trait IndexStore {
+    // Lines of a file, None when it isn't indexed.
+    fn get_file_lines(&self, file_path: &str) -> Result<Option<FileLines>, StoreError>;
+
+    // Replaces the lines of a file, and records the commit it was indexed at.
+    fn put_file_lines(
+        &mut self,
+        file_path: &str,
+        lines: &FileLines,
+        indexed_commit: Option<&str>,
+    ) -> Result<(), StoreError>;
+
+    // Same as put_file_lines for many files, backends can do it at once.
+    fn put_files(
+        &mut self,
+        files: &[(String, FileLines, Option<String>)],
+    ) -> Result<(), StoreError> {
+        for (file_path, lines, indexed_commit) in files {
+            self.put_file_lines(file_path, lines, indexed_commit.as_deref())?;
+        }
+        Ok(())
+    }
+
+    fn last_indexed_commit(&self, file_path: &str) -> Result<Option<String>, StoreError>;
+
+    // Records the commit a file was indexed at, without touching its lines.
+    fn set_indexed_commit(&mut self, file_path: &str, commit_hash: &str) -> Result<(), StoreError>;
+
+    // Paths of all the files with lines, sorted.
+    fn list_files(&self) -> Result<Vec<String>, StoreError>;
+
+    // Drops the lines of a file and the commit it was indexed at.
+    fn remove_file(&mut self, file_path: &str) -> Result<(), StoreError>;
+
+    // Author details of the given commits, the ones that aren't stored are left out.
+    fn get_commits(
+        &self,
+        commit_hashes: &[String],
+    ) -> Result<HashMap<String, CommitInfo>, StoreError>;
+
+    fn put_commits(&mut self, commits: &HashMap<String, CommitInfo>) -> Result<(), StoreError>;
+
+    // Records why a file couldn't be indexed, until its lines are stored or it's removed.
+    fn mark_failed(&mut self, file_path: &str, reason: &str) -> Result<(), StoreError>;
+
+    // File path -> why it couldn't be indexed.
+    fn failed_files(&self) -> Result<BTreeMap<String, String>, StoreError>;
+
+    fn has_file(&self, file_path: &str) -> Result<bool, StoreError> {
+        Ok(self.get_file_lines(file_path)?.is_some())
+    }
+
+    // The renames and files of the repository with the commit they were read at, so that
+    // queries don't walk the whole history. Backends that don't keep it build it every time.
+    fn get_path_resolver(&self) -> Result<Option<(String, PathResolver)>, StoreError> {
+        Ok(None)
+    }
+
+    // Replaces the stored one.
+    fn put_path_resolver(
+        &mut self,
+        _commit_hash: &str,
+        _path_resolver: &PathResolver,
+    ) -> Result<(), StoreError> {
+        Ok(())
+    }
+}
+
+// Only the tests use it.
+#[allow(dead_code)]
+#[derive(Default, Debug, Clone)]
+pub struct MemoryStore {
+    pub files: HashMap<String, FileLines>,
+    pub indexed_commits: HashMap<String, String>,
+    pub commits: HashMap<String, CommitInfo>,
+    pub failed_files: BTreeMap<String, String>,
+    pub path_resolver: Option<(String, PathResolver)>,
+}
+
+impl IndexStore for MemoryStore {
+    fn get_file_lines(&self, file_path: &str) -> Result<Option<FileLines>, StoreError> {
+        Ok(self.files.get(file_path).cloned())
+    }
+
+    fn put_file_lines(
+        &mut self,
+        file_path: &str,
+        lines: &FileLines,
+        indexed_commit: Option<&str>,
+    ) -> Result<(), StoreError> {
+        self.files.insert(file_path.to_string(), lines.clone());
+        self.failed_files.remove(file_path);
+        if let Some(indexed_commit) = indexed_commit {
+            self.set_indexed_commit(file_path, indexed_commit)?;
+        }
+        Ok(())
+    }
+
+    fn last_indexed_commit(&self, file_path: &str) -> Result<Option<String>, StoreError> {
+        Ok(self.indexed_commits.get(file_path).cloned())
+    }
+
+    fn set_indexed_commit(&mut self, file_path: &str, commit_hash: &str) -> Result<(), StoreError> {
+        self.indexed_commits
+            .insert(file_path.to_string(), commit_hash.to_string());
+        Ok(())
+    }
+
+    fn list_files(&self) -> Result<Vec<String>, StoreError> {
+        let mut file_paths: Vec<String> = self.files.keys().cloned().collect();
+        file_paths.sort();
+        Ok(file_paths)
+    }
+
+    fn remove_file(&mut self, file_path: &str) -> Result<(), StoreError> {
+        self.files.remove(file_path);
+        self.indexed_commits.remove(file_path);
+        self.failed_files.remove(file_path);
+        Ok(())
+    }
+
+    fn get_commits(
+        &self,
+        commit_hashes: &[String],
+    ) -> Result<HashMap<String, CommitInfo>, StoreError> {
+        Ok(commit_hashes
+            .iter()
+            .filter_map(|commit_hash| {
+                let commit_info = self.commits.get(commit_hash)?;
+                Some((commit_hash.clone(), commit_info.clone()))
+            })
+            .collect())
+    }
+
+    fn put_commits(&mut self, commits: &HashMap<String, CommitInfo>) -> Result<(), StoreError> {
+        self.commits.extend(commits.clone());
+        Ok(())
+    }
+
+    fn mark_failed(&mut self, file_path: &str, reason: &str) -> Result<(), StoreError> {
+        self.failed_files
+            .insert(file_path.to_string(), reason.to_string());
+        Ok(())
+    }
+
+    fn failed_files(&self) -> Result<BTreeMap<String, String>, StoreError> {
+        Ok(self.failed_files.clone())
+    }
+
+    fn get_path_resolver(&self) -> Result<Option<(String, PathResolver)>, StoreError> {
+        Ok(self.path_resolver.clone())
+    }
+
+    fn put_path_resolver(
+        &mut self,
+        commit_hash: &str,
+        path_resolver: &PathResolver,
+    ) -> Result<(), StoreError> {
+        self.path_resolver = Some((commit_hash.to_string(), path_resolver.clone()));
+        Ok(())
+    }
+}
@@ -0,0 +1,368 @@
+// The index as plain JSON files in the DB folder, the layout of older versions:
+// - <index>.json: a shard, the lines of each file keyed by the file path
+// - mapping.json: file path -> indices of its shards
+// - indexing_metadata.json: file path -> commits the file was indexed at, the last one is the latest
+// - commits.json: commit hash -> author details
+//
+// Files are replaced atomically, and writers hold the `.lock` of the folder.
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::contextgpt_structs::CommitInfo;
+use crate::fs_utils::{LOCK_TIMEOUT, lock_folder, write_atomic};
+use crate::index_store::{FileLines, IndexStore, StoreError};
+
+const MAPPING_FILE_NAME: &str = "mapping.json";
+const INDEXING_FILE_NAME: &str = "indexing_metadata.json";
+const COMMITS_FILE_NAME: &str = "commits.json";
+
+type MappingDBType = HashMap<String, Vec<u32>>;
+type IndexingDBType = HashMap<String, Vec<String>>;
+type CommitsDBType = HashMap<String, CommitInfo>;
+
+#[derive(Default, Debug, Serialize, Deserialize)]
+struct Shard {
+    files: HashMap<String, FileLines>,
+}
+
+// Everything a JSON index holds, see JsonStore::read_all.
+#[derive(Default, Debug)]
+pub struct JsonIndex {
+    pub files: HashMap<String, FileLines>,
+    pub indexed_commits: IndexingDBType,
+    pub commits: CommitsDBType,
+    // All the files of the layout that exist, including unreadable ones.
+    pub paths: Vec<PathBuf>,
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct JsonStore {
+    folder_path: PathBuf,
+}
+
+impl JsonStore {
+    pub fn new(folder_path: &Path) -> JsonStore {
+        JsonStore {
+            folder_path: folder_path.to_path_buf(),
+        }
+    }
+
+    fn shard_path(&self, index: u32) -> PathBuf {
+        self.folder_path.join(format!("{}.json", index))
+    }
+
+    // Indices of all the shards in the folder.
+    fn shard_indices(&self) -> Vec<u32> {
+        let Ok(entries) = std::fs::read_dir(&self.folder_path) else {
+            return vec![];
+        };
+        let mut indices: Vec<u32> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                file_name.strip_suffix(".json")?.parse::<u32>().ok()
+            })
+            .collect();
+        indices.sort();
+        indices
+    }
+
+    pub fn exists(&self) -> bool {
+        !self.shard_indices().is_empty()
+            || [MAPPING_FILE_NAME, INDEXING_FILE_NAME, COMMITS_FILE_NAME]
+                .iter()
+                .any(|file_name| self.folder_path.join(file_name).exists())
+    }
+
+    // Serializes writes to the folder across processes, e.g. two editors indexing files on
+    // save. Released when the returned file is dropped.
+    fn lock(&self) -> Result<File, StoreError> {
+        Ok(lock_folder(&self.folder_path, LOCK_TIMEOUT)?)
+    }
+
+    // The mapping, rebuilt from the shards when it's corrupt (e.g. from a crash halfway
+    // through writing it).
+    fn read_mapping(&self) -> MappingDBType {
+        read_json::<MappingDBType>(&self.folder_path.join(MAPPING_FILE_NAME)).unwrap_or_else(|e| {
+            eprintln!("Rebuilding the mapping file ({})", e);
+            self.rebuild_mapping()
+        })
+    }
+
+    // Maps every file back to the shards holding its lines. Shards from older versions don't
+    // say which file they belong to, those are left out.
+    fn rebuild_mapping(&self) -> MappingDBType {
+        let mut mapping_data: MappingDBType = HashMap::new();
+        for index in self.shard_indices() {
+            let Ok(shard) = read_json::<Shard>(&self.shard_path(index)) else {
+                continue;
+            };
+            for file_path in shard.files.into_keys() {
+                mapping_data.entry(file_path).or_default().push(index);
+            }
+        }
+        mapping_data
+    }
+
+    // Lines of a file across its shards, later shards win.
+    fn read_file_lines(&self, mapping_data: &MappingDBType, file_path: &str) -> Option<FileLines> {
+        let indices = mapping_data.get(file_path)?;
+        let mut lines = FileLines::new();
+        for index in indices {
+            let shard_path = self.shard_path(*index);
+            let data = std::fs::read_to_string(&shard_path).unwrap_or_default();
+            if let Ok(mut shard) = serde_json::from_str::<Shard>(&data) {
+                lines.extend(shard.files.remove(file_path).unwrap_or_default());
+            } else {
+                // Unkeyed, see JsonStore::key_shards_by_file.
+                eprintln!(
+                    "Skipping shard {} from an older version",
+                    shard_path.display()
+                );
+            }
+        }
+        Some(lines)
+    }
+
+    // Shards written before they were keyed by file only hold the lines.
+    pub fn has_unkeyed_shards(&self) -> bool {
+        self.shard_indices().into_iter().any(|index| {
+            let data = std::fs::read_to_string(self.shard_path(index)).unwrap_or_default();
+            serde_json::from_str::<Shard>(&data).is_err()
+                && serde_json::from_str::<FileLines>(&data).is_ok()
+        })
+    }
+
+    // Rewrites the shards that only hold the lines with the file they belong to, according
+    // to the mapping. Fails when a shard is claimed by several files, its lines can't be told
+    // apart. Call with the lock held.
+    pub fn key_shards_by_file(&self) -> Result<(), StoreError> {
+        let mapping_data = self.read_mapping();
+        for index in self.shard_indices() {
+            let shard_path = self.shard_path(index);
+            let data = std::fs::read_to_string(&shard_path).unwrap_or_default();
+            if serde_json::from_str::<Shard>(&data).is_ok() {
+                continue;
+            }
+            let Ok(lines) = serde_json::from_str::<FileLines>(&data) else {
+                continue;
+            };
+            let owners: Vec<&String> = mapping_data
+                .iter()
+                .filter(|(_, indices)| indices.contains(&index))
+                .map(|(file_path, _)| file_path)
+                .collect();
+            // Nobody reads a shard without an owner anyway.
+            let owner = match owners.as_slice() {
+                [] => continue,
+                [owner] => owner.to_string(),
+                _ => {
+                    return Err(StoreError::ReindexRequired(format!(
+                        "{} is claimed by {} files",
+                        shard_path.display(),
+                        owners.len()
+                    )));
+                }
+            };
+            let shard = Shard {
+                files: HashMap::from([(owner, lines)]),
+            };
+            self.write_json(&format!("{}.json", index), &shard)?;
+        }
+        Ok(())
+    }
+
+    // Everything in the index, e.g. to move it to another store. Call with the lock held.
+    pub fn read_all(&self) -> JsonIndex {
+        let mapping_data = self.read_mapping();
+        let mut json_index = JsonIndex::default();
+        for file_path in mapping_data.keys() {
+            if let Some(lines) = self.read_file_lines(&mapping_data, file_path)
+                && !lines.is_empty()
+            {
+                json_index.files.insert(file_path.clone(), lines);
+            }
+        }
+        json_index.indexed_commits = self.read_indexing_metadata();
+        json_index.commits = self.read_commits();
+        json_index.paths = self.layout_paths();
+        json_index
+    }
+
+    // All the files of the layout that exist, including unreadable ones.
+    pub fn layout_paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self
+            .shard_indices()
+            .into_iter()
+            .map(|index| self.shard_path(index))
+            .collect();
+        for file_name in [MAPPING_FILE_NAME, INDEXING_FILE_NAME, COMMITS_FILE_NAME] {
+            let path = self.folder_path.join(file_name);
+            if path.exists() {
+                paths.push(path);
+            }
+        }
+        paths
+    }
+
+    // A corrupt file only means that the files get indexed again.
+    fn read_indexing_metadata(&self) -> IndexingDBType {
+        read_json(&self.folder_path.join(INDEXING_FILE_NAME)).unwrap_or_else(|e| {
+            eprintln!(
+                "Unable to read the indexing metadata ({}), starting over",
+                e
+            );
+            HashMap::new()
+        })
+    }
+
+    fn read_commits(&self) -> CommitsDBType {
+        read_json(&self.folder_path.join(COMMITS_FILE_NAME)).unwrap_or_else(|e| {
+            eprintln!("Unable to read the commits ({}), starting over", e);
+            HashMap::new()
+        })
+    }
+
+    fn write_json<T: Serialize>(&self, file_name: &str, value: &T) -> Result<(), StoreError> {
+        let data = serde_json::to_string(value)?;
+        write_atomic(&self.folder_path.join(file_name), data.as_bytes())?;
+        Ok(())
+    }
+
+    // Deletes the shards only the given file uses, and its mapping entry.
+    fn delete_shards(&self, mapping_data: &mut MappingDBType, file_path: &str) {
+        let Some(indices) = mapping_data.remove(file_path) else {
+            return;
+        };
+        for index in indices {
+            if mapping_data.values().any(|other| other.contains(&index)) {
+                continue;
+            }
+            let shard_path = self.shard_path(index);
+            if let Err(e) = std::fs::remove_file(&shard_path)
+                && e.kind() != std::io::ErrorKind::NotFound
+            {
+                eprintln!("Failed to delete shard {}: {}", shard_path.display(), e);
+            }
+        }
+    }
+}
+
+// Missing and empty files are empty maps.
+fn read_json<T: DeserializeOwned + Default>(path: &Path) -> Result<T, StoreError> {
+    match std::fs::read_to_string(path) {
+        Ok(data) if data.trim().is_empty() => Ok(T::default()),
+        Ok(data) => Ok(serde_json::from_str(&data)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+impl IndexStore for JsonStore {
+    fn get_file_lines(&self, file_path: &str) -> Result<Option<FileLines>, StoreError> {
+        let mapping_data = self.read_mapping();
+        Ok(self.read_file_lines(&mapping_data, file_path))
+    }
+
+    fn put_file_lines(
+        &mut self,
+        file_path: &str,
+        lines: &FileLines,
+        indexed_commit: Option<&str>,
+    ) -> Result<(), StoreError> {
+        let _lock = self.lock()?;
+        // Other processes may have stored files since the mapping was last read.
+        let mut mapping_data = self.read_mapping();
+        self.delete_shards(&mut mapping_data, file_path);
+        let index = self
+            .shard_indices()
+            .into_iter()
+            .chain(mapping_data.values().flatten().copied())
+            .max()
+            .map_or(0, |index| index + 1);
+        let shard = Shard {
+            files: HashMap::from([(file_path.to_string(), lines.clone())]),
+        };
+        self.write_json(&format!("{}.json", index), &shard)?;
+        mapping_data.insert(file_path.to_string(), vec![index]);
+        self.write_json(MAPPING_FILE_NAME, &mapping_data)?;
+        if let Some(indexed_commit) = indexed_commit {
+            let mut indexing_metadata = self.read_indexing_metadata();
+            indexing_metadata
+                .entry(file_path.to_string())
+                .or_default()
+                .push(indexed_commit.to_string());
+            self.write_json(INDEXING_FILE_NAME, &indexing_metadata)?;
+        }
+        Ok(())
+    }
+
+    fn last_indexed_commit(&self, file_path: &str) -> Result<Option<String>, StoreError> {
+        Ok(self
+            .read_indexing_metadata()
+            .get(file_path)
+            .and_then(|commits| commits.last().cloned()))
+    }
+
+    fn set_indexed_commit(&mut self, file_path: &str, commit_hash: &str) -> Result<(), StoreError> {
+        let _lock = self.lock()?;
+        let mut indexing_metadata = self.read_indexing_metadata();
+        indexing_metadata
+            .entry(file_path.to_string())
+            .or_default()
+            .push(commit_hash.to_string());
+        self.write_json(INDEXING_FILE_NAME, &indexing_metadata)
+    }
+
+    fn list_files(&self) -> Result<Vec<String>, StoreError> {
+        let mut file_paths: Vec<String> = self.read_mapping().into_keys().collect();
+        file_paths.sort();
+        Ok(file_paths)
+    }
+
+    fn remove_file(&mut self, file_path: &str) -> Result<(), StoreError> {
+        let _lock = self.lock()?;
+        let mut mapping_data = self.read_mapping();
+        self.delete_shards(&mut mapping_data, file_path);
+        self.write_json(MAPPING_FILE_NAME, &mapping_data)?;
+        let mut indexing_metadata = self.read_indexing_metadata();
+        if indexing_metadata.remove(file_path).is_some() {
+            self.write_json(INDEXING_FILE_NAME, &indexing_metadata)?;
+        }
+        Ok(())
+    }
+
+    fn get_commits(
+        &self,
+        commit_hashes: &[String],
+    ) -> Result<HashMap<String, CommitInfo>, StoreError> {
+        let mut commits = self.read_commits();
+        Ok(commit_hashes
+            .iter()
+            .filter_map(|commit_hash| Some((commit_hash.clone(), commits.remove(commit_hash)?)))
+            .collect())
+    }
+
+    fn put_commits(&mut self, commits: &HashMap<String, CommitInfo>) -> Result<(), StoreError> {
+        if commits.is_empty() {
+            return Ok(());
+        }
+        let _lock = self.lock()?;
+        let mut stored = self.read_commits();
+        stored.extend(commits.clone());
+        self.write_json(COMMITS_FILE_NAME, &stored)
+    }
+
+    // The layout predates failure tracking, it's only read to be upgraded.
+    fn mark_failed(&mut self, _: &str, _: &str) -> Result<(), StoreError> {
+        Ok(())
+    }
+
+    fn failed_files(&self) -> Result<BTreeMap<String, String>, StoreError> {
+        Ok(BTreeMap::new())
+    }
+}
@@ -1,8 +1,19 @@
-mod authordetails_impl;
 mod config;
+pub mod config_impl;
 pub mod contextgpt_structs;
 pub mod db;
 pub mod diff_v2;
+pub mod embedded_store;
+pub mod fs_utils;
+pub mod git_backend;
 pub mod git_command_algo;
+pub mod index_store;
+pub mod json_store;
+pub mod migrations;
+pub mod output;
+pub mod rpc;
+pub mod scoring;
+pub mod status;
 pub mod utils;
 pub mod algo_loc;
+pub mod archive;
@@ -0,0 +1,367 @@
+// Language Server Protocol front-end: `contextpilot <workspace> -t lsp`.
+//
+// Lets any LSP capable editor use contextpilot without a dedicated plugin:
+// - textDocument/hover: the commits that touched the hovered line.
+// - textDocument/codeLens: a lens above each hunk (lines last changed by the same commit)
+//   with its top related files.
+// - contextpilot/relatedFiles { textDocument, range, limit?, offset? }: the related files for
+//   a range, same records as `-t query --format json`. limit defaults to file_count_threshold
+//   (0 for all).
+//
+// Everything is answered from the index, so the workspace should be indexed first.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::sync::Mutex;
+
+use crate::Server;
+use crate::config_impl::{self, Config};
+use crate::contextgpt_structs::{CommitDescription, RelatedFile};
+use crate::db::DB;
+use crate::git_backend::backend;
+use crate::git_command_algo::get_repo_root;
+use crate::output::short_hash;
+use crate::rpc::{self, Request, Response, RpcError};
+
+// Number of related files shown in a code lens title.
+const LENS_FILE_COUNT: usize = 3;
+
+#[derive(Deserialize)]
+struct TextDocumentIdentifier {
+    uri: String,
+}
+
+#[derive(Deserialize)]
+struct Position {
+    line: usize,
+    #[serde(default)]
+    character: usize,
+}
+
+#[derive(Deserialize)]
+struct Range {
+    start: Position,
+    end: Position,
+}
+
+impl Range {
+    // First and last line, starting at 1. The end is exclusive, a selection that ends at the
+    // start of a line doesn't cover that line.
+    fn line_numbers(&self) -> (usize, usize) {
+        let end_line = if self.end.character == 0 && self.end.line > self.start.line {
+            self.end.line - 1
+        } else {
+            self.end.line
+        };
+        (self.start.line + 1, end_line + 1)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TextDocumentParams {
+    text_document: TextDocumentIdentifier,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TextDocumentPositionParams {
+    text_document: TextDocumentIdentifier,
+    position: Position,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TextDocumentRangeParams {
+    text_document: TextDocumentIdentifier,
+    range: Range,
+    #[serde(default)]
+    limit: Option<usize>,
+    #[serde(default)]
+    offset: usize,
+}
+
+// Converts a `file://` URI to a path, decoding percent-encoded bytes.
+fn uri_to_path(uri: &str) -> Result<String, RpcError> {
+    let Some(encoded) = uri.strip_prefix("file://") else {
+        return Err(RpcError::invalid_params(format!(
+            "Only file:// URIs are supported, got: {}",
+            uri
+        )));
+    };
+    let bytes = encoded.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let escaped = bytes
+            .get(idx + 1..idx + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        if bytes[idx] == b'%'
+            && let Some(byte) = escaped
+        {
+            decoded.push(byte);
+            idx += 3;
+            continue;
+        }
+        decoded.push(bytes[idx]);
+        idx += 1;
+    }
+    let path = String::from_utf8(decoded)
+        .map_err(|e| RpcError::invalid_params(format!("Invalid URI {}: {}", uri, e)))?;
+    // Windows URIs look like file:///C:/path.
+    if cfg!(target_os = "windows") {
+        return Ok(path.trim_start_matches('/').to_string());
+    }
+    Ok(path)
+}
+
+fn hover_markdown(descriptions: &[CommitDescription]) -> String {
+    descriptions
+        .iter()
+        .map(|desc| {
+            let hash = if desc.url.is_empty() {
+                format!("`{}`", short_hash(&desc.hash))
+            } else {
+                format!("[`{}`]({})", short_hash(&desc.hash), desc.url)
+            };
+            format!("- {} {} — {}, {}", hash, desc.title, desc.author, desc.date)
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// Query results are already ranked, only the file itself has to go.
+fn top_related_files(mut related_files: Vec<RelatedFile>, own_path: &str) -> Vec<RelatedFile> {
+    related_files.retain(|file| file.path != own_path);
+    related_files
+}
+
+struct LanguageServer {
+    workspace_path: String,
+    // Top of the repository, query results are relative to it.
+    repo_root: PathBuf,
+    config: Config,
+    loaded: HashMap<String, Arc<Mutex<DB>>>,
+}
+
+impl LanguageServer {
+    fn resolve_file(&self, uri: &str) -> Result<String, RpcError> {
+        let path = PathBuf::from(uri_to_path(uri)?);
+        let path = path
+            .canonicalize()
+            .map_err(|e| RpcError::invalid_params(format!("Invalid file {}: {}", uri, e)))?;
+        Ok(path.to_string_lossy().to_string())
+    }
+
+    // Path of the file relative to the top of the repository, as reported by git.
+    fn relative_path(&self, file_path: &str) -> String {
+        Path::new(file_path)
+            .strip_prefix(&self.repo_root)
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or(file_path.to_string())
+    }
+
+    // Lets other processes at the index until the next request.
+    async fn close_stores(&self) {
+        for db in self.loaded.values() {
+            db.lock().await.store.close();
+        }
+    }
+
+    async fn load_db(&mut self, file_path: &str) -> Arc<Mutex<DB>> {
+        if let Some(db) = self.loaded.get(file_path) {
+            return db.clone();
+        }
+        let db = Server::open_db(&self.workspace_path, file_path, &self.config).await;
+        self.loaded.insert(file_path.to_string(), db.clone());
+        db
+    }
+
+    async fn related_files(
+        &mut self,
+        file_path: String,
+        start_number: usize,
+        end_number: usize,
+    ) -> Result<Vec<RelatedFile>, RpcError> {
+        let db = self.load_db(&file_path).await;
+        tokio::spawn(async move {
+            db.lock()
+                .await
+                .query(file_path, start_number, end_number)
+                .await
+        })
+        .await
+        .map_err(|e| RpcError::internal(format!("Query failed: {}", e)))
+    }
+
+    async fn hover(&mut self, params: Value) -> Result<Value, RpcError> {
+        let params: TextDocumentPositionParams = rpc::parse_params(params)?;
+        let file_path = self.resolve_file(&params.text_document.uri)?;
+        let line_number = params.position.line + 1;
+        let db = self.load_db(&file_path).await;
+        let descriptions = tokio::spawn(async move {
+            db.lock()
+                .await
+                .query_descriptions(file_path, line_number, line_number)
+                .await
+        })
+        .await
+        .map_err(|e| RpcError::internal(format!("Hover failed: {}", e)))?;
+        if descriptions.is_empty() {
+            return Ok(Value::Null);
+        }
+        Ok(json!({
+            "contents": { "kind": "markdown", "value": hover_markdown(&descriptions) },
+        }))
+    }
+
+    async fn code_lens(&mut self, params: Value) -> Result<Value, RpcError> {
+        let params: TextDocumentParams = rpc::parse_params(params)?;
+        let file_path = self.resolve_file(&params.text_document.uri)?;
+        let own_path = self.relative_path(&file_path);
+        let db = self.load_db(&file_path).await;
+        let hunks = tokio::spawn(async move { db.lock().await.query_hunks(&file_path).await })
+            .await
+            .map_err(|e| RpcError::internal(format!("Query failed: {}", e)))?;
+        let mut lenses: Vec<Value> = vec![];
+        for ((start_number, end_number), related_files) in hunks {
+            let related_files = config_impl::page_results(
+                top_related_files(related_files, &own_path),
+                0,
+                self.config.file_count_threshold,
+            );
+            if related_files.is_empty() {
+                continue;
+            }
+            let mut title = related_files
+                .iter()
+                .take(LENS_FILE_COUNT)
+                .map(|file| file.path.clone())
+                .collect::<Vec<String>>()
+                .join(", ");
+            if related_files.len() > LENS_FILE_COUNT {
+                title = format!("{} (+{})", title, related_files.len() - LENS_FILE_COUNT);
+            }
+            let range = json!({
+                "start": { "line": start_number - 1, "character": 0 },
+                "end": { "line": end_number - 1, "character": 0 },
+            });
+            lenses.push(json!({
+                "range": range,
+                "command": {
+                    "title": format!("Related: {}", title),
+                    "command": "contextpilot.relatedFiles",
+                    "arguments": [params.text_document.uri, range],
+                },
+            }));
+        }
+        Ok(Value::Array(lenses))
+    }
+
+    async fn dispatch(&mut self, request: &Request) -> Result<Value, RpcError> {
+        match request.method.as_str() {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": { "openClose": false, "change": 0, "save": true },
+                    "hoverProvider": true,
+                    "codeLensProvider": { "resolveProvider": false },
+                },
+                "serverInfo": { "name": "contextpilot", "version": env!("CARGO_PKG_VERSION") },
+            })),
+            "textDocument/hover" => self.hover(request.params.clone()).await,
+            "textDocument/codeLens" => self.code_lens(request.params.clone()).await,
+            "contextpilot/relatedFiles" => {
+                let params: TextDocumentRangeParams = rpc::parse_params(request.params.clone())?;
+                let file_path = self.resolve_file(&params.text_document.uri)?;
+                let (start_number, end_number) = params.range.line_numbers();
+                let related_files = self
+                    .related_files(file_path, start_number, end_number)
+                    .await?;
+                let limit = params.limit.unwrap_or(self.config.file_count_threshold);
+                Ok(serde_json::to_value(config_impl::page_results(
+                    related_files,
+                    params.offset,
+                    limit,
+                ))
+                .unwrap())
+            }
+            "shutdown" => Ok(Value::Null),
+            method => Err(RpcError::method_not_found(method)),
+        }
+    }
+
+    fn handle_notification(&mut self, request: &Request) {
+        if request.method == "textDocument/didSave" {
+            // The file may have been re-indexed by a save hook, reload it next time.
+            if let Ok(params) = rpc::parse_params::<TextDocumentParams>(request.params.clone())
+                && let Ok(file_path) = self.resolve_file(&params.text_document.uri)
+            {
+                self.loaded.remove(&file_path);
+            }
+        }
+        // Everything else (initialized, didOpen, $/cancelRequest, ...) is ignored.
+    }
+}
+
+pub async fn run(workspace_path: String, config: Config) {
+    let workspace_path = std::fs::canonicalize(&workspace_path)
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or(workspace_path);
+    let repo_root =
+        get_repo_root(backend(config.git_backend)).unwrap_or(PathBuf::from(&workspace_path));
+    let mut server = LanguageServer {
+        workspace_path,
+        repo_root,
+        config,
+        loaded: HashMap::new(),
+    };
+    let mut reader = BufReader::new(tokio::io::stdin());
+    let mut stdout = tokio::io::stdout();
+    loop {
+        let message = match rpc::read_framed_message(&mut reader).await {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
+            Err(e) => {
+                eprintln!("Failed reading LSP message: {}", e);
+                break;
+            }
+        };
+        let request = match rpc::parse_request(&message) {
+            Ok(request) => request,
+            Err(response) => {
+                let response = serde_json::to_string(&response).unwrap();
+                let _ = stdout
+                    .write_all(rpc::frame_message(&response).as_bytes())
+                    .await;
+                let _ = stdout.flush().await;
+                continue;
+            }
+        };
+        if request.method == "exit" {
+            break;
+        }
+        let Some(id) = request.id.clone() else {
+            server.handle_notification(&request);
+            continue;
+        };
+        let response = match server.dispatch(&request).await {
+            Ok(result) => Response::success(id, result),
+            Err(error) => Response::failure(id, error),
+        };
+        server.close_stores().await;
+        let response = serde_json::to_string(&response).unwrap();
+        if stdout
+            .write_all(rpc::frame_message(&response).as_bytes())
+            .await
+            .is_err()
+            || stdout.flush().await.is_err()
+        {
+            break;
+        }
+    }
+}
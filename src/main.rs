@@ -1,16 +1,33 @@
 mod algo_loc;
-mod authordetails_impl;
+mod archive;
 mod config;
 mod config_impl;
 mod contextgpt_structs;
+mod daemon;
 mod db;
 mod diff_v2;
+mod embedded_store;
+mod fs_utils;
+mod git_backend;
 mod git_command_algo;
+mod index_store;
+mod json_store;
+mod lsp;
+mod migrations;
+mod output;
+mod rpc;
+mod scoring;
+mod status;
 
 use crate::{algo_loc::perform_for_whole_file, db::DB};
 use async_recursion::async_recursion;
-use contextgpt_structs::{AuthorDetailsV2, Cli, RequestTypeOptions};
-use git_command_algo::print_all_valid_files;
+use contextgpt_structs::{
+    AuthorDetailsV2, AuthorSummary, Cli, CommitDescription, RelatedFile, RequestTypeOptions,
+};
+use embedded_store::EmbeddedStore;
+use git_backend::{GitBackend, backend};
+use git_command_algo::get_latest_commit;
+use git_command_algo::{extract_repository_details, get_latest_commits};
 use std::collections::HashMap;
 use std::fs::metadata;
 use std::{
@@ -19,8 +36,11 @@ use std::{
 };
 use structopt::StructOpt;
 use tokio::sync::Mutex;
+use tokio::sync::mpsc::UnboundedSender;
 
 use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use config_impl::{Config, ConfigLayer, IndexingMode, PathFilter};
+use output::OutputFormat;
 use quicli::prelude::{
     log::{log, Level},
     CliResult,
@@ -108,6 +128,12 @@ pub struct Server {
     state: State,
     curr_db: Option<Arc<Mutex<DB>>>,
     state_db_handler: DBHandler,
+    output_format: OutputFormat,
+    // Receives the path of every file once it's stored in the DB (used by the daemon).
+    progress: Option<UnboundedSender<String>>,
+    config: Config,
+    // Number of related files to skip in query results.
+    offset: usize,
 }
 
 #[derive(Default, Debug, Clone)]
@@ -128,6 +154,10 @@ impl Server {
             state,
             curr_db: None,
             state_db_handler: db_handler,
+            output_format: OutputFormat::default(),
+            progress: None,
+            config: Config::default(),
+            offset: 0,
         }
     }
 
@@ -135,6 +165,18 @@ impl Server {
         self.curr_db = Some(curr_db);
     }
 
+    // Where the history is read from, the git_backend config picks it.
+    fn git(&self) -> &'static dyn GitBackend {
+        backend(self.config.git_backend)
+    }
+
+    fn report_progress(&self, file_path: &str) {
+        if let Some(progress) = &self.progress {
+            // The receiver going away only means nobody is listening anymore.
+            let _ = progress.send(file_path.to_string());
+        }
+    }
+
     fn _is_valid_file(file: &Path) -> bool {
         if file.exists() && file.is_file() {
             // not optimising one liners here for debugging later on
@@ -147,12 +189,53 @@ impl Server {
     async fn _index_file(
         file_path_inp: PathBuf,
         workspace_path: String,
+        config: Config,
     ) -> HashMap<u32, AuthorDetailsV2> {
         // Don't make it write to the DB, write it atomically later.
         // For now, just store the output somewhere in the DB.
         let file_path = std::fs::canonicalize(file_path_inp).expect("Failed");
         let file_path_str = file_path.to_str().unwrap();
-        perform_for_whole_file(file_path_str.to_string(), true, None, Some(workspace_path)).await
+        perform_for_whole_file(
+            file_path_str.to_string(),
+            true,
+            None,
+            Some(workspace_path),
+            &config,
+        )
+        .await
+    }
+
+    // Replays the commits since an indexed file was indexed on its stored lines. False when
+    // the file isn't indexed, it's indexed from scratch then.
+    async fn _catch_up_file(file_path_inp: &Path, curr_db: Option<Arc<Mutex<DB>>>) -> bool {
+        let (Some(db), Ok(file_path)) = (curr_db, std::fs::canonicalize(file_path_inp)) else {
+            return false;
+        };
+        let file_path_str = file_path.to_string_lossy().to_string();
+        let mut db_locked = db.lock().await;
+        if !db_locked.is_indexed(&file_path_str) {
+            return false;
+        }
+        db_locked.catch_up(&file_path_str).await;
+        true
+    }
+
+    // A file with history that still has no lines in the index after indexing couldn't be
+    // indexed. Files without history (untracked) or content are skipped, not failures.
+    async fn check_indexed(&self, file_path: &Path) {
+        let Ok(file_path) = std::fs::canonicalize(file_path) else {
+            return;
+        };
+        if !metadata(&file_path).is_ok_and(|file_metadata| file_metadata.len() > 0) {
+            return;
+        }
+        let file_path = file_path.to_string_lossy().to_string();
+        let db = self.curr_db.clone().unwrap();
+        let mut db_locked = db.lock().await;
+        if !db_locked.is_indexed(&file_path) && get_latest_commit(self.git(), &file_path).is_some()
+        {
+            db_locked.mark_failed(&file_path, "no lines could be traced in its history");
+        }
     }
 
     #[async_recursion]
@@ -160,8 +243,11 @@ impl Server {
         &mut self,
         workspace_path: PathBuf,
         gitignore_builder_obj: Option<Gitignore>,
+        path_filter: PathFilter,
     ) -> HashMap<u32, AuthorDetailsV2> {
-        let mut files_set: task::JoinSet<HashMap<u32, AuthorDetailsV2>> = task::JoinSet::new();
+        // Files come back with their path, to tell the ones that failed.
+        let mut files_set: task::JoinSet<(Option<PathBuf>, HashMap<u32, AuthorDetailsV2>)> =
+            task::JoinSet::new();
         let path = Path::new(&workspace_path);
         let mut final_authordetails: HashMap<u32, AuthorDetailsV2> = HashMap::new();
         if path.is_dir() {
@@ -187,41 +273,66 @@ impl Server {
                         continue;
                     }
                 }
+                // Same for the include/exclude globs from the config.
+                if !path_filter.is_included(Path::new(&entry_path_stripped), entry_path_path.is_dir())
+                {
+                    continue;
+                }
                 if entry_path_path.is_dir() {
                     files_set.spawn({
                         let entry_path_clone = entry_path_path.clone();
                         let state_db_handler_clone = self.state_db_handler.clone();
                         let curr_db_clone = curr_db.clone();
                         let gitignore_obj_cloned = gitignore_builder_obj.clone();
+                        let progress_clone = self.progress.clone();
+                        let config_clone = self.config.clone();
+                        let path_filter_clone = path_filter.clone();
                         async move {
                             let mut server = Server {
                                 state: State::Running,
                                 curr_db: curr_db_clone,
                                 state_db_handler: state_db_handler_clone,
+                                output_format: OutputFormat::default(),
+                                progress: progress_clone,
+                                config: config_clone,
+                                offset: 0,
                             };
 
-                            server
+                            let output = server
                                 ._iterate_through_workspace(
                                     entry_path_clone.clone(),
                                     gitignore_obj_cloned,
+                                    path_filter_clone,
                                 )
-                                .await
+                                .await;
+                            (None, output)
                         }
                     });
                 } else if Server::_is_valid_file(&entry_path_path) {
                     log!(Level::Info, "File is valid: {}", entry_path_path.display());
                     let workspace_path = workspace_path.clone();
                     let w_path = self.state_db_handler.metadata.workspace_path.clone();
+                    let config = self.config.clone();
                     files_set.spawn({
-                        async move { Server::_index_file(entry_path_path.clone(), w_path).await }
+                        async move {
+                            if Server::_catch_up_file(&entry_path_path, curr_db).await {
+                                return (None, HashMap::new());
+                            }
+                            let output =
+                                Server::_index_file(entry_path_path.clone(), w_path, config).await;
+                            (Some(entry_path_path), output)
+                        }
                     });
                 }
             }
 
             while let Some(res) = files_set.join_next().await {
-                let output_authordetails = res.unwrap();
+                let (file_path, output_authordetails) = res.unwrap();
 
                 if output_authordetails.is_empty() {
+                    if let Some(file_path) = file_path {
+                        self.check_indexed(&file_path).await;
+                    }
                     continue;
                 }
 
@@ -254,6 +365,7 @@ impl Server {
                         details_vec_map.clone(),
                     );
                     db_locked.store();
+                    self.report_progress(&origin_file_path);
                     final_authordetails.extend(
                         details_vec
                             .into_iter()
@@ -267,8 +379,11 @@ impl Server {
                 "File is valid but not in a sub-directory: {}",
                 path.display()
             );
+            if Server::_catch_up_file(path, self.curr_db.clone()).await {
+                return final_authordetails;
+            }
             let w_path = self.state_db_handler.metadata.workspace_path.clone();
-            let output = Server::_index_file(path.to_path_buf(), w_path).await;
+            let output = Server::_index_file(path.to_path_buf(), w_path, self.config.clone()).await;
             return output;
         } else {
             log!(Level::Warn, "File is not valid: {}", path.display());
@@ -276,6 +391,89 @@ impl Server {
         final_authordetails
     }
 
+    // The files _iterate_through_workspace indexes under the path: everything .gitignore and
+    // the include/exclude globs don't leave out.
+    fn collect_workspace_files(
+        &self,
+        path: &Path,
+        gitignore: &Option<Gitignore>,
+        path_filter: &PathFilter,
+        files: &mut Vec<PathBuf>,
+    ) {
+        let Ok(entries) = path.read_dir() else {
+            return;
+        };
+        let to_strip = format!("{}{}", self.state_db_handler.metadata.workspace_path, "/");
+        for entry in entries.flatten() {
+            let entry_path = entry.path();
+            let entry_path_str = entry_path.to_string_lossy();
+            let entry_path_stripped = entry_path_str
+                .strip_prefix(to_strip.as_str())
+                .unwrap_or(&entry_path_str);
+            if let Some(gitignore) = gitignore
+                && gitignore.matched(entry_path_stripped, true).is_ignore()
+            {
+                continue;
+            }
+            if !path_filter.is_included(Path::new(entry_path_stripped), entry_path.is_dir()) {
+                continue;
+            }
+            if entry_path.is_dir() {
+                self.collect_workspace_files(&entry_path, gitignore, path_filter, files);
+            } else if Server::_is_valid_file(&entry_path) {
+                files.push(entry_path);
+            }
+        }
+    }
+
+    // Indexes the same files as _iterate_through_workspace, from a single walk of the history
+    // of the repository. Files that are indexed at their latest commit are left alone.
+    async fn index_repository(
+        &self,
+        paths: &[PathBuf],
+        gitignore: &Option<Gitignore>,
+        path_filter: &PathFilter,
+    ) {
+        let mut workspace_files = vec![];
+        for path in paths {
+            self.collect_workspace_files(path, gitignore, path_filter, &mut workspace_files);
+        }
+        let latest_commits = get_latest_commits(self.git());
+        let db = self.curr_db.clone().unwrap();
+        let db_locked = db.lock().await;
+        let files_to_index: Vec<String> = workspace_files
+            .into_iter()
+            .filter_map(|file_path| std::fs::canonicalize(file_path).ok())
+            .map(|file_path| file_path.to_string_lossy().to_string())
+            .filter(|file_path| {
+                // Untracked files have no history to replay.
+                latest_commits.get(file_path).is_some_and(|latest_commit| {
+                    db_locked.last_indexed_commit(file_path).as_ref() != Some(latest_commit)
+                })
+            })
+            .collect();
+        drop(db_locked);
+        if files_to_index.is_empty() {
+            eprintln!("All files are already indexed at their latest commit");
+            return;
+        }
+        eprintln!("Indexing {} files in one walk of the history", files_to_index.len());
+        let mut all_details =
+            extract_repository_details(self.git(), &files_to_index, self.config.merge_policy);
+        for file_path in files_to_index {
+            let details = all_details.remove(&file_path).unwrap_or_default();
+            if details.is_empty() {
+                self.check_indexed(Path::new(&file_path)).await;
+                continue;
+            }
+            let mut db_locked = db.lock().await;
+            db_locked.append_to_db(&file_path, 0, details);
+            db_locked.store();
+            drop(db_locked);
+            self.report_progress(&file_path);
+        }
+    }
+
     pub async fn start_file(&mut self, metadata: &mut DBMetadata, file_path: Option<String>) {
         // Only index the given file and do no more than that.
         if file_path.is_none() {
@@ -289,6 +487,7 @@ impl Server {
             let workspace_path = &metadata.workspace_path;
             let db = DB {
                 folder_path: workspace_path.clone(),
+                config: self.config.clone(),
                 ..Default::default()
             };
             let curr_db: Arc<Mutex<DB>> = Arc::new(db.into());
@@ -302,56 +501,23 @@ impl Server {
             let mut server = Server::new(State::Dead, DBHandler::new(metadata.clone()));
             server.init_server(curr_db.clone());
 
-            // Check if the file already exists in the DB
-            let mut db_locked = curr_db.lock().await;
-            let indices = db_locked.find_index(&file_path_str);
-            drop(db_locked);
-
-            // If the file exists, delete all shards and update mapping data
-            if let Some(indices_vec) = indices {
-                log!(Level::Info, "File already exists in DB. Deleting existing shards.");
-                for index in indices_vec {
-                    let shard_path = format!("{}/{}.json", workspace_path, index);
-                    if Path::new(&shard_path).exists() {
-                        if let Err(e) = std::fs::remove_file(&shard_path) {
-                            log!(Level::Error, "Failed to delete shard {}: {}", shard_path, e);
-                        } else {
-                            log!(Level::Info, "Deleted shard: {}", shard_path);
-                        }
-                    }
-                }
-
-                // Update mapping data to remove references to deleted shards
-                let mut db_locked = curr_db.lock().await;
-                // Remove the file path from the mapping data
-                db_locked.mapping_data.remove(&file_path_str);
-
-                // Write the updated mapping data to disk
-                let mapping_file_path = format!("{}/mapping.json", workspace_path);
-                if let Ok(mut file) = std::fs::File::create(&mapping_file_path) {
-                    let mapping_string = serde_json::to_string_pretty(&db_locked.mapping_data)
-                        .expect("Failed to serialize mapping");
-                    if let Err(e) = std::io::Write::write_fmt(&mut file, format_args!("{}", mapping_string)) {
-                        log!(Level::Error, "Failed writing mapping: {}", e);
-                    } else {
-                        log!(Level::Info, "Updated mapping file to remove deleted shards");
-                    }
-                } else {
-                    log!(Level::Error, "Failed to create mapping file: {}", mapping_file_path);
-                }
-                drop(db_locked);
+            // If the file already exists in the DB, only the commits since it was indexed are
+            // replayed
+            if Server::_catch_up_file(file_path_path, Some(curr_db.clone())).await {
+                log!(Level::Info, "File already exists in DB. Caught up with its new commits.");
+                return;
             }
 
             // Index the file
             let w_path = workspace_path.clone();
-            let out = Server::_index_file(file_path_buf.clone(), w_path).await;
+            let out = Server::_index_file(file_path_buf.clone(), w_path, self.config.clone()).await;
 
             if !out.is_empty() {
                 let db = server.curr_db.clone().unwrap();
                 let mut db_locked = db.lock().await;
                 let start_line_number = 0;
 
-                println!(
+                eprintln!(
                     "Indexing file: {} with {} lines",
                     file_path_buf.display(),
                     out.len()
@@ -364,10 +530,12 @@ impl Server {
                 // Store the output to the DB
                 db_locked.append_to_db(origin_file_path, start_line_number, out.clone());
                 db_locked.store();
+                self.report_progress(origin_file_path);
 
                 log!(Level::Info, "Successfully indexed file: {}", file_path_str);
             } else {
                 log!(Level::Warn, "No data to index for file: {}", file_path_str);
+                server.check_indexed(&file_path_buf).await;
             }
         }
     }
@@ -383,9 +551,9 @@ impl Server {
 
         let workspace_path_buf = PathBuf::from(workspace_path);
         // First check if indexing is already done - if yes, just cleanup and restart.
-        // Check if mapping.json exists.
         let db = DB {
             folder_path: workspace_path.clone(),
+            config: self.config.clone(),
             ..Default::default()
         };
         // In case we are attempting to index subfolders - do NOT cleanup
@@ -400,7 +568,14 @@ impl Server {
             .await
             .init_db(workspace_path.as_str(), None, cleanup);
         let mut server = Server::new(State::Dead, DBHandler::new(metadata.clone()));
-        server.init_server(curr_db);
+        server.init_server(curr_db.clone());
+        server.progress = self.progress.clone();
+        server.config = self.config.clone();
+        // Already validated when the config was loaded.
+        let path_filter = self
+            .config
+            .path_filter(workspace_path)
+            .unwrap_or_default();
         // Initialize a gitignore builder:
         let mut gitignore_builder = GitignoreBuilder::new(workspace_path_buf.clone());
         gitignore_builder.add(".gitignore");
@@ -410,24 +585,115 @@ impl Server {
             gitignore_builder_obj = Some(gitignore.unwrap());
         }
 
-        if !self.state_db_handler.metadata.folders_to_index.is_empty() {
+        if self.config.indexing_mode == IndexingMode::Repository {
+            let mut paths = vec![];
+            for subfolder in self.state_db_handler.metadata.folders_to_index.iter() {
+                paths.push(PathBuf::from(format!("{}/{}", workspace_path, subfolder)));
+            }
+            if paths.is_empty() {
+                paths.push(workspace_path_buf.clone());
+            }
+            server
+                .index_repository(&paths, &gitignore_builder_obj, &path_filter)
+                .await;
+        } else if !self.state_db_handler.metadata.folders_to_index.is_empty() {
             // If subfolders are provided - just index them.
             for subfolder in self.state_db_handler.metadata.folders_to_index.iter() {
                 let subfolder_path = PathBuf::from(format!("{}/{}", workspace_path, subfolder));
                 if subfolder_path.exists() {
                     server
-                        ._iterate_through_workspace(subfolder_path, gitignore_builder_obj.clone())
+                        ._iterate_through_workspace(
+                            subfolder_path,
+                            gitignore_builder_obj.clone(),
+                            path_filter.clone(),
+                        )
                         .await;
                 } else {
-                    println!("Subfolder does not exist: {}", subfolder);
+                    eprintln!("Subfolder does not exist: {}", subfolder);
                     log!(Level::Error, "Subfolder does not exist: {}", subfolder);
                 }
             }
         } else {
             let _ = server
-                ._iterate_through_workspace(workspace_path_buf.clone(), gitignore_builder_obj)
+                ._iterate_through_workspace(
+                    workspace_path_buf.clone(),
+                    gitignore_builder_obj,
+                    path_filter,
+                )
                 .await;
         }
+        // Stores the renames in the index, so that queries don't walk the history for them.
+        curr_db.lock().await.load_path_resolver();
+    }
+
+    pub async fn open_db(workspace_path: &str, file_path: &str, config: &Config) -> Arc<Mutex<DB>> {
+        let db = DB {
+            folder_path: workspace_path.to_string().clone(),
+            config: config.clone(),
+            ..Default::default()
+        };
+        let curr_db: Arc<Mutex<DB>> = Arc::new(db.into());
+        curr_db
+            .lock()
+            .await
+            .init_db(workspace_path, Some(file_path), /*cleanup=*/ false);
+        curr_db
+    }
+
+    pub async fn run_query(
+        &mut self,
+        workspace_path: &str,
+        file_path: String,
+        start_number: usize,
+        end_number: usize,
+    ) -> Vec<RelatedFile> {
+        self.init_server(Server::open_db(workspace_path, &file_path, &self.config).await);
+        assert!(self.curr_db.is_some());
+        let related_files = self
+            .curr_db
+            .clone()
+            .unwrap()
+            .lock()
+            .await
+            .query(file_path, start_number, end_number)
+            .await;
+        config_impl::page_results(related_files, self.offset, self.config.file_count_threshold)
+    }
+
+    pub async fn run_descriptions(
+        &mut self,
+        workspace_path: &str,
+        file_path: String,
+        start_number: usize,
+        end_number: usize,
+    ) -> Vec<CommitDescription> {
+        self.init_server(Server::open_db(workspace_path, &file_path, &self.config).await);
+        assert!(self.curr_db.is_some());
+        self.curr_db
+            .clone()
+            .unwrap()
+            .lock()
+            .await
+            .query_descriptions(file_path, start_number, end_number)
+            .await
+    }
+
+    pub async fn run_authors(
+        &mut self,
+        workspace_path: &str,
+        file_path: String,
+        start_number: usize,
+        end_number: usize,
+    ) -> Vec<AuthorSummary> {
+        self.init_server(Server::open_db(workspace_path, &file_path, &self.config).await);
+        assert!(self.curr_db.is_some());
+        self.curr_db
+            .clone()
+            .unwrap()
+            .lock()
+            .await
+            .query_authors(file_path, start_number, end_number)
+            .await
     }
 
     pub async fn handle_server(
@@ -471,64 +737,76 @@ impl Server {
 
         // If this is a call to query and not to index ->
         if request_type.is_some() && request_type.clone().unwrap() == RequestTypeOptions::Query {
-            let db = DB {
-                folder_path: workspace_path.to_string().clone(),
-                ..Default::default()
-            };
-            let curr_db: Arc<Mutex<DB>> = Arc::new(db.into());
-            curr_db.lock().await.init_db(
-                workspace_path,
-                file_path.clone().as_deref(),
-                /*cleanp=*/ false,
-            );
-            // let mut server = Server::new(State::Dead, DBHandler::new(metadata.clone()));
-            self.init_server(curr_db);
-            // Then you query
             // assert!(file_path.is_some());
             assert!(start_number.is_some());
             assert!(end_number.is_some());
-            assert!(self.curr_db.is_some());
-            self.curr_db
-                .clone()
-                .unwrap()
-                .lock()
-                .await
-                .query(
-                    file_path.clone().unwrap(),
+            let related_files = self
+                .run_query(
+                    workspace_path,
+                    file_path.unwrap(),
                     start_number.unwrap(),
                     end_number.unwrap(),
                 )
                 .await;
+            output::print_lines(output::render_related_files(
+                &related_files,
+                self.output_format,
+            ));
+            return;
+        } else if request_type.is_some() && request_type.clone().unwrap() == RequestTypeOptions::File
+        {
+            if file_path.is_none() {
+                log!(Level::Error, "No file path provided to find the related files for.");
+                return;
+            }
+            // Same as a query, over every line of the file.
+            let related_files = self
+                .run_query(workspace_path, file_path.unwrap(), 1, 0)
+                .await;
+            output::print_lines(output::render_related_files(
+                &related_files,
+                self.output_format,
+            ));
+            return;
         } else if request_type.is_some()
-            && request_type.unwrap() == RequestTypeOptions::Descriptions
+            && request_type.clone().unwrap() == RequestTypeOptions::Descriptions
         {
-            let db = DB {
-                folder_path: workspace_path.to_string().clone(),
-                ..Default::default()
-            };
-            let curr_db: Arc<Mutex<DB>> = Arc::new(db.into());
-            curr_db.lock().await.init_db(
-                workspace_path,
-                file_path.clone().as_deref(),
-                /*cleanp=*/ false,
-            );
-            // let mut server = Server::new(State::Dead, DBHandler::new(metadata.clone()));
-            self.init_server(curr_db);
             assert!(file_path.is_some());
             assert!(start_number.is_some());
             assert!(end_number.is_some());
-            assert!(self.curr_db.is_some());
-            self.curr_db
-                .clone()
-                .unwrap()
-                .lock()
-                .await
-                .query_descriptions(
-                    file_path.clone().unwrap(),
+            let descriptions = self
+                .run_descriptions(
+                    workspace_path,
+                    file_path.unwrap(),
                     start_number.unwrap(),
                     end_number.unwrap(),
                 )
                 .await;
+            output::print_lines(output::render_commit_descriptions(
+                &descriptions,
+                self.output_format,
+            ));
+            return;
+        } else if request_type.is_some()
+            && request_type.clone().unwrap() == RequestTypeOptions::Author
+        {
+            if file_path.is_none() {
+                log!(Level::Error, "No file path provided to find the authors for.");
+                return;
+            }
+            // Without a range, report the authors of the whole file.
+            let authors = self
+                .run_authors(
+                    workspace_path,
+                    file_path.unwrap(),
+                    start_number.unwrap_or(1),
+                    end_number.unwrap_or(0),
+                )
+                .await;
+            output::print_lines(output::render_author_summaries(
+                &authors,
+                self.output_format,
+            ));
             return;
         }
 
@@ -595,6 +873,32 @@ impl Server {
 //     }
 // }
 
+fn split_comma_separated(value: &Option<String>) -> Vec<String> {
+    value
+        .iter()
+        .flat_map(|value| value.split(','))
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+// The config set through CLI flags, takes precedence over the config files.
+fn config_overrides(args: &Cli) -> ConfigLayer {
+    ConfigLayer {
+        include: split_comma_separated(&args.include),
+        exclude: split_comma_separated(&args.exclude),
+        ignored_authors: split_comma_separated(&args.ignore_authors),
+        ignored_commits: split_comma_separated(&args.ignore_commits),
+        db_dir: args.db_dir.clone(),
+        merge_policy: args.merge_policy,
+        scoring_model: args.scoring_model,
+        score_half_life_days: args.score_half_life_days,
+        git_backend: args.git_backend,
+        indexing_mode: args.indexing_mode,
+        file_count_threshold: args.limit,
+    }
+}
+
 #[tokio::main]
 async fn main() -> CliResult {
     let args = Cli::from_args();
@@ -606,25 +910,42 @@ async fn main() -> CliResult {
         state_db_handler: DBHandler {
             metadata: DBMetadata::default(),
         },
+        output_format: args.format,
+        progress: None,
+        config: Config::default(),
+        offset: args.offset,
     };
 
-    // TODO: Add support for config file.
-    // let config_obj: config_impl::Config = config_impl::read_config(config::CONFIG_FILE_NAME);
-    // let mut file_path: Option<PathBuf> = None;
-    // if args.file.is_some() {
-    //     file_path = PathBuf::from_str(args.file.unwrap().as_str())
-    //         .unwrap()
-    //         .into();
-    // }
+    server.config = match config_impl::load_config(&args.folder_path, config_overrides(&args)) {
+        Ok(config_obj) => config_obj,
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            std::process::exit(1);
+        }
+    };
     match args.request_type {
         RequestTypeOptions::File => {
             server
-                .handle_server(args.folder_path.as_str(), args.file, None, None, None, None)
+                .handle_server(
+                    args.folder_path.as_str(),
+                    args.file,
+                    None,
+                    None,
+                    Some(RequestTypeOptions::File),
+                    None,
+                )
                 .await;
         }
         RequestTypeOptions::Author => {
             server
-                .handle_server(args.folder_path.as_str(), args.file, None, None, None, None)
+                .handle_server(
+                    args.folder_path.as_str(),
+                    args.file,
+                    args.start_number,
+                    args.end_number,
+                    Some(RequestTypeOptions::Author),
+                    None,
+                )
                 .await;
         }
         RequestTypeOptions::Index => {
@@ -684,6 +1005,94 @@ async fn main() -> CliResult {
                 )
                 .await;
         }
+        RequestTypeOptions::Serve => {
+            daemon::serve(args.folder_path, server).await;
+        }
+        RequestTypeOptions::Lsp => {
+            lsp::run(args.folder_path, server.config).await;
+        }
+        RequestTypeOptions::Export | RequestTypeOptions::Import => {
+            let Some(archive_path) = args.file else {
+                eprintln!("❌ Missing the path of the archive");
+                std::process::exit(1);
+            };
+            let archive_path = PathBuf::from(archive_path);
+            let (git, config) = (server.git(), &server.config);
+            let (verb, result) = if args.request_type == RequestTypeOptions::Export {
+                (
+                    "Exported",
+                    archive::export(git, &args.folder_path, &archive_path, config),
+                )
+            } else {
+                (
+                    "Imported",
+                    archive::import(git, &args.folder_path, &archive_path, config),
+                )
+            };
+            match result {
+                Ok(summary) => eprintln!(
+                    "✅ {} {} files and {} commits, built at {}",
+                    verb,
+                    summary.files,
+                    summary.commits,
+                    summary.head_commit.as_deref().unwrap_or("an unknown commit")
+                ),
+                Err(e) => {
+                    eprintln!("❌ {}: {}", archive_path.display(), e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        RequestTypeOptions::Gc => {
+            let Some(db_folder_path) =
+                config_impl::db_folder_path(&args.folder_path, &server.config)
+            else {
+                eprintln!("❌ Failed to determine the home directory");
+                std::process::exit(1);
+            };
+            if !db_folder_path.exists() {
+                eprintln!("❌ {} isn't indexed", args.folder_path);
+                std::process::exit(1);
+            }
+            // Files are keyed by absolute path, so this doesn't depend on the working directory.
+            let store = EmbeddedStore::new(&db_folder_path);
+            match store.gc(|file_path| !Path::new(file_path).exists()) {
+                Ok(report) => output::print_lines(output::render_gc_report(&report, args.format)),
+                Err(e) => {
+                    eprintln!("❌ {}: {}", db_folder_path.display(), e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        RequestTypeOptions::Status => {
+            let Some(db_folder_path) =
+                config_impl::db_folder_path(&args.folder_path, &server.config)
+            else {
+                eprintln!("❌ Failed to determine the home directory");
+                std::process::exit(1);
+            };
+            if !db_folder_path.exists() {
+                eprintln!("❌ {} isn't indexed", args.folder_path);
+                std::process::exit(1);
+            }
+            // Already validated when the config was loaded.
+            let path_filter = server
+                .config
+                .path_filter(&args.folder_path)
+                .unwrap_or_default();
+            match status::index_status(
+                server.git(),
+                &args.folder_path,
+                &db_folder_path,
+                &path_filter,
+            ) {
+                Ok(status) => output::print_lines(output::render_index_status(&status, args.format)),
+                Err(e) => {
+                    eprintln!("❌ {}: {}", db_folder_path.display(), e);
+                    std::process::exit(1);
+                }
+            }
+        }
         RequestTypeOptions::ListSubdirs => {
             // Just prints the subdirs to stdout
             server
@@ -0,0 +1,130 @@
+// Versions of the index format, and how to upgrade an index written by an older version.
+//
+// The version is kept in meta.json in the DB folder. Folders without it come from versions
+// that predate it, their version is told from the files they hold:
+// 1: numbered JSON shards holding only the lines, mapping.json says which file they belong to.
+// 2: the same, with the shards keyed by file path.
+// 3: a single embedded database, see EmbeddedStore.
+// 4: the same, with the message and the files changed in the commit table.
+// 5: the same, with full commit hashes instead of abbreviated ones.
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::embedded_store::{self, INDEX_FILE_NAME};
+use crate::fs_utils::write_atomic;
+use crate::index_store::StoreError;
+use crate::json_store::JsonStore;
+
+pub const META_FILE_NAME: &str = "meta.json";
+pub const FORMAT_VERSION: u32 = 5;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Meta {
+    pub format_version: u32,
+}
+
+// Upgrades an index from one version to the next.
+struct Migration {
+    from: u32,
+    description: &'static str,
+    run: fn(&Path) -> Result<(), StoreError>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 1,
+        description: "keying the shards by file path",
+        run: |folder_path| JsonStore::new(folder_path).key_shards_by_file(),
+    },
+    Migration {
+        from: 2,
+        description: "moving the JSON files into a single database",
+        run: embedded_store::import_json_layout,
+    },
+    Migration {
+        from: 3,
+        description: "clearing the commit table, it's filled again as needed",
+        run: embedded_store::clear_commits,
+    },
+    Migration {
+        from: 4,
+        description: "expanding abbreviated commit hashes",
+        run: embedded_store::expand_commit_hashes,
+    },
+];
+
+pub fn read_meta(folder_path: &Path) -> Result<Option<Meta>, StoreError> {
+    match std::fs::read_to_string(folder_path.join(META_FILE_NAME)) {
+        Ok(data) => Ok(Some(serde_json::from_str(&data)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn write_meta(folder_path: &Path, format_version: u32) -> Result<(), StoreError> {
+    let data = serde_json::to_string(&Meta { format_version })?;
+    write_atomic(&folder_path.join(META_FILE_NAME), data.as_bytes())?;
+    Ok(())
+}
+
+// For folders written before meta.json existed.
+fn detect_version(folder_path: &Path) -> u32 {
+    let json_store = JsonStore::new(folder_path);
+    if folder_path.join(INDEX_FILE_NAME).exists() {
+        3
+    } else if !json_store.exists() {
+        FORMAT_VERSION
+    } else if json_store.has_unkeyed_shards() {
+        1
+    } else {
+        2
+    }
+}
+
+// Brings the index in the folder to the current version, one migration at a time. Call with
+// the lock held.
+pub fn upgrade(folder_path: &Path) -> Result<(), StoreError> {
+    let meta = read_meta(folder_path).unwrap_or_else(|e| {
+        eprintln!(
+            "Unable to read {} ({}), detecting the version",
+            META_FILE_NAME, e
+        );
+        None
+    });
+    let has_meta = meta.is_some();
+    let mut format_version = match meta {
+        Some(meta) => meta.format_version,
+        None => detect_version(folder_path),
+    };
+    if format_version > FORMAT_VERSION {
+        return Err(StoreError::ReindexRequired(format!(
+            "it's at version {}, this version of contextpilot only knows up to {}",
+            format_version, FORMAT_VERSION
+        )));
+    }
+    while format_version < FORMAT_VERSION {
+        let Some(migration) = MIGRATIONS
+            .iter()
+            .find(|migration| migration.from == format_version)
+        else {
+            return Err(StoreError::ReindexRequired(format!(
+                "no migration from version {}",
+                format_version
+            )));
+        };
+        eprintln!(
+            "Upgrading the index at {} to version {}: {}",
+            folder_path.display(),
+            format_version + 1,
+            migration.description
+        );
+        (migration.run)(folder_path)?;
+        format_version += 1;
+        write_meta(folder_path, format_version)?;
+    }
+    if !has_meta {
+        write_meta(folder_path, format_version)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,196 @@
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::contextgpt_structs::{
+    AuthorSummary, CommitDescription, GcReport, IndexStatus, RelatedFile,
+};
+
+const SHORT_HASH_LENGTH: usize = 7;
+
+// Output formats for the results written to stdout.
+//
+// - text: the legacy human readable format (kept as is for existing plugins).
+// - json: a single JSON array with all the records.
+// - ndjson: one JSON object per line, handy for streaming consumers.
+//
+// Reports (gc, status) are a single record, a JSON object in both json and ndjson.
+//
+// Diagnostics never go to stdout, so that stdout only ever contains the records.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    Ndjson,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            _ => Err(format!(
+                "Could not parse the output format: {}, available formats: [\"text\", \"json\", \"ndjson\"]",
+                format
+            )),
+        }
+    }
+}
+
+// Commits are kept by full hash, only what people read is abbreviated, like git does.
+pub fn short_hash(commit_hash: &str) -> &str {
+    commit_hash.get(..SHORT_HASH_LENGTH).unwrap_or(commit_hash)
+}
+
+fn render_json<T: Serialize>(records: &[T], format: OutputFormat) -> Vec<String> {
+    match format {
+        OutputFormat::Json => {
+            vec![serde_json::to_string(records).expect("Failed to serialize the output")]
+        }
+        OutputFormat::Ndjson => records
+            .iter()
+            .map(|record| serde_json::to_string(record).expect("Failed to serialize the output"))
+            .collect(),
+        OutputFormat::Text => unreachable!("text output is rendered by the caller"),
+    }
+}
+
+fn render_report<T: Serialize>(report: &T) -> Vec<String> {
+    vec![serde_json::to_string(report).expect("Failed to serialize the output")]
+}
+
+pub fn render_related_files(files: &[RelatedFile], format: OutputFormat) -> Vec<String> {
+    match format {
+        OutputFormat::Text => files
+            .iter()
+            .map(|file| format!("{} - {} occurrences", file.path, file.occurrences))
+            .collect(),
+        _ => render_json(files, format),
+    }
+}
+
+pub fn render_commit_descriptions(
+    descriptions: &[CommitDescription],
+    format: OutputFormat,
+) -> Vec<String> {
+    match format {
+        OutputFormat::Text => {
+            // Plugins parse this as a list of [title, body, author, date, url].
+            let legacy: Vec<Vec<String>> = descriptions
+                .iter()
+                .map(|desc| {
+                    vec![
+                        desc.title.clone(),
+                        desc.body.clone(),
+                        desc.author.clone(),
+                        desc.date.clone(),
+                        desc.url.clone(),
+                    ]
+                })
+                .collect();
+            vec![format!("{:?}", legacy)]
+        }
+        _ => render_json(descriptions, format),
+    }
+}
+
+pub fn render_author_summaries(authors: &[AuthorSummary], format: OutputFormat) -> Vec<String> {
+    match format {
+        OutputFormat::Text => authors
+            .iter()
+            .map(|author| {
+                format!(
+                    "{} <{}> - {} lines, {} commits (first: {}, last: {})",
+                    author.author,
+                    author.email,
+                    author.lines,
+                    author.commits,
+                    author.first_touch,
+                    author.last_touch
+                )
+            })
+            .collect(),
+        _ => render_json(authors, format),
+    }
+}
+
+pub fn render_gc_report(report: &GcReport, format: OutputFormat) -> Vec<String> {
+    match format {
+        OutputFormat::Text => vec![format!(
+            "Removed {} deleted files, {} unused commits and {} leftover files, reclaimed {} ({} -> {})",
+            report.removed_files,
+            report.removed_commits,
+            report.removed_leftovers,
+            format_size(report.size_before.saturating_sub(report.size_after)),
+            format_size(report.size_before),
+            format_size(report.size_after)
+        )],
+        _ => render_report(report),
+    }
+}
+
+pub fn render_index_status(status: &IndexStatus, format: OutputFormat) -> Vec<String> {
+    if format != OutputFormat::Text {
+        return render_report(status);
+    }
+    let stale_files = status.files.iter().filter(|file| file.stale).count();
+    let mut lines = vec![
+        format!(
+            "Indexed {} of {} eligible files, {} on disk in {} tables of {} pages (format version {})",
+            status.indexed_files,
+            status.eligible_files,
+            format_size(status.size_on_disk),
+            status.tables,
+            status.pages,
+            status
+                .format_version
+                .map_or(String::from("unknown"), |format_version| format_version.to_string())
+        ),
+        format!(
+            "{} stale files, {} failed files",
+            stale_files,
+            status.failed_files.len()
+        ),
+    ];
+    for file in status.files.iter() {
+        let indexed_commit = file.indexed_commit.as_deref().map_or("-", short_hash);
+        lines.push(if file.stale {
+            format!(
+                "  {} {} (stale, HEAD: {})",
+                file.path,
+                indexed_commit,
+                file.latest_commit.as_deref().map_or("deleted", short_hash)
+            )
+        } else {
+            format!("  {} {}", file.path, indexed_commit)
+        });
+    }
+    for failed_file in status.failed_files.iter() {
+        lines.push(format!("  {} failed: {}", failed_file.path, failed_file.reason));
+    }
+    lines
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+pub fn print_lines(lines: Vec<String>) {
+    for line in lines {
+        println!("{}", line);
+    }
+}
@@ -0,0 +1,71 @@
+use crate::config_impl::{Config, ScoringModel};
+
+const SECONDS_PER_DAY: f64 = 24.0 * 60.0 * 60.0;
+
+// How much a commit counts for each of the files it changed.
+pub fn commit_weight(
+    config: &Config,
+    files_changed: usize,
+    tracked_files: usize,
+    age_days: f64,
+) -> f64 {
+    let weight = match config.scoring_model {
+        ScoringModel::Count => 1.0,
+        // The idf of the commit: one that touched every file (a mass reformat) is worth ln 2,
+        // a focused one up to ln(1 + number of files).
+        ScoringModel::TfIdf => {
+            let files_changed = files_changed.max(1) as f64;
+            let tracked_files = (tracked_files as f64).max(files_changed);
+            (1.0 + tracked_files / files_changed).ln()
+        }
+    };
+    match config.score_half_life_days {
+        Some(half_life_days) => weight * 0.5_f64.powf(age_days.max(0.0) / half_life_days),
+        None => weight,
+    }
+}
+
+// Age of a commit in days, from its unix timestamp.
+pub fn age_in_days(timestamp: i64, now: i64) -> f64 {
+    (now - timestamp) as f64 / SECONDS_PER_DAY
+}
+
+// Scales the weight so that the best match gets 1, rounded to keep the output readable.
+pub fn normalize(weight: f64, max_weight: f64) -> f64 {
+    if max_weight <= 0.0 {
+        return 0.0;
+    }
+    ((weight / max_weight) * 10_000.0).round() / 10_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tf_idf_prefers_focused_commits() {
+        let config = Config::default();
+        let focused = commit_weight(&config, 2, 400, 0.0);
+        let mass_change = commit_weight(&config, 400, 400, 0.0);
+        assert!(focused > mass_change * 5.0);
+        assert_eq!(mass_change, 2.0_f64.ln());
+    }
+
+    #[test]
+    fn test_count_and_decay() {
+        let mut config = Config {
+            scoring_model: ScoringModel::Count,
+            ..Default::default()
+        };
+        assert_eq!(commit_weight(&config, 400, 400, 1000.0), 1.0);
+        config.score_half_life_days = Some(30.0);
+        assert_eq!(commit_weight(&config, 1, 400, 60.0), 0.25);
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(3.0, 3.0), 1.0);
+        assert_eq!(normalize(1.0, 3.0), 0.3333);
+        assert_eq!(normalize(1.0, 0.0), 0.0);
+    }
+}
@@ -0,0 +1,78 @@
+// What `-t status` reports about the index of a workspace: how much of the workspace it
+// covers, how big it is, and which files are behind HEAD or couldn't be indexed.
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::config_impl::PathFilter;
+use crate::contextgpt_structs::{FailedFile, IndexStatus, IndexedFileStatus};
+use crate::embedded_store::EmbeddedStore;
+use crate::fs_utils::folder_size;
+use crate::git_backend::GitBackend;
+use crate::git_command_algo::{get_all_valid_files, get_latest_commits};
+use crate::index_store::{IndexStore, StoreError};
+use crate::migrations;
+
+// Reads the index in db_folder_path, which must exist. Run from the workspace, for git.
+pub fn index_status(
+    git: &dyn GitBackend,
+    workspace_path: &str,
+    db_folder_path: &Path,
+    path_filter: &PathFilter,
+) -> Result<IndexStatus, StoreError> {
+    let workspace =
+        std::fs::canonicalize(workspace_path).unwrap_or_else(|_| PathBuf::from(workspace_path));
+    let store = EmbeddedStore::new(db_folder_path);
+    let indexed_files = store.list_files()?;
+
+    // Same rules as indexing.
+    let eligible_files: HashSet<PathBuf> =
+        get_all_valid_files(workspace.to_string_lossy().to_string(), None)
+            .into_iter()
+            .filter_map(|file_path| std::fs::canonicalize(file_path).ok())
+            .filter(|file_path| {
+                let relative_path = file_path.strip_prefix(&workspace).unwrap_or(file_path);
+                path_filter.is_included(relative_path, false)
+            })
+            .collect();
+
+    let latest_commits = get_latest_commits(git);
+    let mut files = vec![];
+    for file_path in indexed_files.iter() {
+        let indexed_commit = store.last_indexed_commit(file_path)?;
+        let latest_commit = latest_commits.get(file_path).cloned();
+        let stale = indexed_commit != latest_commit;
+        files.push(IndexedFileStatus {
+            path: relative_path(&workspace, file_path),
+            indexed_commit,
+            latest_commit,
+            stale,
+        });
+    }
+    let failed_files = store
+        .failed_files()?
+        .into_iter()
+        .map(|(file_path, reason)| FailedFile {
+            path: relative_path(&workspace, &file_path),
+            reason,
+        })
+        .collect();
+
+    let (tables, pages) = store.stats()?;
+    Ok(IndexStatus {
+        indexed_files: indexed_files.len(),
+        eligible_files: eligible_files.len(),
+        size_on_disk: folder_size(db_folder_path),
+        tables,
+        pages,
+        format_version: migrations::read_meta(db_folder_path)?.map(|meta| meta.format_version),
+        files,
+        failed_files,
+    })
+}
+
+fn relative_path(workspace: &Path, file_path: &str) -> String {
+    Path::new(file_path)
+        .strip_prefix(workspace)
+        .map(|relative_path| relative_path.to_string_lossy().to_string())
+        .unwrap_or_else(|_| file_path.to_string())
+}
@@ -0,0 +1,132 @@
+use contextpilot::archive::{ARCHIVE_FORMAT_VERSION, Archive};
+use contextpilot::config_impl::workspace_key;
+use contextpilot::embedded_store::EmbeddedStore;
+use contextpilot::index_store::IndexStore;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use std::fs::{self, File};
+use std::process::Command;
+use tempfile::tempdir;
+
+mod common;
+use common::{git, run};
+
+#[test]
+fn test_index_moves_between_checkouts() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    fs::create_dir_all(repo_dir.join("src")).unwrap();
+    let repo_dir = repo_dir.canonicalize().unwrap();
+    git(&repo_dir, &["init"]);
+    fs::write(repo_dir.join("src/main.txt"), "one\ntwo\n").unwrap();
+    fs::write(repo_dir.join("other.txt"), "other\n").unwrap();
+    git(&repo_dir, &["add", "."]);
+    git(&repo_dir, &["commit", "-m", "Initial commit"]);
+
+    // Built on one machine...
+    let ci_db_dir = temp_dir.path().join("ci_db");
+    assert!(
+        run(&repo_dir, &ci_db_dir, &["-t", "index"])
+            .status
+            .success()
+    );
+    let archive_path = temp_dir.path().join("index.json.gz");
+    let output = run(
+        &repo_dir,
+        &ci_db_dir,
+        &["-t", "export", archive_path.to_str().unwrap()],
+    );
+    assert!(output.status.success());
+
+    let archive: Archive =
+        serde_json::from_reader(GzDecoder::new(File::open(&archive_path).unwrap())).unwrap();
+    assert_eq!(archive.format_version, ARCHIVE_FORMAT_VERSION);
+    let head_commit = String::from_utf8(
+        Command::new("git")
+            .args(["rev-parse", "HEAD"])
+            .current_dir(&repo_dir)
+            .output()
+            .unwrap()
+            .stdout,
+    )
+    .unwrap();
+    assert_eq!(archive.head_commit.as_deref(), Some(head_commit.trim()));
+    assert_eq!(
+        archive.files.keys().collect::<Vec<_>>(),
+        vec!["other.txt", "src/main.txt"]
+    );
+    assert!(!archive.commits.is_empty());
+
+    // ...imported in a checkout somewhere else.
+    let clone_dir = temp_dir.path().join("clone");
+    git(
+        temp_dir.path(),
+        &[
+            "clone",
+            repo_dir.to_str().unwrap(),
+            clone_dir.to_str().unwrap(),
+        ],
+    );
+    let clone_dir = clone_dir.canonicalize().unwrap();
+    let db_dir = temp_dir.path().join("db");
+    let output = run(
+        &clone_dir,
+        &db_dir,
+        &["-t", "import", archive_path.to_str().unwrap()],
+    );
+    assert!(output.status.success());
+
+    let store = EmbeddedStore::new(&db_dir.join(workspace_key(&clone_dir.to_string_lossy())));
+    let main_path = clone_dir.join("src").join("main.txt");
+    let main_path = main_path.to_string_lossy();
+    assert_eq!(
+        store.get_file_lines(&main_path).unwrap(),
+        Some(archive.files["src/main.txt"].lines.clone())
+    );
+    assert_eq!(
+        store.last_indexed_commit(&main_path).unwrap(),
+        archive.files["src/main.txt"].indexed_commit
+    );
+
+    // Queries run on the imported index.
+    let output = run(
+        &clone_dir,
+        &db_dir,
+        &[
+            "-t", "query", &main_path, "-s", "1", "-e", "2", "--format", "json",
+        ],
+    );
+    let related_files: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(
+        related_files
+            .iter()
+            .any(|related_file| related_file["path"] == "other.txt")
+    );
+}
+
+#[test]
+fn test_archives_of_other_versions_are_rejected() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    fs::create_dir_all(&repo_dir).unwrap();
+    git(&repo_dir, &["init"]);
+
+    let archive_path = temp_dir.path().join("index.json.gz");
+    let mut encoder = GzEncoder::new(File::create(&archive_path).unwrap(), Compression::default());
+    serde_json::to_writer(
+        &mut encoder,
+        &serde_json::json!({"format_version": ARCHIVE_FORMAT_VERSION + 1}),
+    )
+    .unwrap();
+    encoder.finish().unwrap();
+
+    let output = run(
+        &repo_dir,
+        &temp_dir.path().join("db"),
+        &["-t", "import", archive_path.to_str().unwrap()],
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("version"), "Unexpected stderr: {}", stderr);
+}
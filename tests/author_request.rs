@@ -0,0 +1,149 @@
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+mod common;
+use common::{commit_as, contextpilot, git};
+
+fn setup_repo(repo_dir: &Path) {
+    git(repo_dir, &["init"]);
+    fs::write(repo_dir.join("code.txt"), "a\nb\nc\nd\n").unwrap();
+    git(repo_dir, &["add", "code.txt"]);
+    commit_as(
+        repo_dir,
+        "Alice",
+        "alice@example.com",
+        "2024-01-01T10:00:00+00:00",
+        "Add code",
+    );
+    fs::write(repo_dir.join("code.txt"), "a\nB\nc\nd\n").unwrap();
+    commit_as(
+        repo_dir,
+        "Bob",
+        "bob@example.com",
+        "2024-02-01T10:00:00+00:00",
+        "Change line 2",
+    );
+    fs::write(repo_dir.join("code.txt"), "a\nB\nc\nD\n").unwrap();
+    commit_as(
+        repo_dir,
+        "Alice",
+        "alice@example.com",
+        "2024-03-01T10:00:00+00:00",
+        "Change line 4",
+    );
+}
+
+fn run(repo_dir: &Path, home_dir: &Path, args: &[&str]) -> String {
+    String::from_utf8(contextpilot(repo_dir, home_dir, args).stdout).unwrap()
+}
+
+fn authors(output: &str) -> Vec<Value> {
+    serde_json::from_str::<Vec<Value>>(output.trim()).expect("stdout must be a JSON array")
+}
+
+#[test]
+fn test_author_request_ranks_authors() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    let home_dir = temp_dir.path().join("home");
+    fs::create_dir_all(&repo_dir).unwrap();
+    fs::create_dir_all(&home_dir).unwrap();
+    setup_repo(&repo_dir);
+    let repo_dir = repo_dir.canonicalize().unwrap();
+
+    // Without an index, the history is computed on the fly.
+    let output = run(
+        &repo_dir,
+        &home_dir,
+        &[
+            "-t", "author", "code.txt", "-s", "2", "-e", "2", "--format", "json",
+        ],
+    );
+    let result = authors(&output);
+    let names: Vec<&str> = result
+        .iter()
+        .map(|author| author["author"].as_str().unwrap())
+        .collect();
+    assert!(names.contains(&"Bob"), "Unexpected output: {}", output);
+    // And stored, like an indexed file.
+    let output = run(&repo_dir, &home_dir, &["-t", "status", "--format", "json"]);
+    let status: Value = serde_json::from_str(&output).expect("stdout must be a JSON object");
+    let files = status["files"].as_array().unwrap();
+    assert_eq!(files.len(), 1, "Unexpected output: {}", output);
+    assert_eq!(files[0]["path"], "code.txt");
+    assert_eq!(files[0]["stale"], false);
+
+    // With an index, the answer comes from the stored commit table.
+    run(&repo_dir, &home_dir, &["-t", "indexfile", "code.txt"]);
+    let output = run(
+        &repo_dir,
+        &home_dir,
+        &["-t", "author", "code.txt", "--format", "json"],
+    );
+    let result = authors(&output);
+    assert_eq!(result.len(), 2, "Unexpected output: {}", output);
+    // Alice touched all the lines (she added them), Bob only one.
+    assert_eq!(result[0]["author"], "Alice");
+    assert_eq!(result[0]["email"], "alice@example.com");
+    assert_eq!(result[0]["lines"], 4);
+    assert_eq!(result[0]["commits"], 2);
+    assert_eq!(result[0]["first_touch"], "2024-01-01T10:00:00+00:00");
+    assert_eq!(result[0]["last_touch"], "2024-03-01T10:00:00+00:00");
+    assert_eq!(result[1]["author"], "Bob");
+    assert_eq!(result[1]["lines"], 1);
+    assert_eq!(result[1]["commits"], 1);
+
+    // The range restricts the lines that are looked at.
+    let output = run(
+        &repo_dir,
+        &home_dir,
+        &["-t", "author", "code.txt", "-s", "2", "-e", "2"],
+    );
+    // Line 2 was added by Alice and changed by Bob, ties are ordered by name.
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 2, "Unexpected output: {}", output);
+    assert!(lines[0].starts_with("Alice <alice@example.com> - 1 lines, 1 commits"));
+    assert!(lines[1].starts_with("Bob <bob@example.com> - 1 lines, 1 commits"));
+}
+
+#[test]
+fn test_author_request_catches_up_commits_since_indexing() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    let home_dir = temp_dir.path().join("home");
+    fs::create_dir_all(&repo_dir).unwrap();
+    fs::create_dir_all(&home_dir).unwrap();
+    setup_repo(&repo_dir);
+    let repo_dir = repo_dir.canonicalize().unwrap();
+    run(&repo_dir, &home_dir, &["-t", "indexfile", "code.txt"]);
+
+    fs::write(repo_dir.join("code.txt"), "a\nB\nC\nD\n").unwrap();
+    commit_as(
+        &repo_dir,
+        "Carol",
+        "carol@example.com",
+        "2024-04-01T10:00:00+00:00",
+        "Change line 3",
+    );
+    let output = run(
+        &repo_dir,
+        &home_dir,
+        &[
+            "-t", "author", "code.txt", "-s", "3", "-e", "3", "--format", "json",
+        ],
+    );
+    let result = authors(&output);
+    let names: Vec<&str> = result
+        .iter()
+        .map(|author| author["author"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        names,
+        vec!["Alice", "Carol"],
+        "Unexpected output: {}",
+        output
+    );
+    assert_eq!(result[1]["last_touch"], "2024-04-01T10:00:00+00:00");
+}
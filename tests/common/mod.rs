@@ -0,0 +1,70 @@
+// Helpers shared by the integration tests, every test binary only uses some of them.
+#![allow(dead_code)]
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+// Runs git in the directory as a test user, what it printed. Fails the test when git fails.
+pub fn git(dir_path: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .current_dir(dir_path)
+        .output()
+        .expect("Failed to run git");
+    assert!(output.status.success(), "git {:?} failed", args);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+// Same as git, with the author and committer dates set to `date`.
+pub fn git_at(dir_path: &Path, date: &str, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .env("GIT_AUTHOR_DATE", date)
+        .env("GIT_COMMITTER_DATE", date)
+        .current_dir(dir_path)
+        .output()
+        .expect("Failed to run git");
+    assert!(output.status.success(), "git {:?} failed", args);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+// Commits the changes to tracked files as the given author, at the given date.
+pub fn commit_as(dir_path: &Path, name: &str, email: &str, date: &str, message: &str) {
+    let output = Command::new("git")
+        .args(["-c", &format!("user.name={}", name)])
+        .args(["-c", &format!("user.email={}", email)])
+        .args(["commit", "-am", message])
+        .env("GIT_AUTHOR_DATE", date)
+        .env("GIT_COMMITTER_DATE", date)
+        .current_dir(dir_path)
+        .output()
+        .expect("Failed to commit");
+    assert!(output.status.success(), "Commit failed: {:?}", output);
+}
+
+// Runs contextpilot on the repository from inside it, with `home_dir` as HOME so that the
+// index doesn't end up in the real one.
+pub fn contextpilot(repo_dir: &Path, home_dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_contextpilot"))
+        .arg(repo_dir)
+        .args(args)
+        .current_dir(repo_dir)
+        .env("HOME", home_dir)
+        .output()
+        .expect("Failed to run contextpilot")
+}
+
+// Same as contextpilot, with the index in `db_dir` through --db-dir.
+pub fn run(repo_dir: &Path, db_dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_contextpilot"))
+        .arg(repo_dir)
+        .args(args)
+        .arg("--db-dir")
+        .arg(db_dir)
+        .current_dir(repo_dir)
+        .env("HOME", db_dir)
+        .output()
+        .expect("Failed to run contextpilot")
+}
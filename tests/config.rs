@@ -0,0 +1,262 @@
+use contextpilot::config_impl::{
+    Config, ConfigLayer, PathFilter, load_config, read_config, workspace_key,
+};
+use contextpilot::embedded_store::EmbeddedStore;
+use contextpilot::index_store::IndexStore;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use tempfile::tempdir;
+
+mod common;
+use common::git;
+
+#[test]
+fn test_layers_are_merged_in_order() {
+    let mut config_obj = Config::default();
+    config_obj.apply(ConfigLayer {
+        file_count_threshold: Some(5),
+        exclude: vec![String::from("target/")],
+        db_dir: Some(String::from("/global/db")),
+        ..Default::default()
+    });
+    config_obj.apply(ConfigLayer {
+        exclude: vec![String::from("*.lock")],
+        ignored_authors: vec![String::from("bot@example.com")],
+        ..Default::default()
+    });
+    config_obj.apply(ConfigLayer {
+        file_count_threshold: Some(20),
+        ..Default::default()
+    });
+    assert_eq!(config_obj.file_count_threshold, 20);
+    assert_eq!(config_obj.exclude, vec!["target/", "*.lock"]);
+    assert_eq!(config_obj.db_dir, Some(String::from("/global/db")));
+    assert!(config_obj.is_ignored_author("Bot", "BOT@example.com"));
+    assert!(!config_obj.is_ignored_author("Test", "test@example.com"));
+}
+
+#[test]
+fn test_workspace_config_and_overrides() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let workspace = temp_dir.path().to_str().unwrap();
+    fs::write(
+        temp_dir.path().join(".contextpilot.json"),
+        r#"{"file_count_threshold": 3, "ignored_commits": ["abc123"]}"#,
+    )
+    .unwrap();
+    let config_obj = load_config(
+        workspace,
+        ConfigLayer {
+            ignored_commits: vec![String::from("def456")],
+            ..Default::default()
+        },
+    )
+    .expect("Config should load");
+    assert_eq!(config_obj.file_count_threshold, 3);
+    assert!(config_obj.is_ignored_commit("abc123456789"));
+    assert!(config_obj.is_ignored_commit("def456"));
+    assert!(!config_obj.is_ignored_commit("0123456"));
+}
+
+#[test]
+fn test_malformed_config_is_an_error() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let config_path = temp_dir.path().join(".contextpilot.json");
+
+    fs::write(&config_path, r#"{"file_count_threshold": "ten"}"#).unwrap();
+    let error = read_config(&config_path).unwrap_err();
+    assert!(error.to_string().contains(".contextpilot.json"));
+
+    fs::write(&config_path, r#"{"exclud": ["target/"]}"#).unwrap();
+    let error = read_config(&config_path).unwrap_err();
+    assert!(error.to_string().contains("unknown field `exclud`"));
+
+    assert!(
+        read_config(&temp_dir.path().join("missing.json"))
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[test]
+fn test_path_filter() {
+    let include = vec![String::from("src/**/*.rs")];
+    let exclude = vec![String::from("src/generated/")];
+    let path_filter = PathFilter::new("/workspace", &include, &exclude).unwrap();
+    assert!(path_filter.is_included(Path::new("src/main.rs"), false));
+    assert!(path_filter.is_included(Path::new("src/db/mod.rs"), false));
+    assert!(path_filter.is_included(Path::new("src"), true));
+    assert!(!path_filter.is_included(Path::new("README.md"), false));
+    assert!(!path_filter.is_included(Path::new("src/generated"), true));
+    assert!(!path_filter.is_included(Path::new("src/generated/api.rs"), false));
+
+    assert!(PathFilter::new("/workspace", &[String::from("src/{a")], &[]).is_err());
+}
+
+#[test]
+fn test_config_applies_to_indexing_and_queries() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    let home_dir = temp_dir.path().join("home");
+    let db_dir = temp_dir.path().join("db");
+    fs::create_dir_all(repo_dir.join("vendor")).unwrap();
+    fs::create_dir_all(&home_dir).unwrap();
+    let repo_dir = repo_dir.canonicalize().unwrap();
+
+    git(&repo_dir, &["init"]);
+    fs::write(repo_dir.join("main.txt"), "one\ntwo\n").unwrap();
+    fs::write(repo_dir.join("other.txt"), "other\n").unwrap();
+    fs::write(repo_dir.join("vendor/lib.txt"), "lib\n").unwrap();
+    git(&repo_dir, &["add", "."]);
+    git(&repo_dir, &["commit", "-m", "Initial commit"]);
+    fs::write(repo_dir.join("main.txt"), "one\nTWO\n").unwrap();
+    fs::write(repo_dir.join("other.txt"), "OTHER\n").unwrap();
+    git(&repo_dir, &["commit", "-am", "Reformat"]);
+    let reformat_commit = String::from_utf8(
+        Command::new("git")
+            .args(["rev-parse", "HEAD"])
+            .current_dir(&repo_dir)
+            .output()
+            .unwrap()
+            .stdout,
+    )
+    .unwrap()
+    .trim()
+    .to_string();
+    fs::write(
+        repo_dir.join(".contextpilot.json"),
+        format!(
+            r#"{{"exclude": ["vendor/"], "ignored_commits": ["{}"]}}"#,
+            reformat_commit
+        ),
+    )
+    .unwrap();
+
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_contextpilot"))
+            .arg(&repo_dir)
+            .args(args)
+            .current_dir(&repo_dir)
+            .env("HOME", &home_dir)
+            .output()
+            .expect("Failed to run contextpilot")
+    };
+
+    let output = run(&["-t", "index", "--db-dir", db_dir.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert!(!home_dir.join(".context_pilot_db").exists());
+    let db_folder = db_dir.join(workspace_key(&repo_dir.to_string_lossy()));
+    let indexed_files = EmbeddedStore::new(&db_folder).list_files().unwrap();
+    assert!(indexed_files.iter().any(|path| path.ends_with("main.txt")));
+    assert!(!indexed_files.iter().any(|path| path.contains("vendor")));
+
+    // The reformat commit is the only one that touched other.txt after the first commit.
+    let file_path = repo_dir.join("main.txt").to_string_lossy().to_string();
+    let output = run(&[
+        "-t",
+        "query",
+        &file_path,
+        "-s",
+        "2",
+        "-e",
+        "2",
+        "--format",
+        "json",
+        "--db-dir",
+        db_dir.to_str().unwrap(),
+    ]);
+    let related_files: Vec<Value> = serde_json::from_slice(&output.stdout).unwrap();
+    let other = related_files
+        .iter()
+        .find(|file| file["path"] == "other.txt")
+        .expect("other.txt should be related through the initial commit");
+    assert_eq!(other["occurrences"], 1);
+
+    // A malformed config is reported, not a panic.
+    fs::write(repo_dir.join(".contextpilot.json"), "{ not json").unwrap();
+    let output = run(&["-t", "index"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Invalid config"),
+        "Unexpected stderr: {}",
+        stderr
+    );
+    assert!(
+        !stderr.contains("panicked"),
+        "Unexpected stderr: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_workspace_keys() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let first = temp_dir.path().join("a").join("project");
+    let second = temp_dir.path().join("b").join("project");
+    fs::create_dir_all(&first).unwrap();
+    fs::create_dir_all(&second).unwrap();
+    let first = first.to_string_lossy().to_string();
+    let second = second.to_string_lossy().to_string();
+
+    let key = workspace_key(&first);
+    assert!(key.starts_with("project-"), "Unexpected key: {}", key);
+    assert!(!key.contains(std::path::MAIN_SEPARATOR));
+    // Same workspace however it's spelled, different workspaces never collide.
+    assert_eq!(workspace_key(&format!("{}/", first)), key);
+    assert_eq!(workspace_key(&format!("{}/../project", first)), key);
+    assert_ne!(workspace_key(&second), key);
+}
+
+#[test]
+fn test_index_location_from_the_environment() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    let home_dir = temp_dir.path().join("home");
+    fs::create_dir_all(&repo_dir).unwrap();
+    fs::create_dir_all(&home_dir).unwrap();
+    let repo_dir = repo_dir.canonicalize().unwrap();
+    git(&repo_dir, &["init"]);
+    fs::write(repo_dir.join("main.txt"), "one\n").unwrap();
+    git(&repo_dir, &["add", "."]);
+    git(&repo_dir, &["commit", "-m", "Initial commit"]);
+    let key = workspace_key(&repo_dir.to_string_lossy());
+
+    let index = |envs: &[(&str, &Path)]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_contextpilot"))
+            .arg(&repo_dir)
+            .args(["-t", "index"])
+            .current_dir(&repo_dir)
+            .env("HOME", &home_dir)
+            .env_remove("CONTEXT_PILOT_DB_DIR")
+            .env_remove("XDG_CACHE_HOME")
+            .envs(envs.iter().copied())
+            .output()
+            .expect("Failed to run contextpilot");
+        assert!(output.status.success());
+    };
+    let has_index = |db_root: &Path| {
+        EmbeddedStore::new(&db_root.join(&key))
+            .list_files()
+            .unwrap()
+            .iter()
+            .any(|path| path.ends_with("main.txt"))
+    };
+
+    index(&[]);
+    assert!(has_index(&home_dir.join(".context_pilot_db")));
+
+    let cache_dir = temp_dir.path().join("cache");
+    index(&[("XDG_CACHE_HOME", &cache_dir)]);
+    assert!(has_index(&cache_dir.join("contextpilot")));
+
+    // The variable wins over $XDG_CACHE_HOME.
+    let db_dir = temp_dir.path().join("db");
+    index(&[
+        ("XDG_CACHE_HOME", &cache_dir),
+        ("CONTEXT_PILOT_DB_DIR", &db_dir),
+    ]);
+    assert!(has_index(&db_dir));
+}
@@ -0,0 +1,141 @@
+use serde_json::Value;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use tempfile::tempdir;
+
+mod common;
+use common::git;
+
+fn init_repo(dir_path: &Path) {
+    git(dir_path, &["init"]);
+    fs::write(dir_path.join("main.txt"), "line 1\nline 2\nline 3\n").unwrap();
+    fs::create_dir_all(dir_path.join("sub")).unwrap();
+    fs::write(dir_path.join("sub").join("other.txt"), "other 1\n").unwrap();
+    git(dir_path, &["add", "-A"]);
+    git(dir_path, &["commit", "-m", "Initial commit"]);
+    fs::write(dir_path.join("main.txt"), "line 1\nline two\nline 3\n").unwrap();
+    fs::write(dir_path.join("sub").join("other.txt"), "other 1\nother 2\n").unwrap();
+    git(dir_path, &["commit", "-am", "Change both files"]);
+}
+
+// Sends all the messages at once and returns every line the daemon wrote to stdout.
+fn run_daemon(repo_dir: &Path, home_dir: &Path, messages: &[&str]) -> Vec<Value> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_contextpilot"))
+        .args([repo_dir.to_str().unwrap(), "-t", "serve"])
+        .current_dir(repo_dir)
+        .env("HOME", home_dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to start the daemon");
+    {
+        let stdin = child.stdin.as_mut().unwrap();
+        for message in messages {
+            writeln!(stdin, "{}", message).unwrap();
+        }
+    }
+    let output = child.wait_with_output().expect("Daemon didn't exit");
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("stdout must only contain JSON"))
+        .collect()
+}
+
+fn response_for(messages: &[Value], id: i64) -> &Value {
+    messages
+        .iter()
+        .find(|message| message["id"] == id)
+        .unwrap_or_else(|| panic!("No response for id {}", id))
+}
+
+#[test]
+fn test_daemon_index_query_and_status() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    let home_dir = temp_dir.path().join("home");
+    fs::create_dir_all(&repo_dir).unwrap();
+    fs::create_dir_all(&home_dir).unwrap();
+    init_repo(&repo_dir);
+
+    let messages = run_daemon(
+        &repo_dir,
+        &home_dir,
+        &[
+            r#"{"jsonrpc":"2.0","id":1,"method":"index"}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"query","params":{"file":"main.txt","start":1,"end":3}}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"describe","params":{"file":"main.txt","start":2,"end":2}}"#,
+            r#"{"jsonrpc":"2.0","id":4,"method":"listSubdirs"}"#,
+            r#"{"jsonrpc":"2.0","id":5,"method":"status"}"#,
+            r#"{"jsonrpc":"2.0","id":6,"method":"query","params":{"file":"main.txt"}}"#,
+            r#"{"jsonrpc":"2.0","id":7,"method":"unknown"}"#,
+            r#"{"jsonrpc":"2.0","id":8,"method":"shutdown"}"#,
+        ],
+    );
+
+    let progress: Vec<&Value> = messages
+        .iter()
+        .filter(|message| message["method"] == "progress")
+        .collect();
+    assert_eq!(
+        progress.len(),
+        2,
+        "Expected one progress notification per file"
+    );
+    assert_eq!(response_for(&messages, 1)["result"]["indexed"], 2);
+
+    let related = response_for(&messages, 2)["result"].as_array().unwrap();
+    let paths: Vec<&str> = related
+        .iter()
+        .map(|file| file["path"].as_str().unwrap())
+        .collect();
+    assert!(paths.contains(&"main.txt"));
+    assert!(paths.contains(&"sub/other.txt"));
+
+    let descriptions = response_for(&messages, 3)["result"].as_array().unwrap();
+    let titles: Vec<&str> = descriptions
+        .iter()
+        .map(|desc| desc["title"].as_str().unwrap())
+        .collect();
+    assert!(titles.contains(&"Change both files"));
+
+    assert_eq!(
+        response_for(&messages, 4)["result"],
+        serde_json::json!(["sub"])
+    );
+
+    let status = &response_for(&messages, 5)["result"];
+    assert_eq!(status["state"], "Running");
+    assert_eq!(status["loadedFiles"].as_array().unwrap().len(), 1);
+
+    assert_eq!(response_for(&messages, 6)["error"]["code"], -32602);
+    assert_eq!(response_for(&messages, 7)["error"]["code"], -32601);
+    assert_eq!(response_for(&messages, 8)["result"], Value::Null);
+}
+
+#[test]
+fn test_daemon_reports_parse_errors() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    let home_dir = temp_dir.path().join("home");
+    fs::create_dir_all(&repo_dir).unwrap();
+    fs::create_dir_all(&home_dir).unwrap();
+    init_repo(&repo_dir);
+
+    let messages = run_daemon(
+        &repo_dir,
+        &home_dir,
+        &[
+            "not json",
+            r#"{"jsonrpc":"1.0","id":1,"method":"status"}"#,
+            r#"{"jsonrpc":"2.0","method":"status"}"#,
+        ],
+    );
+    // The notification (no id) must not be answered.
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0]["error"]["code"], -32700);
+    assert_eq!(messages[1]["error"]["code"], -32600);
+}
@@ -0,0 +1,71 @@
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+mod common;
+use common::{contextpilot, git};
+
+fn setup_repo(repo_dir: &Path) {
+    git(repo_dir, &["init"]);
+    fs::write(repo_dir.join("main.txt"), "one\ntwo\nthree\n").unwrap();
+    fs::write(repo_dir.join("helper.txt"), "help\n").unwrap();
+    git(repo_dir, &["add", "."]);
+    git(repo_dir, &["commit", "-m", "Add main and helper"]);
+    fs::write(repo_dir.join("main.txt"), "one\ntwo\nTHREE\n").unwrap();
+    fs::write(repo_dir.join("other.txt"), "other\n").unwrap();
+    git(repo_dir, &["add", "."]);
+    git(repo_dir, &["commit", "-m", "Change main, add other"]);
+}
+
+fn related_files(repo_dir: &Path, home_dir: &Path, args: &[&str]) -> Vec<(String, u64)> {
+    let args = [args, &["--format", "json"]].concat();
+    let output = contextpilot(repo_dir, home_dir, &args);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let records: Vec<Value> =
+        serde_json::from_str(stdout.trim()).expect("stdout must be a JSON array");
+    let mut files: Vec<(String, u64)> = records
+        .iter()
+        .map(|record| {
+            (
+                record["path"].as_str().unwrap().to_string(),
+                record["occurrences"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn test_file_request_matches_whole_file_query() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    let home_dir = temp_dir.path().join("home");
+    fs::create_dir_all(&repo_dir).unwrap();
+    fs::create_dir_all(&home_dir).unwrap();
+    setup_repo(&repo_dir);
+    let repo_dir = repo_dir.canonicalize().unwrap();
+    let file_path = repo_dir.join("main.txt").to_string_lossy().to_string();
+
+    let expected = vec![
+        (String::from("helper.txt"), 3),
+        (String::from("main.txt"), 4),
+        (String::from("other.txt"), 1),
+    ];
+
+    // Without an index.
+    let files = related_files(&repo_dir, &home_dir, &["-t", "file", &file_path]);
+    assert_eq!(files, expected);
+
+    // With an index, the same as a query over all the lines.
+    contextpilot(&repo_dir, &home_dir, &["-t", "indexfile", &file_path]);
+    let files = related_files(&repo_dir, &home_dir, &["-t", "file", &file_path]);
+    let queried = related_files(
+        &repo_dir,
+        &home_dir,
+        &["-t", "query", &file_path, "-s", "1", "-e", "3"],
+    );
+    assert_eq!(files, queried);
+    assert_eq!(files, expected);
+}
@@ -0,0 +1,285 @@
+use contextpilot::git_backend::{GitBackend, GitCli, GitError, Libgit2};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use tempfile::tempdir;
+
+mod common;
+use common::{git, git_at, run};
+
+// A rename, a merge with a conflict resolution and a file without a trailing newline.
+fn setup_repo(repo_dir: &Path) {
+    git(repo_dir, &["init", "-b", "main"]);
+    fs::write(repo_dir.join("f.txt"), "1\n2\n3\n4\n5\n").unwrap();
+    fs::write(repo_dir.join("old.txt"), "a\nb\nc\nd\ne\nf\n").unwrap();
+    git(repo_dir, &["add", "."]);
+    git(repo_dir, &["commit", "-m", "Base", "-m", "With a body."]);
+    git(repo_dir, &["checkout", "-b", "side"]);
+    fs::write(repo_dir.join("f.txt"), "1\nS2\nS3\n4\n5\n").unwrap();
+    git(repo_dir, &["commit", "-am", "Side change"]);
+    git(repo_dir, &["checkout", "main"]);
+    fs::write(repo_dir.join("f.txt"), "1\n2\nM3\n4\nM5\n").unwrap();
+    git(repo_dir, &["mv", "old.txt", "new.txt"]);
+    fs::write(repo_dir.join("new.txt"), "a\nb\nc\nd\ne\nf").unwrap();
+    git(repo_dir, &["commit", "-am", "Main change"]);
+    let output = Command::new("git")
+        .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+        .args(["merge", "side"])
+        .current_dir(repo_dir)
+        .output()
+        .expect("Failed to run git");
+    assert!(!output.status.success(), "git merge should conflict");
+    fs::write(repo_dir.join("f.txt"), "1\nS2\nR3\n4\nM5\n").unwrap();
+    git(repo_dir, &["commit", "-am", "Merge side"]);
+}
+
+// Merges that keep one side of a file, with the other side changed last.
+fn setup_merge_history(repo_dir: &Path) {
+    git(repo_dir, &["init", "-b", "main"]);
+    for file_name in ["kept.txt", "dropped.txt", "side.txt", "both.txt"] {
+        fs::write(repo_dir.join(file_name), "1\n").unwrap();
+    }
+    git(repo_dir, &["add", "."]);
+    git_at(repo_dir, "2024-01-01T10:00:00Z", &["commit", "-m", "Base"]);
+    git(repo_dir, &["checkout", "-b", "side"]);
+    for file_name in ["dropped.txt", "side.txt", "both.txt"] {
+        fs::write(repo_dir.join(file_name), "side\n").unwrap();
+    }
+    git_at(
+        repo_dir,
+        "2024-01-03T10:00:00Z",
+        &["commit", "-am", "Side change"],
+    );
+    git(repo_dir, &["checkout", "main"]);
+    fs::write(repo_dir.join("dropped.txt"), "main\n").unwrap();
+    fs::write(repo_dir.join("both.txt"), "main\n").unwrap();
+    git_at(
+        repo_dir,
+        "2024-01-02T10:00:00Z",
+        &["commit", "-am", "Main change"],
+    );
+    git_at(
+        repo_dir,
+        "2024-01-04T10:00:00Z",
+        &["merge", "--no-commit", "-s", "ours", "side"],
+    );
+    fs::write(repo_dir.join("side.txt"), "side\n").unwrap();
+    fs::write(repo_dir.join("both.txt"), "main\nside\n").unwrap();
+    git_at(
+        repo_dir,
+        "2024-01-04T10:00:00Z",
+        &["commit", "-am", "Merge side"],
+    );
+}
+
+// The hunks, headers differ in how far object ids are abbreviated.
+fn hunks(patch: String) -> Vec<String> {
+    patch
+        .lines()
+        .filter(|line| {
+            line.starts_with("@@")
+                || line.starts_with('\\')
+                || ((line.starts_with('+') || line.starts_with('-'))
+                    && !line.starts_with("+++ ")
+                    && !line.starts_with("--- "))
+        })
+        .map(|line| line.to_string())
+        .collect()
+}
+
+#[test]
+fn test_backends_read_the_same_history() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    fs::create_dir_all(&repo_dir).unwrap();
+    setup_repo(&repo_dir);
+    let repo_dir = repo_dir.canonicalize().unwrap();
+    // Both backends run in the current directory, no other test of this binary depends on it.
+    std::env::set_current_dir(&repo_dir).unwrap();
+    let commits: Vec<String> = git(&repo_dir, &["rev-list", "--all"])
+        .lines()
+        .map(|line| line.to_string())
+        .collect();
+
+    // The old name of the renamed file too, and one that never existed.
+    for file_name in ["f.txt", "new.txt", "old.txt", "missing.txt"] {
+        let file_path = repo_dir.join(file_name).to_string_lossy().to_string();
+        assert_eq!(
+            Libgit2.latest_commit(&file_path).unwrap(),
+            GitCli.latest_commit(&file_path).unwrap(),
+            "latest commit of {}",
+            file_name
+        );
+        assert_eq!(
+            GitCli.latest_commit(&file_path).unwrap().is_some(),
+            file_name != "missing.txt"
+        );
+    }
+    for commit in commits.iter() {
+        for path in [":(top)f.txt", "new.txt", "old.txt"] {
+            assert_eq!(
+                hunks(Libgit2.show(commit, path).unwrap()),
+                hunks(GitCli.show(commit, path).unwrap()),
+                "show {} {}",
+                commit,
+                path
+            );
+        }
+    }
+    // The rename, diffed under both names.
+    let base = commits.last().unwrap();
+    let main_change = git(&repo_dir, &["rev-parse", "HEAD^1"]);
+    let main_change = main_change.trim();
+    let cli_diff = hunks(
+        GitCli
+            .diff(base, main_change, ":(top)new.txt", Some(":(top)old.txt"))
+            .unwrap(),
+    );
+    assert!(cli_diff.iter().any(|line| line.starts_with('\\')));
+    assert_eq!(
+        hunks(
+            Libgit2
+                .diff(base, main_change, ":(top)new.txt", Some(":(top)old.txt"))
+                .unwrap()
+        ),
+        cli_diff
+    );
+
+    // The patch of the whole tree, with the rename. Object ids are abbreviated and similarities
+    // rounded differently.
+    let diff_stream = |backend: &dyn GitBackend| -> Vec<String> {
+        let mut stream = backend.diff_stream(base, "HEAD").unwrap();
+        let lines = stream
+            .by_ref()
+            .filter(|line| !line.starts_with("index ") && !line.starts_with("similarity index "))
+            .collect();
+        assert!(stream.finish().is_ok());
+        lines
+    };
+    let cli_patch = diff_stream(&GitCli);
+    assert!(cli_patch.contains(&String::from("rename to new.txt")));
+    assert_eq!(diff_stream(&Libgit2), cli_patch);
+
+    let log_args = ["-p", "--unified=0", "--reverse", "--format=%x00%h"];
+    let streamed: Vec<String> = Libgit2.log_stream(&log_args).unwrap().collect();
+    assert_eq!(
+        streamed,
+        GitCli
+            .log(&log_args)
+            .unwrap()
+            .lines()
+            .collect::<Vec<&str>>()
+    );
+    // Failures show once the stream is read, not as an empty history.
+    for backend in [&GitCli as &dyn GitBackend, &Libgit2] {
+        let mut stream = backend.diff_stream("fffffff", "HEAD").unwrap();
+        assert_eq!(stream.next(), None);
+        assert!(stream.finish().is_err());
+        assert!(backend.log_stream(&log_args).unwrap().finish().is_ok());
+    }
+    let stream = GitCli.log_stream(&["--not-an-option"]).unwrap();
+    match stream.finish() {
+        Err(GitError::Command(stderr)) => assert!(stderr.contains("--not-an-option"), "{}", stderr),
+        result => panic!("git should have failed: {:?}", result),
+    }
+
+    let commit_refs: Vec<&String> = commits.iter().collect();
+    assert_eq!(
+        Libgit2.commit_details(&commit_refs).unwrap(),
+        GitCli.commit_details(&commit_refs).unwrap()
+    );
+    assert!(Libgit2.commit_details(&[&String::from("fffffff")]).is_err());
+    // Commits of the same second come in any order.
+    let sorted = |mut commits: Vec<String>| {
+        commits.sort();
+        commits
+    };
+    assert_eq!(
+        sorted(Libgit2.rev_list(base, "HEAD").unwrap()),
+        sorted(GitCli.rev_list(base, "HEAD").unwrap())
+    );
+    assert_eq!(GitCli.rev_list(base, "HEAD").unwrap().len(), 3);
+
+    assert_eq!(
+        sorted(Libgit2.tracked_files().unwrap()),
+        sorted(GitCli.tracked_files().unwrap())
+    );
+    assert_eq!(GitCli.tracked_files().unwrap().len(), 2);
+    assert_eq!(
+        Libgit2.head_commit().unwrap(),
+        GitCli.head_commit().unwrap()
+    );
+    assert_eq!(Libgit2.repo_root().unwrap(), GitCli.repo_root().unwrap());
+    for backend in [&GitCli as &dyn GitBackend, &Libgit2] {
+        assert!(backend.commit_exists(base));
+        assert!(!backend.commit_exists("fffffff"));
+        assert!(backend.path_exists(base, "old.txt"));
+        assert!(!backend.path_exists(base, "new.txt"));
+        assert!(!backend.path_exists("fffffff", "old.txt"));
+        assert!(backend.is_ancestor(base, "HEAD"));
+        assert!(backend.is_ancestor(base, base));
+        assert!(!backend.is_ancestor("HEAD", base));
+        assert!(!backend.is_ancestor("fffffff", "HEAD"));
+        assert!(backend.remote_url("origin").is_err());
+    }
+
+    // Once HEAD moves on.
+    fs::write(repo_dir.join("new.txt"), "a\n").unwrap();
+    git(&repo_dir, &["commit", "-am", "Change new"]);
+    let file_path = repo_dir.join("new.txt").to_string_lossy().to_string();
+    assert_eq!(
+        Libgit2.latest_commit(&file_path).unwrap(),
+        Some(GitCli.head_commit().unwrap())
+    );
+
+    // Changes a merge left out aren't the latest, even when they are the newest.
+    let repo_dir = temp_dir.path().join("merges");
+    fs::create_dir_all(&repo_dir).unwrap();
+    setup_merge_history(&repo_dir);
+    let repo_dir = repo_dir.canonicalize().unwrap();
+    std::env::set_current_dir(&repo_dir).unwrap();
+    let subject = |file_name: &str| {
+        let file_path = repo_dir.join(file_name).to_string_lossy().to_string();
+        let commit = GitCli.latest_commit(&file_path).unwrap().unwrap();
+        assert_eq!(
+            Libgit2.latest_commit(&file_path).unwrap(),
+            Some(commit.clone()),
+            "latest commit of {}",
+            file_name
+        );
+        git(&repo_dir, &["log", "-1", "--format=%s", &commit])
+            .trim()
+            .to_string()
+    };
+    assert_eq!(subject("kept.txt"), "Base");
+    assert_eq!(subject("dropped.txt"), "Main change");
+    assert_eq!(subject("side.txt"), "Side change");
+    assert_eq!(subject("both.txt"), "Merge side");
+}
+
+// What `-t desc` prints for every line of f.txt, after indexing with the given backend.
+fn describe(repo_dir: &Path, git_backend: &str) -> Vec<serde_json::Value> {
+    let db_dir = tempdir().expect("Failed to create temp directory");
+    let file_path = repo_dir.join("f.txt").to_string_lossy().to_string();
+    let run = |args: &[&str]| {
+        let options = ["--git-backend", git_backend, "--format", "json"];
+        run(repo_dir, db_dir.path(), &[args, &options].concat())
+    };
+    assert!(run(&["-t", "index"]).status.success());
+    let output = run(&[&file_path, "-t", "desc", "-s", "1", "-e", "5"]);
+    assert!(output.status.success());
+    serde_json::from_slice(&output.stdout).expect("stdout must be a JSON array")
+}
+
+#[test]
+fn test_indexing_with_libgit2_gives_the_same_results() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    fs::create_dir_all(&repo_dir).unwrap();
+    setup_repo(&repo_dir);
+    let repo_dir = repo_dir.canonicalize().unwrap();
+
+    let descriptions = describe(&repo_dir, "git");
+    assert_eq!(descriptions.len(), 4);
+    assert_eq!(describe(&repo_dir, "libgit2"), descriptions);
+}
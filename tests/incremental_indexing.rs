@@ -0,0 +1,235 @@
+use contextpilot::algo_loc::perform_for_whole_file;
+use contextpilot::config_impl;
+use contextpilot::contextgpt_structs::AuthorDetailsV2;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+use tempfile::tempdir;
+
+mod common;
+use common::git;
+
+fn setup_repo(repo_dir: &Path) {
+    git(repo_dir, &["init", "-b", "main"]);
+    fs::write(repo_dir.join("f.txt"), "1\n2\n3\n4\n5\n").unwrap();
+    git(repo_dir, &["add", "."]);
+    git(repo_dir, &["commit", "-m", "Base"]);
+    fs::write(repo_dir.join("f.txt"), "1\nB2\n3\n4\n5\n").unwrap();
+    git(repo_dir, &["commit", "-am", "Change 2"]);
+}
+
+fn contextpilot(repo_dir: &Path, home_dir: &Path, merge_policy: &str, args: &[&str]) -> Output {
+    let args = [args, &["--merge-policy", merge_policy, "--format", "json"]].concat();
+    common::contextpilot(repo_dir, home_dir, &args)
+}
+
+// Titles of the commits that touched each line of f.txt, sorted.
+fn line_histories(repo_dir: &Path, home_dir: &Path, merge_policy: &str) -> Vec<Vec<String>> {
+    let file_path = repo_dir.join("f.txt").to_string_lossy().to_string();
+    let line_count = fs::read_to_string(&file_path).unwrap().lines().count();
+    (1..=line_count)
+        .map(|line_number| {
+            let line_number = line_number.to_string();
+            let output = contextpilot(
+                repo_dir,
+                home_dir,
+                merge_policy,
+                &[
+                    &file_path,
+                    "-t",
+                    "desc",
+                    "-s",
+                    &line_number,
+                    "-e",
+                    &line_number,
+                ],
+            );
+            assert!(output.status.success());
+            let descriptions: Vec<serde_json::Value> =
+                serde_json::from_slice(&output.stdout).expect("stdout must be a JSON array");
+            let mut titles: Vec<String> = descriptions
+                .iter()
+                .map(|desc| desc["title"].as_str().unwrap().to_string())
+                .collect();
+            titles.sort();
+            titles
+        })
+        .collect()
+}
+
+// What a fresh index says about the lines of f.txt.
+fn indexed_from_scratch(repo_dir: &Path, merge_policy: &str) -> Vec<Vec<String>> {
+    let home_dir = tempdir().expect("Failed to create temp directory");
+    let output = contextpilot(repo_dir, home_dir.path(), merge_policy, &["-t", "index"]);
+    assert!(output.status.success());
+    line_histories(repo_dir, home_dir.path(), merge_policy)
+}
+
+fn is_stale(repo_dir: &Path, home_dir: &Path, merge_policy: &str) -> bool {
+    let output = contextpilot(repo_dir, home_dir, merge_policy, &["-t", "status"]);
+    let status: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("stdout must be a JSON object");
+    let file = status["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|file| file["path"] == "f.txt")
+        .expect("f.txt should be indexed");
+    file["stale"].as_bool().unwrap()
+}
+
+#[test]
+fn test_queries_apply_the_new_commits_of_the_file() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    fs::create_dir_all(&repo_dir).unwrap();
+    setup_repo(&repo_dir);
+    let repo_dir = repo_dir.canonicalize().unwrap();
+    let home_dir = temp_dir.path().join("home");
+    let output = contextpilot(&repo_dir, &home_dir, "first-parent", &["-t", "index"]);
+    assert!(output.status.success());
+
+    // Moves every line down, changes one and commits another file on top.
+    fs::write(repo_dir.join("f.txt"), "0\n1\nB2\n3\n4\n5\n").unwrap();
+    git(&repo_dir, &["commit", "-am", "Insert 0"]);
+    fs::write(repo_dir.join("f.txt"), "0\n1\nB2\n3\nC4\n5\n").unwrap();
+    git(&repo_dir, &["commit", "-am", "Change 4"]);
+    fs::write(repo_dir.join("g.txt"), "g\n").unwrap();
+    git(&repo_dir, &["add", "g.txt"]);
+    git(&repo_dir, &["commit", "-m", "Unrelated"]);
+    assert!(is_stale(&repo_dir, &home_dir, "first-parent"));
+
+    let histories = line_histories(&repo_dir, &home_dir, "first-parent");
+    assert_eq!(histories[0], vec!["Insert 0"]);
+    assert_eq!(histories[2], vec!["Base", "Change 2"]);
+    assert_eq!(histories[4], vec!["Base", "Change 4"]);
+    assert_eq!(histories, indexed_from_scratch(&repo_dir, "first-parent"));
+    // Stored with the commit it's now indexed at.
+    assert!(!is_stale(&repo_dir, &home_dir, "first-parent"));
+}
+
+#[test]
+fn test_merges_since_indexing_match_a_fresh_index() {
+    for merge_policy in ["first-parent", "all-parents"] {
+        let temp_dir = tempdir().expect("Failed to create temp directory");
+        let repo_dir = temp_dir.path().join("repo");
+        fs::create_dir_all(&repo_dir).unwrap();
+        setup_repo(&repo_dir);
+        let repo_dir = repo_dir.canonicalize().unwrap();
+        let home_dir = temp_dir.path().join("home");
+        let output = contextpilot(&repo_dir, &home_dir, merge_policy, &["-t", "index"]);
+        assert!(output.status.success());
+
+        git(&repo_dir, &["checkout", "-b", "side"]);
+        fs::write(repo_dir.join("f.txt"), "S1\nB2\n3\n4\n5\n").unwrap();
+        git(&repo_dir, &["commit", "-am", "Side change"]);
+        git(&repo_dir, &["checkout", "main"]);
+        fs::write(repo_dir.join("f.txt"), "1\nB2\n3\n4\nM5\n").unwrap();
+        git(&repo_dir, &["commit", "-am", "Main change"]);
+        git(&repo_dir, &["merge", "--no-edit", "side"]);
+
+        assert_eq!(
+            line_histories(&repo_dir, &home_dir, merge_policy),
+            indexed_from_scratch(&repo_dir, merge_policy),
+            "{}",
+            merge_policy
+        );
+        assert!(!is_stale(&repo_dir, &home_dir, merge_policy));
+    }
+}
+
+#[test]
+fn test_indexing_again_catches_up_stale_files() {
+    for index_args in [vec!["-t", "index"], vec!["-t", "indexfile", "f.txt"]] {
+        let temp_dir = tempdir().expect("Failed to create temp directory");
+        let repo_dir = temp_dir.path().join("repo");
+        fs::create_dir_all(&repo_dir).unwrap();
+        setup_repo(&repo_dir);
+        let repo_dir = repo_dir.canonicalize().unwrap();
+        let home_dir = temp_dir.path().join("home");
+        let output = contextpilot(&repo_dir, &home_dir, "first-parent", &index_args);
+        assert!(output.status.success());
+
+        fs::write(repo_dir.join("f.txt"), "0\n1\nB2\n3\nC4\n5\n").unwrap();
+        git(&repo_dir, &["commit", "-am", "Insert 0, change 4"]);
+        assert!(is_stale(&repo_dir, &home_dir, "first-parent"));
+        let output = contextpilot(&repo_dir, &home_dir, "first-parent", &index_args);
+        assert!(output.status.success());
+        assert!(
+            !is_stale(&repo_dir, &home_dir, "first-parent"),
+            "{:?}",
+            index_args
+        );
+        assert_eq!(
+            line_histories(&repo_dir, &home_dir, "first-parent"),
+            indexed_from_scratch(&repo_dir, "first-parent"),
+            "{:?}",
+            index_args
+        );
+    }
+}
+
+// The lines of each line number, their commits sorted.
+fn sorted_lines(lines: HashMap<u32, AuthorDetailsV2>) -> BTreeMap<u32, Vec<String>> {
+    lines
+        .into_iter()
+        .map(|(line_number, author_details)| {
+            let mut commit_hashes = author_details.commit_hashes;
+            commit_hashes.sort();
+            (line_number, commit_hashes)
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_given_commits_are_replayed_on_the_stored_lines() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    fs::create_dir_all(&repo_dir).unwrap();
+    setup_repo(&repo_dir);
+    let repo_dir = repo_dir.canonicalize().unwrap();
+    let db_dir = temp_dir.path().join("db");
+    let output = Command::new(env!("CARGO_BIN_EXE_contextpilot"))
+        .arg(&repo_dir)
+        .args(["-t", "index", "--db-dir"])
+        .arg(&db_dir)
+        .current_dir(&repo_dir)
+        .env("HOME", temp_dir.path())
+        .output()
+        .expect("Failed to run contextpilot");
+    assert!(output.status.success());
+    fs::write(repo_dir.join("f.txt"), "0\n1\nB2\n3\n4\n5\n").unwrap();
+    git(&repo_dir, &["commit", "-am", "Insert 0"]);
+
+    // Git runs in the current directory, no other test of this binary depends on it.
+    std::env::set_current_dir(&repo_dir).unwrap();
+    let config = config_impl::Config {
+        db_dir: Some(db_dir.to_string_lossy().to_string()),
+        ..Default::default()
+    };
+    let file_path = repo_dir.join("f.txt").to_string_lossy().to_string();
+    let workspace_path = repo_dir.to_string_lossy().to_string();
+    let from_scratch =
+        sorted_lines(perform_for_whole_file(file_path.clone(), false, None, None, &config).await);
+    let mut commits: Vec<String> = vec![];
+    for revision in ["HEAD~2", "HEAD~1", "HEAD"] {
+        let output = Command::new("git")
+            .args(["rev-parse", revision])
+            .output()
+            .expect("Failed to run git");
+        commits.push(String::from_utf8(output.stdout).unwrap().trim().to_string());
+    }
+    // Only the new commit, and every commit, the indexed ones are left out.
+    for commits_to_index in [vec![commits[2].clone()], commits] {
+        let lines = perform_for_whole_file(
+            file_path.clone(),
+            false,
+            Some(commits_to_index),
+            Some(workspace_path.clone()),
+            &config,
+        )
+        .await;
+        assert_eq!(sorted_lines(lines), from_scratch);
+    }
+}
@@ -1,3 +1,4 @@
+use contextpilot::config_impl;
 use contextpilot::contextgpt_structs::{AuthorDetailsV2, RequestTypeOptions};
 use contextpilot::db::DB;
 use contextpilot::algo_loc::perform_for_whole_file;
@@ -50,7 +51,7 @@ fn commit_file(dir_path: &Path, file_path: &Path, commit_message: &str) -> Strin
 
     // Get the commit hash
     let output = Command::new("git")
-        .args(["rev-parse", "--short", "HEAD"])
+        .args(["rev-parse", "HEAD"])
         .current_dir(dir_path)
         .output()
         .expect("Failed to get commit hash");
@@ -86,7 +87,7 @@ async fn test_index_file_mode() {
 
     // Create workspace path and DB folder
     let workspace_name = "test_workspace";
-    let db_folder = home_dir.join(".context_pilot_db").join(workspace_name);
+    let db_folder = config_impl::db_folder_path(workspace_name, &config_impl::Config::default()).unwrap();
     fs::create_dir_all(&db_folder).expect("Failed to create DB folder");
 
     // Create a DB instance
@@ -100,8 +101,7 @@ async fn test_index_file_mode() {
     db.init_db(workspace_name, Some(&file_path_str), false);
 
     // Test that the file is not yet indexed
-    let indices = db.find_index(&file_path_str);
-    assert!(indices.is_none(), "File should not be indexed yet");
+    assert!(!db.is_indexed(&file_path_str), "File should not be indexed yet");
 
     // Index the file using perform_for_whole_file
     let result = perform_for_whole_file(
@@ -109,6 +109,7 @@ async fn test_index_file_mode() {
         true,
         None,
         Some(workspace_name.to_string()),
+        &config_impl::Config::default(),
     ).await;
 
     // Verify that the result contains data
@@ -119,8 +120,7 @@ async fn test_index_file_mode() {
     db.store();
 
     // Test that the file is now indexed
-    let indices = db.find_index(&file_path_str);
-    assert!(indices.is_some(), "File should be indexed now");
+    assert!(db.is_indexed(&file_path_str), "File should be indexed now");
 
     // Test querying the indexed file
     db.query(file_path_str.clone(), 1, 3).await;
@@ -157,7 +157,7 @@ async fn test_index_file_mode_with_existing_index() {
 
     // Create workspace path and DB folder
     let workspace_name = "test_workspace";
-    let db_folder = home_dir.join(".context_pilot_db").join(workspace_name);
+    let db_folder = config_impl::db_folder_path(workspace_name, &config_impl::Config::default()).unwrap();
     fs::create_dir_all(&db_folder).expect("Failed to create DB folder");
 
     // Create a DB instance
@@ -176,6 +176,7 @@ async fn test_index_file_mode_with_existing_index() {
         true,
         None,
         Some(workspace_name.to_string()),
+        &config_impl::Config::default(),
     ).await;
 
     // Store the result in the DB
@@ -199,6 +200,7 @@ async fn test_index_file_mode_with_existing_index() {
         true,
         None,
         Some(workspace_name.to_string()),
+        &config_impl::Config::default(),
     ).await;
 
     // Verify that the result contains data
@@ -250,7 +252,7 @@ async fn test_index_file_mode_with_specific_commits() {
 
     // Create workspace path and DB folder
     let workspace_name = "test_workspace";
-    let db_folder = home_dir.join(".context_pilot_db").join(workspace_name);
+    let db_folder = config_impl::db_folder_path(workspace_name, &config_impl::Config::default()).unwrap();
     fs::create_dir_all(&db_folder).expect("Failed to create DB folder");
 
     // Create a DB instance
@@ -269,6 +271,7 @@ async fn test_index_file_mode_with_specific_commits() {
         true,
         Some(vec![commit_hash1.clone(), commit_hash2.clone()]),
         Some(workspace_name.to_string()),
+        &config_impl::Config::default(),
     ).await;
 
     // Verify that the result contains data
@@ -313,7 +316,7 @@ async fn test_index_file_mode_does_not_affect_workspace_indexing() {
 
     // Create workspace path and DB folder
     let workspace_name = "test_workspace";
-    let db_folder = home_dir.join(".context_pilot_db").join(workspace_name);
+    let db_folder = config_impl::db_folder_path(workspace_name, &config_impl::Config::default()).unwrap();
     fs::create_dir_all(&db_folder).expect("Failed to create DB folder");
 
     // Create a DB instance for file 1
@@ -332,6 +335,7 @@ async fn test_index_file_mode_does_not_affect_workspace_indexing() {
         true,
         None,
         Some(workspace_name.to_string()),
+        &config_impl::Config::default(),
     ).await;
 
     // Store the result in the DB
@@ -354,6 +358,7 @@ async fn test_index_file_mode_does_not_affect_workspace_indexing() {
         true,
         None,
         Some(workspace_name.to_string()),
+        &config_impl::Config::default(),
     ).await;
 
     // Store the result in the DB
@@ -370,11 +375,8 @@ async fn test_index_file_mode_does_not_affect_workspace_indexing() {
     db_check.init_db(workspace_name, None, false);
 
     // Test that both files are indexed
-    let indices1 = db_check.find_index(&file_path_str1);
-    let indices2 = db_check.find_index(&file_path_str2);
-
-    assert!(indices1.is_some(), "File 1 should be indexed");
-    assert!(indices2.is_some(), "File 2 should be indexed");
+    assert!(db_check.is_indexed(&file_path_str1), "File 1 should be indexed");
+    assert!(db_check.is_indexed(&file_path_str2), "File 2 should be indexed");
 
     // Test querying both files
     db_check.query(file_path_str1.clone(), 1, 1).await;
@@ -1,5 +1,8 @@
+use contextpilot::config_impl;
 use contextpilot::contextgpt_structs::{AuthorDetailsV2, RequestTypeOptions};
 use contextpilot::db::DB;
+use contextpilot::embedded_store::{EmbeddedStore, INDEX_FILE_NAME};
+use contextpilot::index_store::IndexStore;
 use contextpilot::algo_loc::perform_for_whole_file;
 use contextpilot::git_command_algo;
 use std::collections::HashMap;
@@ -50,7 +53,7 @@ fn commit_file(dir_path: &Path, file_path: &Path, commit_message: &str) -> Strin
 
     // Get the commit hash
     let output = Command::new("git")
-        .args(["rev-parse", "--short", "HEAD"])
+        .args(["rev-parse", "HEAD"])
         .current_dir(dir_path)
         .output()
         .expect("Failed to get commit hash");
@@ -88,7 +91,7 @@ async fn test_index_file_reindexing() {
 
     // Create workspace path and DB folder
     let workspace_name = "test_workspace";
-    let db_folder = home_dir.join(".context_pilot_db").join(workspace_name);
+    let db_folder = config_impl::db_folder_path(workspace_name, &config_impl::Config::default()).unwrap();
     fs::create_dir_all(&db_folder).expect("Failed to create DB folder");
 
     // Create a DB instance
@@ -107,6 +110,7 @@ async fn test_index_file_reindexing() {
         true,
         None,
         Some(workspace_name.to_string()),
+        &config_impl::Config::default(),
     ).await;
 
     // Only store and query if there are results
@@ -115,17 +119,10 @@ async fn test_index_file_reindexing() {
         db.append_to_db(&file_path_str, 0, result1.clone());
         db.store();
 
-        // Get the indices for the file after first indexing
-        let indices_before = db.find_index(&file_path_str);
-        assert!(indices_before.is_some(), "File should be indexed now");
-        let indices_before = indices_before.unwrap();
-        println!("Indices before re-indexing: {:?}", indices_before);
-
-        // Check that the shard files exist
-        for index in &indices_before {
-            let shard_path = format!("{}/{}.json", db_folder.display(), index);
-            assert!(Path::new(&shard_path).exists(), "Shard file should exist: {}", shard_path);
-        }
+        // The file is in the index now
+        assert!(db.is_indexed(&file_path_str), "File should be indexed now");
+        let index_path = db_folder.join(INDEX_FILE_NAME);
+        assert!(index_path.exists(), "Index file should exist: {}", index_path.display());
 
         // Modify the file
         let mut file = OpenOptions::new()
@@ -144,6 +141,7 @@ async fn test_index_file_reindexing() {
             true,
             None,
             Some(workspace_name.to_string()),
+            &config_impl::Config::default(),
         ).await;
 
         // Only store and query if there are results
@@ -152,25 +150,13 @@ async fn test_index_file_reindexing() {
             db.append_to_db(&file_path_str, 0, result2.clone());
             db.store();
 
-            // Get the indices for the file after re-indexing
-            let indices_after = db.find_index(&file_path_str);
-            assert!(indices_after.is_some(), "File should still be indexed after re-indexing");
-            let indices_after = indices_after.unwrap();
-            println!("Indices after re-indexing: {:?}", indices_after);
-
-            // Check that the old shard files are deleted
-            for index in &indices_before {
-                if !indices_after.contains(index) {
-                    let shard_path = format!("{}/{}.json", db_folder.display(), index);
-                    assert!(!Path::new(&shard_path).exists(), "Old shard file should be deleted: {}", shard_path);
-                }
-            }
-
-            // Check that the new shard files exist
-            for index in &indices_after {
-                let shard_path = format!("{}/{}.json", db_folder.display(), index);
-                assert!(Path::new(&shard_path).exists(), "New shard file should exist: {}", shard_path);
-            }
+            // The lines of the file are replaced, not added to the old ones
+            assert!(db.is_indexed(&file_path_str), "File should still be indexed after re-indexing");
+            let lines = EmbeddedStore::new(&db_folder)
+                .get_file_lines(&file_path_str)
+                .expect("Failed to read the index")
+                .expect("File should still be indexed after re-indexing");
+            assert!(lines.contains_key(&4), "Line 4 should be indexed: {:?}", lines);
 
             // Test querying the indexed file
             db.query(file_path_str.clone(), 1, 4).await;
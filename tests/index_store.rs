@@ -0,0 +1,623 @@
+use contextpilot::config_impl;
+use contextpilot::contextgpt_structs::{AuthorDetailsV2, CommitInfo};
+use contextpilot::db::DB;
+use contextpilot::embedded_store::{EmbeddedStore, INDEX_FILE_NAME};
+use contextpilot::fs_utils::{LOCK_TIMEOUT, lock_folder};
+use contextpilot::index_store::{FileLines, IndexStore, MemoryStore, StoreError};
+use contextpilot::json_store::JsonStore;
+use contextpilot::migrations::{FORMAT_VERSION, META_FILE_NAME, read_meta};
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+use tempfile::{TempDir, tempdir};
+
+mod common;
+use common::git;
+
+fn workspace(temp_dir: &TempDir, file_names: &[&str]) -> PathBuf {
+    let workspace_dir = temp_dir.path().join("workspace");
+    fs::create_dir_all(&workspace_dir).unwrap();
+    for file_name in file_names {
+        fs::write(workspace_dir.join(file_name), "line\n").unwrap();
+    }
+    workspace_dir.canonicalize().unwrap()
+}
+
+fn path_of(workspace_dir: &std::path::Path, file_name: &str) -> String {
+    workspace_dir.join(file_name).to_string_lossy().to_string()
+}
+
+fn lines(file_path: &str, commit_hash: &str) -> HashMap<u32, AuthorDetailsV2> {
+    HashMap::from([(
+        1,
+        AuthorDetailsV2 {
+            line_number: 1,
+            origin_file_path: file_path.to_string(),
+            commit_hashes: vec![commit_hash.to_string()],
+        },
+    )])
+}
+
+// The full hash of the new commit.
+fn empty_commit(dir_path: &Path, message: &str) -> String {
+    git(dir_path, &["commit", "--allow-empty", "-m", message]);
+    git(dir_path, &["rev-parse", "HEAD"]).trim().to_string()
+}
+
+// The index goes next to the workspace, in the same temporary directory.
+fn open(workspace_path: &str, file_path: Option<&str>) -> DB {
+    let db_dir = Path::new(workspace_path).with_file_name("db");
+    let mut db = DB {
+        folder_path: workspace_path.to_string(),
+        config: config_impl::Config {
+            db_dir: Some(db_dir.to_string_lossy().to_string()),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    db.init_db(workspace_path, file_path, false);
+    db
+}
+
+#[test]
+fn test_files_only_hold_their_own_lines() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let workspace_dir = workspace(&temp_dir, &["a.txt", "b.txt"]);
+    let workspace_path = workspace_dir.to_string_lossy().to_string();
+    let a_path = path_of(&workspace_dir, "a.txt");
+    let b_path = path_of(&workspace_dir, "b.txt");
+
+    let mut db = open(&workspace_path, None);
+    db.append_to_db(&a_path, 0, lines(&a_path, "aaaaaaa"));
+    db.store();
+    db.append_to_db(&b_path, 0, lines(&b_path, "bbbbbbb"));
+    db.store();
+
+    // Indexing the workspace again replaces the lines of b.txt only.
+    let mut db = open(&workspace_path, None);
+    db.append_to_db(&b_path, 0, lines(&b_path, "ccccccc"));
+    db.store();
+
+    let db = open(&workspace_path, Some(&a_path));
+    assert_eq!(
+        db.current_data_v2,
+        HashMap::from([(1, vec![String::from("aaaaaaa")])])
+    );
+    let db = open(&workspace_path, Some(&b_path));
+    assert_eq!(
+        db.current_data_v2,
+        HashMap::from([(1, vec![String::from("ccccccc")])])
+    );
+}
+
+#[test]
+fn test_json_layout_is_migrated() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let workspace_dir = workspace(&temp_dir, &["a.txt", "b.txt", "c.txt"]);
+    let workspace_path = workspace_dir.to_string_lossy().to_string();
+    let a_path = path_of(&workspace_dir, "a.txt");
+    let b_path = path_of(&workspace_dir, "b.txt");
+    let c_path = path_of(&workspace_dir, "c.txt");
+    git(&workspace_dir, &["init"]);
+    // Older versions kept abbreviated hashes, they're expanded on the way.
+    let [a_hash, b_hash, c_hash] =
+        ["a", "b", "c"].map(|message| empty_commit(&workspace_dir, message));
+    let [a_short, b_short, c_short] = [&a_hash, &b_hash, &c_hash].map(|hash| hash[..7].to_string());
+
+    // The files older versions wrote: shards keyed by file, a shard with only the lines
+    // (owned by b.txt through the mapping), the indexing metadata and the commits.
+    let db = open(&workspace_path, None);
+    let db_folder = PathBuf::from(&db.folder_path);
+    // Closes the index before its files are swapped.
+    drop(db);
+    fs::remove_dir_all(&db_folder).unwrap();
+    fs::create_dir_all(&db_folder).unwrap();
+    let legacy_files = [
+        (
+            "0.json",
+            format!(r#"{{"files": {{"{}": {{"1": ["{}"]}}}}}}"#, a_path, a_short),
+        ),
+        (
+            "1.json",
+            format!(r#"{{"1": ["{}"], "2": ["{}"]}}"#, b_short, c_short),
+        ),
+        (
+            "mapping.json",
+            format!(r#"{{"{}": [0], "{}": [1]}}"#, a_path, b_path),
+        ),
+        (
+            "indexing_metadata.json",
+            format!(
+                r#"{{"{}": ["{}"], "{}": ["{}"]}}"#,
+                a_path, a_short, c_path, c_short
+            ),
+        ),
+        (
+            "commits.json",
+            format!(
+                r#"{{"{}": {{"author": "A", "email": "a@example.com", "date": "2024-01-01T00:00:00+00:00", "timestamp": 1704067200}}}}"#,
+                a_short
+            ),
+        ),
+    ];
+    for (file_name, contents) in legacy_files.iter() {
+        fs::write(db_folder.join(file_name), contents).unwrap();
+    }
+
+    let db = open(&workspace_path, Some(&b_path));
+    assert_eq!(
+        db.current_data_v2,
+        HashMap::from([(1, vec![b_hash.clone()]), (2, vec![c_hash.clone()])])
+    );
+    for (file_name, _) in legacy_files.iter() {
+        assert!(
+            !db_folder.join(file_name).exists(),
+            "{} wasn't deleted",
+            file_name
+        );
+    }
+
+    let store = EmbeddedStore::new(&db_folder);
+    assert_eq!(store.list_files().unwrap(), vec![a_path.clone(), b_path]);
+    assert_eq!(
+        store.get_file_lines(&a_path).unwrap(),
+        Some(HashMap::from([(1, vec![a_hash.clone()])]))
+    );
+    assert_eq!(store.last_indexed_commit(&c_path).unwrap(), Some(c_hash));
+    // Stored without the files they changed, fetched from git again when needed.
+    assert!(store.get_commits(&[a_hash]).unwrap().is_empty());
+    assert_eq!(
+        read_meta(&db_folder).unwrap().unwrap().format_version,
+        FORMAT_VERSION
+    );
+}
+
+#[test]
+fn test_abbreviated_hashes_are_expanded() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let workspace_dir = workspace(&temp_dir, &["a.txt", "b.txt"]);
+    let a_path = path_of(&workspace_dir, "a.txt");
+    let b_path = path_of(&workspace_dir, "b.txt");
+    git(&workspace_dir, &["init"]);
+    let first_hash = empty_commit(&workspace_dir, "First");
+    let second_hash = empty_commit(&workspace_dir, "Second");
+    let short = |hash: &str| hash[..7].to_string();
+
+    // An index of version 4, b.txt points to a commit the repository doesn't have.
+    let db_folder = temp_dir.path().join("db");
+    fs::create_dir_all(&db_folder).unwrap();
+    let mut store = EmbeddedStore::new(&db_folder);
+    store
+        .put_file_lines(
+            &a_path,
+            &HashMap::from([
+                (1, vec![short(&first_hash)]),
+                (2, vec![short(&first_hash), short(&second_hash)]),
+            ]),
+            Some(&short(&second_hash)),
+        )
+        .unwrap();
+    store
+        .put_file_lines(
+            &b_path,
+            &HashMap::from([(1, vec![String::from("fffffff")])]),
+            Some(&short(&second_hash)),
+        )
+        .unwrap();
+    store
+        .put_commits(&HashMap::from([
+            (short(&first_hash), CommitInfo::default()),
+            (String::from("fffffff"), CommitInfo::default()),
+        ]))
+        .unwrap();
+    drop(store);
+    fs::write(db_folder.join(META_FILE_NAME), r#"{"format_version": 4}"#).unwrap();
+
+    let store = EmbeddedStore::new(&db_folder);
+    assert_eq!(store.list_files().unwrap(), vec![a_path.clone()]);
+    assert_eq!(
+        store.get_file_lines(&a_path).unwrap(),
+        Some(HashMap::from([
+            (1, vec![first_hash.clone()]),
+            (2, vec![first_hash.clone(), second_hash.clone()]),
+        ]))
+    );
+    assert_eq!(
+        store.last_indexed_commit(&a_path).unwrap(),
+        Some(second_hash)
+    );
+    assert_eq!(store.last_indexed_commit(&b_path).unwrap(), None);
+    let commits = store
+        .get_commits(&[first_hash.clone(), String::from("fffffff")])
+        .unwrap();
+    assert_eq!(commits.keys().collect::<Vec<&String>>(), vec![&first_hash]);
+    assert_eq!(
+        read_meta(&db_folder).unwrap().unwrap().format_version,
+        FORMAT_VERSION
+    );
+}
+
+#[test]
+fn test_indexes_that_cant_be_upgraded_need_a_reindex() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let db_folder = temp_dir.path().join("db");
+
+    // A shard with only the lines, claimed by two files.
+    fs::create_dir_all(&db_folder).unwrap();
+    fs::write(db_folder.join("0.json"), r#"{"1": ["aaaaaaa"]}"#).unwrap();
+    fs::write(
+        db_folder.join("mapping.json"),
+        r#"{"/a.txt": [0], "/b.txt": [0]}"#,
+    )
+    .unwrap();
+    let error = EmbeddedStore::new(&db_folder).list_files().unwrap_err();
+    assert!(matches!(error, StoreError::ReindexRequired(_)));
+    assert!(error.to_string().contains("please re-index"));
+    assert!(db_folder.join("0.json").exists());
+
+    // Written by a newer version.
+    fs::remove_dir_all(&db_folder).unwrap();
+    fs::create_dir_all(&db_folder).unwrap();
+    let meta = format!(r#"{{"format_version": {}}}"#, FORMAT_VERSION + 1);
+    fs::write(db_folder.join(META_FILE_NAME), meta).unwrap();
+    let error = EmbeddedStore::new(&db_folder).list_files().unwrap_err();
+    assert!(matches!(error, StoreError::ReindexRequired(_)));
+    assert!(!db_folder.join(INDEX_FILE_NAME).exists());
+}
+
+#[test]
+fn test_concurrent_stores_keep_every_file() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let file_names: Vec<String> = (0..8).map(|index| format!("{}.txt", index)).collect();
+    let file_name_refs: Vec<&str> = file_names.iter().map(|name| name.as_str()).collect();
+    let workspace_dir = workspace(&temp_dir, &file_name_refs);
+    let workspace_path = workspace_dir.to_string_lossy().to_string();
+    // Creates the DB folder.
+    open(&workspace_path, None);
+
+    // Each thread stands for a separate `indexfile` run, with its own view of the DB.
+    let handles: Vec<_> = file_names
+        .iter()
+        .map(|file_name| {
+            let file_path = path_of(&workspace_dir, file_name);
+            let workspace_path = workspace_path.clone();
+            std::thread::spawn(move || {
+                let mut db = open(&workspace_path, Some(&file_path));
+                db.append_to_db(&file_path, 0, lines(&file_path, &file_path));
+                db.store();
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    for file_name in file_names.iter() {
+        let file_path = path_of(&workspace_dir, file_name);
+        let db = open(&workspace_path, Some(&file_path));
+        assert_eq!(
+            db.current_data_v2,
+            HashMap::from([(1, vec![file_path.clone()])]),
+            "{} lost its lines",
+            file_name
+        );
+    }
+}
+
+#[test]
+fn test_stores_only_hold_the_lock_for_a_while() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let store = EmbeddedStore::new(temp_dir.path());
+    store.list_files().unwrap();
+    // Still open, others give up after their timeout.
+    let error = lock_folder(temp_dir.path(), Duration::from_millis(50)).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::TimedOut);
+    // Closed by now, though the store is still around.
+    let lock = lock_folder(temp_dir.path(), LOCK_TIMEOUT).unwrap();
+    drop(lock);
+    assert!(store.list_files().unwrap().is_empty());
+}
+
+// The same expectations for every backend.
+fn check_store(store: &mut impl IndexStore) {
+    let a_lines: FileLines = HashMap::from([(1, vec![String::from("aaaaaaa")])]);
+    let b_lines: FileLines = HashMap::from([(2, vec![String::from("bbbbbbb")])]);
+    assert_eq!(store.get_file_lines("/a.txt").unwrap(), None);
+    assert!(!store.has_file("/a.txt").unwrap());
+
+    store
+        .put_file_lines("/a.txt", &a_lines, Some("aaaaaaa"))
+        .unwrap();
+    store.put_file_lines("/b.txt", &a_lines, None).unwrap();
+    // Replaced, not merged.
+    store
+        .put_file_lines("/b.txt", &b_lines, Some("bbbbbbb"))
+        .unwrap();
+    assert_eq!(store.get_file_lines("/a.txt").unwrap(), Some(a_lines));
+    assert_eq!(store.get_file_lines("/b.txt").unwrap(), Some(b_lines));
+    assert_eq!(store.list_files().unwrap(), vec!["/a.txt", "/b.txt"]);
+    assert_eq!(
+        store.last_indexed_commit("/b.txt").unwrap().as_deref(),
+        Some("bbbbbbb")
+    );
+    store.set_indexed_commit("/c.txt", "ccccccc").unwrap();
+    assert_eq!(
+        store.last_indexed_commit("/c.txt").unwrap().as_deref(),
+        Some("ccccccc")
+    );
+
+    store.remove_file("/a.txt").unwrap();
+    assert!(!store.has_file("/a.txt").unwrap());
+    assert_eq!(store.last_indexed_commit("/a.txt").unwrap(), None);
+    assert_eq!(store.list_files().unwrap(), vec!["/b.txt"]);
+
+    let commit_info = CommitInfo {
+        author: String::from("A"),
+        email: String::from("a@example.com"),
+        date: String::from("2024-01-01T00:00:00+00:00"),
+        timestamp: 1704067200,
+        subject: String::from("Add a"),
+        body: String::new(),
+        files_changed: vec![String::from("a.txt")],
+        files_touched: 1,
+    };
+    store
+        .put_commits(&HashMap::from([(
+            String::from("aaaaaaa"),
+            commit_info.clone(),
+        )]))
+        .unwrap();
+    let commits = store
+        .get_commits(&[String::from("aaaaaaa"), String::from("fffffff")])
+        .unwrap();
+    assert_eq!(
+        commits,
+        HashMap::from([(String::from("aaaaaaa"), commit_info)])
+    );
+}
+
+#[test]
+fn test_backends_behave_the_same() {
+    check_store(&mut MemoryStore::default());
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    check_store(&mut JsonStore::new(temp_dir.path()));
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    check_store(&mut EmbeddedStore::new(temp_dir.path()));
+}
+
+#[tokio::test]
+async fn test_queries_run_on_an_in_memory_store() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let file_path = path_of(temp_dir.path(), "a.txt");
+    fs::write(&file_path, "one\ntwo\nthree\n").unwrap();
+    let commit_info = |author: &str, timestamp: i64| CommitInfo {
+        author: author.to_string(),
+        email: format!("{}@example.com", author),
+        date: timestamp.to_string(),
+        timestamp,
+        ..Default::default()
+    };
+    let store = MemoryStore {
+        files: HashMap::from([(
+            file_path.clone(),
+            HashMap::from([
+                (1, vec![String::from("aaaaaaa")]),
+                (2, vec![String::from("aaaaaaa"), String::from("bbbbbbb")]),
+                (3, vec![String::from("bbbbbbb")]),
+            ]),
+        )]),
+        commits: HashMap::from([
+            (String::from("aaaaaaa"), commit_info("a", 1)),
+            (String::from("bbbbbbb"), commit_info("b", 2)),
+        ]),
+        ..Default::default()
+    };
+
+    // No DB folder and no repository involved.
+    let mut db = DB {
+        store,
+        ..Default::default()
+    };
+    db.load_file(&file_path);
+    let authors = db.query_authors(file_path.clone(), 1, 2).await;
+    let authors: Vec<(&str, usize, usize)> = authors
+        .iter()
+        .map(|author| (author.author.as_str(), author.lines, author.commits))
+        .collect();
+    assert_eq!(authors, vec![("a", 2, 1), ("b", 1, 1)]);
+}
+
+#[test]
+fn test_related_files_come_from_the_commit_table() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let file_path = path_of(temp_dir.path(), "a.txt");
+    // Commits git doesn't know, so only the store can tell what they changed.
+    let commit_info = |files_changed: &[&str]| CommitInfo {
+        files_changed: files_changed.iter().map(|path| path.to_string()).collect(),
+        files_touched: files_changed.len(),
+        ..Default::default()
+    };
+    let store = MemoryStore {
+        files: HashMap::from([(
+            file_path.clone(),
+            HashMap::from([
+                (1, vec![String::from("aaaaaaa")]),
+                (2, vec![String::from("aaaaaaa"), String::from("bbbbbbb")]),
+            ]),
+        )]),
+        commits: HashMap::from([
+            (String::from("aaaaaaa"), commit_info(&["a.txt", "b.txt"])),
+            (String::from("bbbbbbb"), commit_info(&["a.txt", "c.txt"])),
+        ]),
+        ..Default::default()
+    };
+    let mut db = DB {
+        store,
+        ..Default::default()
+    };
+    db.load_file(&file_path);
+    let (counter_for_paths, uncovered_indices) = db.exists_and_return(&1, &2);
+    assert!(uncovered_indices.is_empty());
+    let mut occurrences: Vec<(&str, usize)> = counter_for_paths
+        .iter()
+        .map(|(path, co_change)| (path.as_str(), co_change.occurrences))
+        .collect();
+    occurrences.sort();
+    assert_eq!(occurrences, vec![("a.txt", 3), ("b.txt", 2), ("c.txt", 1)]);
+}
+
+// Moves the index that older versions kept in ~/.context_pilot_db/<workspace path> by
+// running a query, with the given DB folder settings, and checks that it ends up in
+// db_root/<workspace key>. HOME is temp_dir/home.
+fn check_legacy_index_is_moved(
+    temp_dir: &TempDir,
+    db_args: &[&Path],
+    db_env: &[(&str, &Path)],
+    db_root: &Path,
+) {
+    let workspace_dir = temp_dir.path().join("workspace");
+    fs::create_dir_all(&workspace_dir).unwrap();
+    let workspace_dir = workspace_dir.canonicalize().unwrap();
+    fs::write(workspace_dir.join("a.txt"), "line\n").unwrap();
+    git(&workspace_dir, &["init"]);
+    git(&workspace_dir, &["add", "."]);
+    git(&workspace_dir, &["commit", "-m", "Add a"]);
+    let workspace_path = workspace_dir.to_string_lossy().to_string();
+    let home_dir = temp_dir.path().join("home");
+
+    let legacy_folder = home_dir
+        .join(".context_pilot_db")
+        .join(workspace_path.trim_start_matches('/'));
+    fs::create_dir_all(&legacy_folder).unwrap();
+    let mut store = EmbeddedStore::new(&legacy_folder);
+    store
+        .put_file_lines(
+            "/elsewhere.txt",
+            &HashMap::from([(1, vec![String::from("aaaaaaa")])]),
+            None,
+        )
+        .unwrap();
+    drop(store);
+    // The index of a workspace nested in this one stays where it is.
+    fs::create_dir_all(legacy_folder.join("nested")).unwrap();
+
+    let a_path = path_of(&workspace_dir, "a.txt");
+    let output = Command::new(env!("CARGO_BIN_EXE_contextpilot"))
+        .arg(&workspace_dir)
+        .args(["-t", "query", &a_path, "-s", "1", "-e", "1"])
+        .args(db_args)
+        .current_dir(&workspace_dir)
+        .env("HOME", &home_dir)
+        .env_remove("XDG_CACHE_HOME")
+        .env_remove("CONTEXT_PILOT_DB_DIR")
+        .envs(db_env.iter().copied())
+        .output()
+        .expect("Failed to run contextpilot");
+    assert!(output.status.success(), "{:?}", output);
+
+    let db_folder = db_root.join(config_impl::workspace_key(&workspace_path));
+    let store = EmbeddedStore::new(&db_folder);
+    assert!(store.has_file("/elsewhere.txt").unwrap());
+    assert!(!legacy_folder.join(INDEX_FILE_NAME).exists());
+    assert!(legacy_folder.join("nested").exists());
+}
+
+#[test]
+fn test_index_is_moved_from_the_legacy_location() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let db_root = temp_dir.path().join("home").join(".context_pilot_db");
+    check_legacy_index_is_moved(&temp_dir, &[], &[], &db_root);
+
+    // Older versions had no other DB folder, the index is still under the home directory.
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let cache_dir = temp_dir.path().join("cache");
+    check_legacy_index_is_moved(
+        &temp_dir,
+        &[],
+        &[("XDG_CACHE_HOME", &cache_dir)],
+        &cache_dir.join("contextpilot"),
+    );
+
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let db_dir = temp_dir.path().join("db");
+    check_legacy_index_is_moved(&temp_dir, &[Path::new("--db-dir"), &db_dir], &[], &db_dir);
+}
+
+#[test]
+fn test_gc_removes_what_nothing_uses() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let mut store = EmbeddedStore::new(temp_dir.path());
+    let commit_info = CommitInfo::default();
+    store
+        .put_commits(&HashMap::from([
+            (String::from("aaaaaaa"), commit_info.clone()),
+            (String::from("bbbbbbb"), commit_info.clone()),
+            (String::from("ccccccc"), commit_info.clone()),
+        ]))
+        .unwrap();
+    store
+        .put_file_lines(
+            "/a.txt",
+            &HashMap::from([(1, vec![String::from("aaaaaaa")])]),
+            Some("aaaaaaa"),
+        )
+        .unwrap();
+    store
+        .put_file_lines(
+            "/deleted.txt",
+            &HashMap::from([(1, vec![String::from("bbbbbbb")])]),
+            Some("bbbbbbb"),
+        )
+        .unwrap();
+    // Indexed at a commit, without lines.
+    store.set_indexed_commit("/stray.txt", "ccccccc").unwrap();
+    // Left behind by a crash and by an older version.
+    fs::write(temp_dir.path().join(".meta.json.123.tmp"), "{").unwrap();
+    fs::write(temp_dir.path().join("mapping.json"), "{}").unwrap();
+
+    let report = store.gc(|file_path| file_path == "/deleted.txt").unwrap();
+    assert_eq!(report.removed_files, 1);
+    // Only aaaaaaa is still used by a line.
+    assert_eq!(report.removed_commits, 2);
+    assert_eq!(report.removed_leftovers, 2);
+    assert!(report.size_after <= report.size_before);
+
+    assert_eq!(store.list_files().unwrap(), vec!["/a.txt"]);
+    assert_eq!(store.last_indexed_commit("/stray.txt").unwrap(), None);
+    assert_eq!(store.last_indexed_commit("/deleted.txt").unwrap(), None);
+    let commits = store
+        .get_commits(&[
+            String::from("aaaaaaa"),
+            String::from("bbbbbbb"),
+            String::from("ccccccc"),
+        ])
+        .unwrap();
+    assert_eq!(commits.keys().collect::<Vec<_>>(), vec!["aaaaaaa"]);
+    assert!(!temp_dir.path().join("mapping.json").exists());
+    assert!(temp_dir.path().join(META_FILE_NAME).exists());
+}
+
+fn check_failed_files(store: &mut impl IndexStore) {
+    let lines: FileLines = HashMap::from([(1, vec![String::from("aaaaaaa")])]);
+    store.mark_failed("/a.txt", "no lines").unwrap();
+    store.mark_failed("/b.txt", "no lines").unwrap();
+    assert_eq!(
+        store.failed_files().unwrap().keys().collect::<Vec<_>>(),
+        vec!["/a.txt", "/b.txt"]
+    );
+
+    // Until it's indexed or removed.
+    store.put_file_lines("/a.txt", &lines, None).unwrap();
+    store.remove_file("/b.txt").unwrap();
+    assert!(store.failed_files().unwrap().is_empty());
+}
+
+#[test]
+fn test_failed_files_are_recorded_until_indexed() {
+    check_failed_files(&mut MemoryStore::default());
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    check_failed_files(&mut EmbeddedStore::new(temp_dir.path()));
+}
@@ -0,0 +1,297 @@
+use serde_json::{Value, json};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use tempfile::tempdir;
+
+mod common;
+use common::{contextpilot, git};
+
+fn init_repo(dir_path: &Path) {
+    git(dir_path, &["init"]);
+    fs::write(dir_path.join("main.txt"), "line 1\nline 2\nline 3\n").unwrap();
+    fs::write(dir_path.join("other.txt"), "other 1\n").unwrap();
+    git(dir_path, &["add", "-A"]);
+    git(dir_path, &["commit", "-m", "Initial commit"]);
+    fs::write(dir_path.join("main.txt"), "line 1\nline two\nline 3\n").unwrap();
+    fs::write(dir_path.join("other.txt"), "other 1\nother 2\n").unwrap();
+    git(dir_path, &["commit", "-am", "Change both files"]);
+}
+
+fn frame(message: Value) -> String {
+    let body = message.to_string();
+    format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+}
+
+// Splits the framed stdout back into the JSON messages.
+fn unframe(mut output: &str) -> Vec<Value> {
+    let mut messages = vec![];
+    while let Some((header, rest)) = output.split_once("\r\n\r\n") {
+        let length: usize = header
+            .strip_prefix("Content-Length: ")
+            .expect("Expected a Content-Length header")
+            .parse()
+            .unwrap();
+        messages.push(serde_json::from_str(&rest[..length]).unwrap());
+        output = &rest[length..];
+    }
+    messages
+}
+
+#[test]
+fn test_lsp_hover_code_lens_and_related_files() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    let home_dir = temp_dir.path().join("home");
+    fs::create_dir_all(&repo_dir).unwrap();
+    fs::create_dir_all(&home_dir).unwrap();
+    init_repo(&repo_dir);
+    let repo_dir = repo_dir.canonicalize().unwrap();
+
+    let binary = env!("CARGO_BIN_EXE_contextpilot");
+    Command::new(binary)
+        .args([repo_dir.to_str().unwrap(), "-t", "indexfile", "main.txt"])
+        .current_dir(&repo_dir)
+        .env("HOME", &home_dir)
+        .output()
+        .expect("Failed to index the file");
+
+    let uri = format!("file://{}", repo_dir.join("main.txt").display());
+    let input: String = [
+        json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}),
+        json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}),
+        json!({"jsonrpc": "2.0", "id": 2, "method": "textDocument/hover",
+               "params": {"textDocument": {"uri": uri}, "position": {"line": 1, "character": 0}}}),
+        json!({"jsonrpc": "2.0", "id": 3, "method": "textDocument/codeLens",
+               "params": {"textDocument": {"uri": uri}}}),
+        json!({"jsonrpc": "2.0", "id": 4, "method": "contextpilot/relatedFiles",
+               "params": {"textDocument": {"uri": uri},
+                          "range": {"start": {"line": 0, "character": 0}, "end": {"line": 2, "character": 0}}}}),
+        json!({"jsonrpc": "2.0", "id": 5, "method": "shutdown"}),
+        json!({"jsonrpc": "2.0", "method": "exit"}),
+    ]
+    .into_iter()
+    .map(frame)
+    .collect();
+
+    let mut child = Command::new(binary)
+        .args([repo_dir.to_str().unwrap(), "-t", "lsp"])
+        .current_dir(&repo_dir)
+        .env("HOME", &home_dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to start the language server");
+    child
+        .stdin
+        .as_mut()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    let messages = unframe(&String::from_utf8(output.stdout).unwrap());
+    assert_eq!(messages.len(), 5, "Notifications must not be answered");
+
+    let capabilities = &messages[0]["result"]["capabilities"];
+    assert_eq!(capabilities["hoverProvider"], true);
+    assert!(capabilities["codeLensProvider"].is_object());
+
+    let hover = messages[1]["result"]["contents"]["value"].as_str().unwrap();
+    assert!(
+        hover.contains("Change both files"),
+        "Unexpected hover: {}",
+        hover
+    );
+
+    let lenses = messages[2]["result"].as_array().unwrap();
+    assert!(!lenses.is_empty());
+    assert!(
+        lenses
+            .iter()
+            .any(|lens| lens["command"]["title"] == "Related: other.txt")
+    );
+
+    let related = messages[3]["result"].as_array().unwrap();
+    assert!(related.iter().any(|file| file["path"] == "other.txt"));
+    assert_eq!(messages[4]["result"], Value::Null);
+}
+
+// Messages sent to a language server for the workspace, its answers.
+fn run_lsp(workspace_dir: &Path, home_dir: &Path, requests: Vec<Value>) -> Vec<Value> {
+    let input: String = requests.into_iter().map(frame).collect();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_contextpilot"))
+        .args([workspace_dir.to_str().unwrap(), "-t", "lsp"])
+        .current_dir(workspace_dir)
+        .env("HOME", home_dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to start the language server");
+    child
+        .stdin
+        .as_mut()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    unframe(&String::from_utf8(output.stdout).unwrap())
+}
+
+#[test]
+fn test_lsp_in_a_subdirectory_of_the_repository() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    let home_dir = temp_dir.path().join("home");
+    fs::create_dir_all(repo_dir.join("sub")).unwrap();
+    fs::create_dir_all(&home_dir).unwrap();
+    git(&repo_dir, &["init"]);
+    fs::write(repo_dir.join("sub/a.txt"), "a 1\na 2\n").unwrap();
+    git(&repo_dir, &["add", "-A"]);
+    git(&repo_dir, &["commit", "-m", "Initial commit"]);
+    fs::write(repo_dir.join("sub/a.txt"), "a one\na 2\n").unwrap();
+    fs::write(repo_dir.join("c.txt"), "c\n").unwrap();
+    git(&repo_dir, &["add", "-A"]);
+    git(&repo_dir, &["commit", "-m", "Change a 1, add c"]);
+    fs::write(repo_dir.join("sub/a.txt"), "a one\na two\n").unwrap();
+    fs::write(repo_dir.join("d.txt"), "d\n").unwrap();
+    git(&repo_dir, &["add", "-A"]);
+    git(&repo_dir, &["commit", "-m", "Change a 2, add d"]);
+    let workspace_dir = repo_dir.join("sub").canonicalize().unwrap();
+
+    let output = contextpilot(&workspace_dir, &home_dir, &["-t", "indexfile", "a.txt"]);
+    assert!(output.status.success());
+
+    let uri = format!("file://{}", workspace_dir.join("a.txt").display());
+    let related_files = |id: usize, end: Value, limit: Option<usize>| {
+        let mut params = json!({"textDocument": {"uri": uri},
+                                "range": {"start": {"line": 0, "character": 0}, "end": end}});
+        if let Some(limit) = limit {
+            params["limit"] = json!(limit);
+        }
+        json!({"jsonrpc": "2.0", "id": id, "method": "contextpilot/relatedFiles", "params": params})
+    };
+    let messages = run_lsp(
+        &workspace_dir,
+        &home_dir,
+        vec![
+            json!({"jsonrpc": "2.0", "id": 1, "method": "textDocument/codeLens",
+                   "params": {"textDocument": {"uri": uri}}}),
+            // Ends at the start of the second line, which isn't selected.
+            related_files(2, json!({"line": 1, "character": 0}), None),
+            related_files(3, json!({"line": 1, "character": 3}), None),
+            related_files(4, json!({"line": 1, "character": 3}), Some(1)),
+            json!({"jsonrpc": "2.0", "method": "exit"}),
+        ],
+    );
+    assert_eq!(messages.len(), 4);
+
+    let lenses = messages[0]["result"].as_array().unwrap();
+    assert!(!lenses.is_empty());
+    for lens in lenses {
+        let title = lens["command"]["title"].as_str().unwrap();
+        assert!(!title.contains("a.txt"), "Related to itself: {}", title);
+    }
+
+    let paths = |message: &Value| -> Vec<String> {
+        message["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|file| file["path"].as_str().unwrap().to_string())
+            .collect()
+    };
+    let first_line = paths(&messages[1]);
+    assert!(first_line.contains(&String::from("c.txt")));
+    assert!(!first_line.contains(&String::from("d.txt")));
+    let both_lines = paths(&messages[2]);
+    assert!(both_lines.contains(&String::from("c.txt")));
+    assert!(both_lines.contains(&String::from("d.txt")));
+    assert_eq!(paths(&messages[3]).len(), 1);
+}
+
+#[test]
+fn test_lsp_code_lens_after_new_commits() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    let home_dir = temp_dir.path().join("home");
+    fs::create_dir_all(&repo_dir).unwrap();
+    fs::create_dir_all(&home_dir).unwrap();
+    init_repo(&repo_dir);
+    let repo_dir = repo_dir.canonicalize().unwrap();
+    let output = contextpilot(&repo_dir, &home_dir, &["-t", "indexfile", "main.txt"]);
+    assert!(output.status.success());
+    // The first two lines end up in the same hunk, changed along with a new file.
+    fs::write(repo_dir.join("main.txt"), "line one\nline TWO\nline 3\n").unwrap();
+    fs::write(repo_dir.join("new.txt"), "new\n").unwrap();
+    git(&repo_dir, &["add", "-A"]);
+    git(&repo_dir, &["commit", "-m", "Change the first lines, add new"]);
+
+    let uri = format!("file://{}", repo_dir.join("main.txt").display());
+    let messages = run_lsp(
+        &repo_dir,
+        &home_dir,
+        vec![
+            json!({"jsonrpc": "2.0", "id": 1, "method": "textDocument/codeLens",
+                   "params": {"textDocument": {"uri": uri}}}),
+            json!({"jsonrpc": "2.0", "method": "exit"}),
+        ],
+    );
+    assert_eq!(messages.len(), 1);
+    let lenses: Vec<(u64, u64, &str)> = messages[0]["result"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|lens| {
+            (
+                lens["range"]["start"]["line"].as_u64().unwrap(),
+                lens["range"]["end"]["line"].as_u64().unwrap(),
+                lens["command"]["title"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        lenses,
+        vec![
+            (0, 1, "Related: other.txt, new.txt"),
+            (2, 2, "Related: other.txt")
+        ]
+    );
+}
+
+#[test]
+fn test_lsp_hover_stores_the_lines_of_unindexed_files() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    let home_dir = temp_dir.path().join("home");
+    fs::create_dir_all(&repo_dir).unwrap();
+    fs::create_dir_all(&home_dir).unwrap();
+    init_repo(&repo_dir);
+    let repo_dir = repo_dir.canonicalize().unwrap();
+
+    let uri = format!("file://{}", repo_dir.join("main.txt").display());
+    let hover = json!({"jsonrpc": "2.0", "id": 1, "method": "textDocument/hover",
+                       "params": {"textDocument": {"uri": uri}, "position": {"line": 1, "character": 0}}});
+    let messages = run_lsp(
+        &repo_dir,
+        &home_dir,
+        vec![hover, json!({"jsonrpc": "2.0", "method": "exit"})],
+    );
+    assert_eq!(messages.len(), 1);
+    let hover = messages[0]["result"]["contents"]["value"].as_str().unwrap();
+    assert!(
+        hover.contains("Change both files"),
+        "Unexpected hover: {}",
+        hover
+    );
+
+    // Stored like an indexed file, the next hovers don't walk the history again.
+    let output = contextpilot(&repo_dir, &home_dir, &["-t", "status", "--format", "json"]);
+    let status: Value = serde_json::from_slice(&output.stdout).expect("stdout must be JSON");
+    let files = status["files"].as_array().unwrap();
+    assert_eq!(files.len(), 1, "Unexpected status: {}", status);
+    assert_eq!(files[0]["path"], "main.txt");
+    assert_eq!(files[0]["stale"], false);
+}
@@ -0,0 +1,241 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use tempfile::tempdir;
+
+mod common;
+use common::{contextpilot, git};
+
+// main and side both change line 3 of f.txt, the merge resolves the conflict with a new line.
+fn setup_repo(repo_dir: &Path) {
+    git(repo_dir, &["init", "-b", "main"]);
+    fs::write(repo_dir.join("f.txt"), "1\n2\n3\n4\n5\n").unwrap();
+    git(repo_dir, &["add", "."]);
+    git(repo_dir, &["commit", "-m", "Base"]);
+    git(repo_dir, &["checkout", "-b", "side"]);
+    fs::write(repo_dir.join("f.txt"), "1\nS2\nS3\n4\n5\n").unwrap();
+    git(repo_dir, &["commit", "-am", "Side change"]);
+    git(repo_dir, &["checkout", "main"]);
+    fs::write(repo_dir.join("f.txt"), "1\n2\nM3\n4\nM5\n").unwrap();
+    git(repo_dir, &["commit", "-am", "Main change"]);
+    // Conflicts on line 3, so the merge stops before committing.
+    let output = Command::new("git")
+        .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+        .args(["merge", "side"])
+        .current_dir(repo_dir)
+        .output()
+        .expect("Failed to run git");
+    assert!(!output.status.success(), "git merge should conflict");
+    fs::write(repo_dir.join("f.txt"), "1\nS2\nR3\n4\nM5\n").unwrap();
+    git(repo_dir, &["commit", "-am", "Merge side"]);
+}
+
+// Titles of the commits that touched the given line, sorted.
+fn line_history(
+    repo_dir: &Path,
+    file_name: &str,
+    merge_policy: &str,
+    line_number: usize,
+) -> Vec<String> {
+    let home_dir = tempdir().expect("Failed to create temp directory");
+    let file_path = repo_dir.join(file_name).to_string_lossy().to_string();
+    let line_number = line_number.to_string();
+    let output = contextpilot(
+        repo_dir,
+        home_dir.path(),
+        &[
+            &file_path,
+            "-t",
+            "desc",
+            "-s",
+            &line_number,
+            "-e",
+            &line_number,
+            "--merge-policy",
+            merge_policy,
+            "--format",
+            "json",
+        ],
+    );
+    let descriptions: Vec<serde_json::Value> =
+        serde_json::from_slice(&output.stdout).expect("stdout must be a JSON array");
+    let mut titles: Vec<String> = descriptions
+        .iter()
+        .map(|desc| desc["title"].as_str().unwrap().to_string())
+        .collect();
+    titles.sort();
+    titles
+}
+
+fn setup() -> (tempfile::TempDir, std::path::PathBuf) {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    fs::create_dir_all(&repo_dir).unwrap();
+    setup_repo(&repo_dir);
+    let repo_dir = repo_dir.canonicalize().unwrap();
+    (temp_dir, repo_dir)
+}
+
+#[test]
+fn test_all_parents_keeps_branch_commits() {
+    let (_temp_dir, repo_dir) = setup();
+    assert_eq!(
+        line_history(&repo_dir, "f.txt", "all-parents", 1),
+        vec!["Base"]
+    );
+    assert_eq!(
+        line_history(&repo_dir, "f.txt", "all-parents", 2),
+        vec!["Base", "Side change"]
+    );
+    // The conflict resolution is the only line owned by the merge.
+    assert_eq!(
+        line_history(&repo_dir, "f.txt", "all-parents", 3),
+        vec!["Base", "Main change", "Merge side"]
+    );
+    assert_eq!(
+        line_history(&repo_dir, "f.txt", "all-parents", 5),
+        vec!["Base", "Main change"]
+    );
+}
+
+#[test]
+fn test_first_parent_attributes_merged_lines_to_the_merge() {
+    let (_temp_dir, repo_dir) = setup();
+    assert_eq!(
+        line_history(&repo_dir, "f.txt", "first-parent", 2),
+        vec!["Base", "Merge side"]
+    );
+    assert_eq!(
+        line_history(&repo_dir, "f.txt", "first-parent", 3),
+        vec!["Base", "Main change", "Merge side"]
+    );
+    assert_eq!(
+        line_history(&repo_dir, "f.txt", "first-parent", 5),
+        vec!["Base", "Main change"]
+    );
+}
+
+#[test]
+fn test_skip_leaves_merges_out() {
+    let (_temp_dir, repo_dir) = setup();
+    assert_eq!(
+        line_history(&repo_dir, "f.txt", "skip", 2),
+        vec!["Base", "Side change"]
+    );
+    assert_eq!(
+        line_history(&repo_dir, "f.txt", "skip", 3),
+        vec!["Base", "Main change"]
+    );
+}
+
+#[test]
+fn test_all_parents_follows_renames_across_branches() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    fs::create_dir_all(&repo_dir).unwrap();
+    git(&repo_dir, &["init", "-b", "main"]);
+    fs::write(repo_dir.join("f.txt"), "1\n2\n3\n4\n5\n").unwrap();
+    git(&repo_dir, &["add", "."]);
+    git(&repo_dir, &["commit", "-m", "Base"]);
+    git(&repo_dir, &["checkout", "-b", "side"]);
+    git(&repo_dir, &["mv", "f.txt", "g.txt"]);
+    git(&repo_dir, &["commit", "-m", "Rename"]);
+    fs::write(repo_dir.join("g.txt"), "S1\n2\n3\n4\n5\n").unwrap();
+    git(&repo_dir, &["commit", "-am", "Side change"]);
+    git(&repo_dir, &["checkout", "main"]);
+    fs::write(repo_dir.join("f.txt"), "1\n2\n3\n4\nM5\n").unwrap();
+    git(&repo_dir, &["commit", "-am", "Main change"]);
+    git(&repo_dir, &["merge", "--no-edit", "side"]);
+    let repo_dir = repo_dir.canonicalize().unwrap();
+    assert_eq!(
+        line_history(&repo_dir, "g.txt", "all-parents", 1),
+        vec!["Base", "Side change"]
+    );
+    assert_eq!(
+        line_history(&repo_dir, "g.txt", "all-parents", 3),
+        vec!["Base"]
+    );
+    assert_eq!(
+        line_history(&repo_dir, "g.txt", "all-parents", 5),
+        vec!["Base", "Main change"]
+    );
+}
+
+// Titles of the commits that touched each of the (file, line) pairs, sorted, after indexing the
+// workspace the given way.
+fn indexed_line_histories(
+    repo_dir: &Path,
+    merge_policy: &str,
+    indexing_mode: &str,
+    lines: &[(&str, usize)],
+) -> Vec<Vec<String>> {
+    let home_dir = tempdir().expect("Failed to create temp directory");
+    let run = |args: &[&str]| {
+        let options = [
+            "--merge-policy",
+            merge_policy,
+            "--indexing-mode",
+            indexing_mode,
+            "--format",
+            "json",
+        ];
+        contextpilot(repo_dir, home_dir.path(), &[args, &options].concat())
+    };
+    assert!(run(&["-t", "index"]).status.success());
+    lines
+        .iter()
+        .map(|(file_name, line_number)| {
+            let file_path = repo_dir.join(file_name).to_string_lossy().to_string();
+            let line_number = line_number.to_string();
+            let output = run(&[
+                &file_path,
+                "-t",
+                "desc",
+                "-s",
+                &line_number,
+                "-e",
+                &line_number,
+            ]);
+            let descriptions: Vec<serde_json::Value> =
+                serde_json::from_slice(&output.stdout).expect("stdout must be a JSON array");
+            let mut titles: Vec<String> = descriptions
+                .iter()
+                .map(|desc| desc["title"].as_str().unwrap().to_string())
+                .collect();
+            titles.sort();
+            titles
+        })
+        .collect()
+}
+
+#[test]
+fn test_repository_indexing_matches_per_file_indexing() {
+    let (_temp_dir, repo_dir) = setup();
+    // A second file, renamed after the merge.
+    fs::write(repo_dir.join("g.txt"), "a\nb\n").unwrap();
+    git(&repo_dir, &["add", "."]);
+    git(&repo_dir, &["commit", "-m", "Add g"]);
+    git(&repo_dir, &["mv", "g.txt", "h.txt"]);
+    fs::write(repo_dir.join("h.txt"), "a\nB\n").unwrap();
+    git(&repo_dir, &["commit", "-am", "Rename g"]);
+
+    let lines = [
+        ("f.txt", 1),
+        ("f.txt", 2),
+        ("f.txt", 3),
+        ("f.txt", 4),
+        ("f.txt", 5),
+        ("h.txt", 1),
+        ("h.txt", 2),
+    ];
+    for merge_policy in ["all-parents", "first-parent", "skip"] {
+        let per_file = indexed_line_histories(&repo_dir, merge_policy, "per-file", &lines);
+        assert!(per_file.iter().all(|titles| !titles.is_empty()));
+        assert_eq!(
+            indexed_line_histories(&repo_dir, merge_policy, "repository", &lines),
+            per_file,
+            "{}",
+            merge_policy
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use contextpilot::contextgpt_structs::{CommitDescription, GcReport, RelatedFile};
+use contextpilot::output::{
+    OutputFormat, render_commit_descriptions, render_gc_report, render_related_files,
+};
+use std::str::FromStr;
+
+fn sample_files() -> Vec<RelatedFile> {
+    vec![
+        RelatedFile {
+            path: String::from("src/db.rs"),
+            score: 1.0,
+            occurrences: 3,
+            ..Default::default()
+        },
+        RelatedFile {
+            path: String::from("src/main.rs"),
+            score: 0.3333,
+            occurrences: 1,
+            ..Default::default()
+        },
+    ]
+}
+
+fn sample_descriptions() -> Vec<CommitDescription> {
+    vec![CommitDescription {
+        hash: String::from("0f9ed16"),
+        title: String::from("Fix the thing"),
+        body: String::from("Longer explanation"),
+        author: String::from("Test User"),
+        date: String::from("Sun Oct 18 06:27:24 2026"),
+        url: String::from("https://github.com/owner/repo/commit/0f9ed16"),
+    }]
+}
+
+#[test]
+fn test_output_format_from_str() {
+    assert_eq!(OutputFormat::from_str("text").unwrap(), OutputFormat::Text);
+    assert_eq!(OutputFormat::from_str("json").unwrap(), OutputFormat::Json);
+    assert_eq!(
+        OutputFormat::from_str("ndjson").unwrap(),
+        OutputFormat::Ndjson
+    );
+    assert!(OutputFormat::from_str("yaml").is_err());
+}
+
+#[test]
+fn test_related_files_text_is_unchanged() {
+    let lines = render_related_files(&sample_files(), OutputFormat::Text);
+    assert_eq!(
+        lines,
+        vec!["src/db.rs - 3 occurrences", "src/main.rs - 1 occurrences"]
+    );
+}
+
+#[test]
+fn test_related_files_json_and_ndjson() {
+    let lines = render_related_files(&sample_files(), OutputFormat::Json);
+    assert_eq!(lines.len(), 1);
+    let parsed: Vec<RelatedFile> = serde_json::from_str(&lines[0]).unwrap();
+    assert_eq!(parsed, sample_files());
+
+    let lines = render_related_files(&sample_files(), OutputFormat::Ndjson);
+    assert_eq!(lines.len(), 2);
+    let parsed: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+    assert_eq!(parsed["path"], "src/db.rs");
+    assert_eq!(parsed["score"], 1.0);
+    assert_eq!(parsed["occurrences"], 3);
+}
+
+#[test]
+fn test_commit_descriptions_text_and_json() {
+    let lines = render_commit_descriptions(&sample_descriptions(), OutputFormat::Text);
+    assert_eq!(
+        lines,
+        vec![
+            "[[\"Fix the thing\", \"Longer explanation\", \"Test User\", \"Sun Oct 18 06:27:24 2026\", \"https://github.com/owner/repo/commit/0f9ed16\"]]"
+        ]
+    );
+
+    let lines = render_commit_descriptions(&sample_descriptions(), OutputFormat::Ndjson);
+    assert_eq!(lines.len(), 1);
+    let parsed: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+    assert_eq!(parsed["hash"], "0f9ed16");
+    assert_eq!(parsed["title"], "Fix the thing");
+    assert_eq!(parsed["body"], "Longer explanation");
+    assert_eq!(parsed["author"], "Test User");
+}
+
+#[test]
+fn test_gc_report_json_is_an_object() {
+    let report = GcReport {
+        removed_files: 2,
+        removed_commits: 14,
+        removed_leftovers: 0,
+        size_before: 1048576,
+        size_after: 917504,
+    };
+    for format in [OutputFormat::Json, OutputFormat::Ndjson] {
+        let lines = render_gc_report(&report, format);
+        assert_eq!(lines.len(), 1);
+        let parsed: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        assert!(parsed.is_object(), "{:?} output must be an object: {}", format, lines[0]);
+        assert_eq!(serde_json::from_value::<GcReport>(parsed).unwrap(), report);
+    }
+}
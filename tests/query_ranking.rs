@@ -0,0 +1,146 @@
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+mod common;
+use common::{contextpilot, git};
+
+fn commit_files(repo_dir: &Path, version: &str, files: &[&str]) {
+    fs::write(repo_dir.join("main.txt"), format!("{}\n", version)).unwrap();
+    for file in files {
+        fs::write(repo_dir.join(file), version).unwrap();
+    }
+    git(repo_dir, &["add", "."]);
+    git(repo_dir, &["commit", "-m", version]);
+}
+
+fn query(repo_dir: &Path, home_dir: &Path, extra_args: &[&str]) -> Vec<Value> {
+    let file_path = repo_dir.join("main.txt").to_string_lossy().to_string();
+    let args = [
+        &[
+            "-t", "query", &file_path, "-s", "1", "-e", "1", "--format", "json",
+        ],
+        extra_args,
+    ]
+    .concat();
+    let output = contextpilot(repo_dir, home_dir, &args);
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+// Ranked by raw co-occurrences, to keep the expectations simple.
+fn ranked_paths(repo_dir: &Path, home_dir: &Path, extra_args: &[&str]) -> Vec<(String, u64)> {
+    let mut args = vec!["--scoring", "count"];
+    args.extend(extra_args);
+    query(repo_dir, home_dir, &args)
+        .iter()
+        .map(|record| {
+            (
+                record["path"].as_str().unwrap().to_string(),
+                record["occurrences"].as_u64().unwrap(),
+            )
+        })
+        .collect()
+}
+
+fn paths(records: &[Value]) -> Vec<&str> {
+    records
+        .iter()
+        .map(|record| record["path"].as_str().unwrap())
+        .collect()
+}
+
+#[test]
+fn test_query_results_are_ranked_and_paged() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    let home_dir = temp_dir.path().join("home");
+    fs::create_dir_all(&repo_dir).unwrap();
+    fs::create_dir_all(&home_dir).unwrap();
+    let repo_dir = repo_dir.canonicalize().unwrap();
+    git(&repo_dir, &["init"]);
+    commit_files(&repo_dir, "v1", &["d.txt", "b.txt", "c.txt", "a.txt"]);
+    commit_files(&repo_dir, "v2", &["c.txt"]);
+    commit_files(&repo_dir, "v3", &["d.txt", "e.txt"]);
+
+    let all = vec![
+        (String::from("main.txt"), 3),
+        (String::from("c.txt"), 2),
+        (String::from("d.txt"), 2),
+        (String::from("a.txt"), 1),
+        (String::from("b.txt"), 1),
+        (String::from("e.txt"), 1),
+    ];
+    assert_eq!(ranked_paths(&repo_dir, &home_dir, &["-n", "0"]), all);
+    // The default threshold (10) keeps everything here.
+    assert_eq!(ranked_paths(&repo_dir, &home_dir, &[]), all);
+    assert_eq!(ranked_paths(&repo_dir, &home_dir, &["-n", "2"]), all[..2]);
+    assert_eq!(
+        ranked_paths(&repo_dir, &home_dir, &["--limit", "2", "--offset", "2"]),
+        all[2..4]
+    );
+    assert_eq!(
+        ranked_paths(&repo_dir, &home_dir, &["--offset", "5"]),
+        all[5..]
+    );
+
+    // The configured threshold applies when there's no -n.
+    fs::write(
+        repo_dir.join(".contextpilot.json"),
+        r#"{"file_count_threshold": 3}"#,
+    )
+    .unwrap();
+    assert_eq!(ranked_paths(&repo_dir, &home_dir, &[]), all[..3]);
+    assert_eq!(ranked_paths(&repo_dir, &home_dir, &["-n", "4"]), all[..4]);
+}
+
+// focus.txt changed once with main.txt, f1..f8 in a reformat that touched every file.
+fn setup_mass_change(repo_dir: &Path) {
+    git(repo_dir, &["init"]);
+    for index in 1..=8 {
+        fs::write(repo_dir.join(format!("f{}.txt", index)), "f\n").unwrap();
+    }
+    git(repo_dir, &["add", "."]);
+    git(repo_dir, &["commit", "-m", "Add the other files"]);
+    fs::write(repo_dir.join("main.txt"), "x\n").unwrap();
+    fs::write(repo_dir.join("focus.txt"), "focus\n").unwrap();
+    git(repo_dir, &["add", "."]);
+    git(
+        repo_dir,
+        &["commit", "-m", "Focused", "--date", "2000-01-01T00:00:00Z"],
+    );
+    fs::write(repo_dir.join("main.txt"), "X\n").unwrap();
+    for index in 1..=8 {
+        fs::write(repo_dir.join(format!("f{}.txt", index)), "F\n").unwrap();
+    }
+    git(repo_dir, &["commit", "-am", "Reformat"]);
+}
+
+#[test]
+fn test_tf_idf_down_weights_mass_changes() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    let home_dir = temp_dir.path().join("home");
+    fs::create_dir_all(&repo_dir).unwrap();
+    fs::create_dir_all(&home_dir).unwrap();
+    let repo_dir = repo_dir.canonicalize().unwrap();
+    setup_mass_change(&repo_dir);
+
+    // By raw co-occurrences, focus.txt is tied with the reformatted files.
+    let by_count = query(&repo_dir, &home_dir, &["--scoring", "count", "-n", "0"]);
+    assert_eq!(paths(&by_count)[..3], ["main.txt", "f1.txt", "f2.txt"]);
+    assert_eq!(by_count[1]["score"], 0.5);
+
+    let by_tf_idf = query(&repo_dir, &home_dir, &["-n", "0"]);
+    assert_eq!(paths(&by_tf_idf)[..3], ["main.txt", "focus.txt", "f1.txt"]);
+    assert_eq!(by_tf_idf[0]["score"], 1.0);
+    let focus_score = by_tf_idf[1]["score"].as_f64().unwrap();
+    let mass_change_score = by_tf_idf[2]["score"].as_f64().unwrap();
+    assert!(focus_score > 2.0 * mass_change_score);
+    assert_eq!(by_tf_idf[1]["occurrences"], 1);
+
+    // The focused commit is decades old, a short half-life makes it irrelevant.
+    let decayed = query(&repo_dir, &home_dir, &["-n", "0", "--half-life", "30"]);
+    assert_eq!(paths(&decayed)[..2], ["main.txt", "f1.txt"]);
+    assert_eq!(paths(&decayed).last(), Some(&"focus.txt"));
+}
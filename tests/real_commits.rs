@@ -1,6 +1,7 @@
 // Testing on real git commits (From context-pilot-rs itself)
 use contextpilot::contextgpt_structs::AuthorDetailsV2;
 use contextpilot::diff_v2::{LineDetail, extract_commit_hashes};
+use contextpilot::git_backend::GitCli;
 use std::collections::HashMap;
 
 #[cfg(test)]
@@ -22,6 +23,7 @@ mod tests_real_commits {
         let mut parent_commit_hash = String::from("");
         for commit_hash in all_commits.iter() {
             extract_commit_hashes(
+                &GitCli,
                 &parent_commit_hash,
                 commit_hash,
                 &mut map,
@@ -40,12 +42,10 @@ mod tests_real_commits {
         sorted_keys.sort();
         for line_number in sorted_keys.iter() {
             let line_detail = map.get(line_number).unwrap();
-            // author_full_name is a TODO.
             let author_details = AuthorDetailsV2 {
                 origin_file_path: file_path.clone(),
                 line_number: *line_number as usize,
                 commit_hashes: line_detail[0].commit_hashes.clone(),
-                author_full_name: Vec::new(),
             };
             auth_details_map.insert(*line_number, author_details.clone());
             // author_details_vec.push(author_details);
@@ -0,0 +1,200 @@
+use contextpilot::db::DB;
+use contextpilot::git_backend::GitCli;
+use contextpilot::git_command_algo::{PathResolver, get_head_commit};
+use contextpilot::index_store::{IndexStore, MemoryStore};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+mod common;
+use common::{contextpilot, git};
+
+fn setup_repo(repo_dir: &Path) {
+    git(repo_dir, &["init"]);
+    fs::write(repo_dir.join("src.txt"), "a\nb\nc\n").unwrap();
+    fs::write(repo_dir.join("helper.txt"), "helper\n").unwrap();
+    fs::write(repo_dir.join("legacy.txt"), "legacy\n").unwrap();
+    git(repo_dir, &["add", "."]);
+    git(repo_dir, &["commit", "-m", "Add files"]);
+    git(repo_dir, &["mv", "helper.txt", "tools.txt"]);
+    git(repo_dir, &["commit", "-m", "Rename helper"]);
+    git(repo_dir, &["rm", "legacy.txt"]);
+    git(repo_dir, &["commit", "-m", "Drop legacy"]);
+    git(repo_dir, &["mv", "src.txt", "main.txt"]);
+    git(repo_dir, &["commit", "-m", "Rename src"]);
+    fs::write(repo_dir.join("main.txt"), "a\nb\nC\n").unwrap();
+    git(repo_dir, &["commit", "-am", "Change c"]);
+}
+
+fn run(repo_dir: &Path, home_dir: &Path, args: &[&str]) -> Vec<Value> {
+    let output = contextpilot(repo_dir, home_dir, &[args, &["--format", "json"]].concat());
+    serde_json::from_slice(&output.stdout).expect("stdout must be a JSON array")
+}
+
+#[test]
+fn test_history_and_results_follow_renames() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    let home_dir = temp_dir.path().join("home");
+    fs::create_dir_all(&repo_dir).unwrap();
+    fs::create_dir_all(&home_dir).unwrap();
+    setup_repo(&repo_dir);
+    let repo_dir = repo_dir.canonicalize().unwrap();
+    let file_path = repo_dir.join("main.txt").to_string_lossy().to_string();
+
+    // The first line was written before main.txt was renamed from src.txt.
+    let descriptions = run(
+        &repo_dir,
+        &home_dir,
+        &["-t", "desc", &file_path, "-s", "1", "-e", "1"],
+    );
+    let titles: Vec<&str> = descriptions
+        .iter()
+        .map(|desc| desc["title"].as_str().unwrap())
+        .collect();
+    assert_eq!(titles, vec!["Add files"]);
+
+    let expected = serde_json::json!([
+        {"path": "legacy.txt", "score": 1.0, "occurrences": 1, "deleted": true},
+        {"path": "main.txt", "score": 1.0, "occurrences": 1, "renamed_from": "src.txt"},
+        {"path": "tools.txt", "score": 1.0, "occurrences": 1, "renamed_from": "helper.txt"},
+    ]);
+    let related_files = run(
+        &repo_dir,
+        &home_dir,
+        &["-t", "query", &file_path, "-s", "1", "-e", "1"],
+    );
+    assert_eq!(Value::Array(related_files), expected);
+
+    // Same thing from the index.
+    contextpilot(&repo_dir, &home_dir, &["-t", "indexfile", &file_path]);
+    let related_files = run(
+        &repo_dir,
+        &home_dir,
+        &["-t", "query", &file_path, "-s", "1", "-e", "1"],
+    );
+    assert_eq!(Value::Array(related_files), expected);
+}
+
+#[test]
+fn test_path_resolver() {
+    let renames: HashMap<String, String> = HashMap::from([
+        (String::from("a.txt"), String::from("b.txt")),
+        (String::from("b.txt"), String::from("c.txt")),
+        (String::from("x.txt"), String::from("y.txt")),
+        (String::from("y.txt"), String::from("x.txt")),
+    ]);
+    let tracked_files: HashSet<String> =
+        HashSet::from([String::from("c.txt"), String::from("kept.txt")]);
+    let path_resolver = PathResolver::new(renames, tracked_files);
+
+    assert_eq!(
+        path_resolver.resolve("kept.txt"),
+        (String::from("kept.txt"), None, false)
+    );
+    assert_eq!(
+        path_resolver.resolve("a.txt"),
+        (String::from("c.txt"), Some(String::from("a.txt")), false)
+    );
+    assert_eq!(
+        path_resolver.resolve("gone.txt"),
+        (String::from("gone.txt"), None, true)
+    );
+    // Renamed back and forth, then deleted.
+    let (_, _, deleted) = path_resolver.resolve("x.txt");
+    assert!(deleted);
+}
+
+#[test]
+fn test_path_resolver_is_kept_in_the_index() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    fs::create_dir_all(&repo_dir).unwrap();
+    setup_repo(&repo_dir);
+    // Git runs in the current directory.
+    std::env::set_current_dir(&repo_dir).unwrap();
+    let mut db: DB<MemoryStore> = DB::default();
+
+    let path_resolver = db.load_path_resolver();
+    assert_eq!(path_resolver.resolve("helper.txt").0, "tools.txt");
+    let head_commit = get_head_commit(&GitCli).unwrap();
+    assert_eq!(
+        db.store.get_path_resolver().unwrap(),
+        Some((head_commit.clone(), path_resolver))
+    );
+
+    // Built at HEAD, the history isn't read again.
+    let stored = PathResolver::new(
+        HashMap::from([(String::from("old.txt"), String::from("main.txt"))]),
+        HashSet::from([String::from("main.txt"), String::from("tools.txt")]),
+    );
+    db.store.put_path_resolver(&head_commit, &stored).unwrap();
+    assert_eq!(db.load_path_resolver(), stored);
+
+    // Only the commits since are read once HEAD moves on.
+    git(&repo_dir, &["mv", "tools.txt", "utils.txt"]);
+    git(&repo_dir, &["commit", "-m", "Rename tools"]);
+    let path_resolver = db.load_path_resolver();
+    assert_eq!(path_resolver.resolve("old.txt").0, "main.txt");
+    assert_eq!(path_resolver.resolve("helper.txt").0, "helper.txt");
+    assert_eq!(
+        path_resolver.resolve("tools.txt"),
+        (
+            String::from("utils.txt"),
+            Some(String::from("tools.txt")),
+            false
+        )
+    );
+    assert_eq!(path_resolver.tracked_file_count(), 2);
+    assert_eq!(
+        db.store.get_path_resolver().unwrap(),
+        Some((get_head_commit(&GitCli).unwrap(), path_resolver))
+    );
+
+    // Built at a commit that's gone from the history, it's built again.
+    db.store
+        .put_path_resolver(&"0".repeat(40), &stored)
+        .unwrap();
+    let path_resolver = db.load_path_resolver();
+    assert_eq!(path_resolver.resolve("old.txt").0, "old.txt");
+    assert_eq!(path_resolver.resolve("helper.txt").0, "utils.txt");
+}
+
+#[test]
+fn test_history_follows_renames_of_names_with_spaces() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    let home_dir = temp_dir.path().join("home");
+    fs::create_dir_all(&repo_dir).unwrap();
+    fs::create_dir_all(&home_dir).unwrap();
+    git(&repo_dir, &["init"]);
+    fs::write(repo_dir.join("old notes.txt"), "a\nb\nc\nd\ne\n").unwrap();
+    git(&repo_dir, &["add", "."]);
+    git(&repo_dir, &["commit", "-m", "Add notes"]);
+    // Patches end the lines of names with spaces with a tab.
+    git(&repo_dir, &["mv", "old notes.txt", "new notes.txt"]);
+    fs::write(repo_dir.join("new notes.txt"), "a\nB\nc\nd\ne\n").unwrap();
+    git(&repo_dir, &["commit", "-am", "Rename notes"]);
+    fs::write(repo_dir.join("new notes.txt"), "a\nB\nC\nd\ne\n").unwrap();
+    git(&repo_dir, &["commit", "-am", "Change c"]);
+    let repo_dir = repo_dir.canonicalize().unwrap();
+    let file_path = repo_dir.join("new notes.txt").to_string_lossy().to_string();
+
+    contextpilot(&repo_dir, &home_dir, &["-t", "index"]);
+    let titles = |line: &str| {
+        let descriptions = run(
+            &repo_dir,
+            &home_dir,
+            &["-t", "desc", &file_path, "-s", line, "-e", line],
+        );
+        descriptions
+            .iter()
+            .map(|desc| desc["title"].as_str().unwrap().to_string())
+            .collect::<Vec<String>>()
+    };
+    assert_eq!(titles("1"), vec!["Add notes"]);
+    assert_eq!(titles("2"), vec!["Add notes", "Rename notes"]);
+    assert_eq!(titles("3"), vec!["Add notes", "Change c"]);
+}
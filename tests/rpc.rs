@@ -0,0 +1,85 @@
+use contextpilot::rpc::{
+    self, INVALID_PARAMS, INVALID_REQUEST, PARSE_ERROR, Request, Response, RpcError,
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+#[test]
+fn test_parse_request() {
+    let request = rpc::parse_request(r#"{"jsonrpc":"2.0","id":7,"method":"status"}"#).unwrap();
+    assert_eq!(request.id, Some(json!(7)));
+    assert_eq!(request.method, "status");
+    assert_eq!(request.params, Value::Null);
+
+    let notification = rpc::parse_request(r#"{"jsonrpc":"2.0","method":"initialized"}"#).unwrap();
+    assert!(notification.id.is_none());
+}
+
+#[test]
+fn test_parse_request_errors() {
+    let response = rpc::parse_request("{").unwrap_err();
+    assert_eq!(response.id, Value::Null);
+    assert_eq!(response.error.unwrap().code, PARSE_ERROR);
+
+    let response = rpc::parse_request(r#"{"jsonrpc":"2.0","id":"a"}"#).unwrap_err();
+    assert_eq!(response.id, json!("a"));
+    assert_eq!(response.error.unwrap().code, INVALID_REQUEST);
+
+    let response = rpc::parse_request(r#"{"jsonrpc":"1.0","id":1,"method":"x"}"#).unwrap_err();
+    assert_eq!(response.error.unwrap().code, INVALID_REQUEST);
+}
+
+#[test]
+fn test_parse_params() {
+    #[derive(Deserialize)]
+    struct Params {
+        file: String,
+        #[serde(default)]
+        start: Option<usize>,
+    }
+    let params: Params = rpc::parse_params(json!({"file": "a.rs", "start": 3})).unwrap();
+    assert_eq!(params.file, "a.rs");
+    assert_eq!(params.start, Some(3));
+
+    let error = rpc::parse_params::<Params>(Value::Null).err().unwrap();
+    assert_eq!(error.code, INVALID_PARAMS);
+}
+
+#[test]
+fn test_response_serialization() {
+    let success = serde_json::to_value(Response::success(json!(1), json!([1, 2]))).unwrap();
+    assert_eq!(
+        success,
+        json!({"jsonrpc": "2.0", "id": 1, "result": [1, 2]})
+    );
+
+    let failure =
+        serde_json::to_value(Response::failure(json!(2), RpcError::method_not_found("x"))).unwrap();
+    assert_eq!(failure["error"]["code"], -32601);
+    assert!(failure.get("result").is_none());
+}
+
+#[tokio::test]
+async fn test_framed_messages() {
+    let first = r#"{"jsonrpc":"2.0","id":1,"method":"initialize"}"#;
+    let second = r#"{"jsonrpc":"2.0","method":"exit"}"#;
+    // The second message uses a lowercase header name and carries an extra header.
+    let input = format!(
+        "{}Content-Type: application/vscode-jsonrpc; charset=utf-8\r\ncontent-length: {}\r\n\r\n{}",
+        rpc::frame_message(first),
+        second.len(),
+        second
+    );
+    let mut reader = input.as_bytes();
+    let message = rpc::read_framed_message(&mut reader).await.unwrap();
+    assert_eq!(message.as_deref(), Some(first));
+    let message = rpc::read_framed_message(&mut reader).await.unwrap();
+    let request: Request = serde_json::from_str(&message.unwrap()).unwrap();
+    assert_eq!(request.method, "exit");
+    assert!(
+        rpc::read_framed_message(&mut reader)
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
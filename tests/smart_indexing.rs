@@ -1,3 +1,4 @@
+use contextpilot::config_impl;
 use contextpilot::contextgpt_structs::AuthorDetailsV2;
 use contextpilot::algo_loc::perform_for_whole_file;
 use contextpilot::git_command_algo;
@@ -48,7 +49,7 @@ fn commit_file(dir_path: &Path, file_path: &Path, commit_message: &str) -> Strin
 
     // Get the commit hash
     let output = Command::new("git")
-        .args(["rev-parse", "--short", "HEAD"])
+        .args(["rev-parse", "HEAD"])
         .current_dir(dir_path)
         .output()
         .expect("Failed to get commit hash");
@@ -84,7 +85,7 @@ async fn test_perform_for_whole_file_already_indexed() {
 
     // Create workspace path and DB folder
     let workspace_name = "test_workspace";
-    let db_folder = home_dir.join(".context_pilot_db").join(workspace_name);
+    let db_folder = config_impl::db_folder_path(workspace_name, &config_impl::Config::default()).unwrap();
     fs::create_dir_all(&db_folder).expect("Failed to create DB folder");
 
     // Create indexing metadata file with the test file already indexed with the actual commit hash
@@ -102,6 +103,7 @@ async fn test_perform_for_whole_file_already_indexed() {
         true,
         None,
         Some(workspace_name.to_string()),
+        &config_impl::Config::default(),
     ).await;
 
     // Assert that the result is empty since the file is already indexed
@@ -136,7 +138,7 @@ async fn test_perform_for_whole_file_needs_indexing() {
 
     // Create workspace path and DB folder
     let workspace_name = "test_workspace";
-    let db_folder = home_dir.join(".context_pilot_db").join(workspace_name);
+    let db_folder = config_impl::db_folder_path(workspace_name, &config_impl::Config::default()).unwrap();
     fs::create_dir_all(&db_folder).expect("Failed to create DB folder");
 
     // Create indexing metadata file with different commit hash
@@ -154,6 +156,7 @@ async fn test_perform_for_whole_file_needs_indexing() {
         true,
         None,
         Some(workspace_name.to_string()),
+        &config_impl::Config::default(),
     ).await;
 
     // The result might be empty if there are no commits for the file
@@ -187,6 +190,7 @@ async fn test_perform_for_whole_file_with_specific_commits() {
         true,
         Some(commits),
         None,
+        &config_impl::Config::default(),
     ).await;
 
     // The result might be empty if there are no commits for the file
@@ -222,7 +226,7 @@ async fn test_perform_for_whole_file_no_metadata_file() {
 
     // Create workspace path and DB folder
     let workspace_name = "test_workspace";
-    let db_folder = home_dir.join(".context_pilot_db").join(workspace_name);
+    let db_folder = config_impl::db_folder_path(workspace_name, &config_impl::Config::default()).unwrap();
     fs::create_dir_all(&db_folder).expect("Failed to create DB folder");
 
     // No metadata file is created, so indexing should proceed
@@ -234,6 +238,7 @@ async fn test_perform_for_whole_file_no_metadata_file() {
         true,
         None,
         Some(workspace_name.to_string()),
+        &config_impl::Config::default(),
     ).await;
 
     // Assert that the result is not empty
@@ -268,7 +273,7 @@ async fn test_perform_for_whole_file_file_not_in_metadata() {
 
     // Create workspace path and DB folder
     let workspace_name = "test_workspace";
-    let db_folder = home_dir.join(".context_pilot_db").join(workspace_name);
+    let db_folder = config_impl::db_folder_path(workspace_name, &config_impl::Config::default()).unwrap();
     fs::create_dir_all(&db_folder).expect("Failed to create DB folder");
 
     // Create indexing metadata file without the test file
@@ -286,6 +291,7 @@ async fn test_perform_for_whole_file_file_not_in_metadata() {
         true,
         None,
         Some(workspace_name.to_string()),
+        &config_impl::Config::default(),
     ).await;
 
     // Assert that the result is not empty
@@ -0,0 +1,175 @@
+use contextpilot::config_impl::workspace_key;
+use contextpilot::contextgpt_structs::IndexStatus;
+use contextpilot::embedded_store::EmbeddedStore;
+use contextpilot::index_store::IndexStore;
+use std::fs;
+use tempfile::tempdir;
+
+mod common;
+use common::{git, git_at, run};
+
+#[test]
+fn test_status_reports_stale_and_failed_files() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    fs::create_dir_all(&repo_dir).unwrap();
+    let repo_dir = repo_dir.canonicalize().unwrap();
+    git(&repo_dir, &["init"]);
+    fs::write(repo_dir.join(".gitignore"), "ignored.txt\n").unwrap();
+    fs::write(repo_dir.join("a.txt"), "one\ntwo\n").unwrap();
+    fs::write(repo_dir.join("b.txt"), "three\n").unwrap();
+    fs::write(repo_dir.join("c.txt"), "five\n").unwrap();
+    fs::write(repo_dir.join("ignored.txt"), "ignored\n").unwrap();
+    git(&repo_dir, &["add", "a.txt", "b.txt", "c.txt", ".gitignore"]);
+    git(&repo_dir, &["commit", "-m", "Initial commit"]);
+    let db_dir = temp_dir.path().join("db");
+    assert!(run(&repo_dir, &db_dir, &["-t", "index"]).status.success());
+
+    // Changed after indexing.
+    fs::write(repo_dir.join("b.txt"), "three\nfour\n").unwrap();
+    git(&repo_dir, &["commit", "-am", "Change b"]);
+    let mut store = EmbeddedStore::new(&db_dir.join(workspace_key(&repo_dir.to_string_lossy())));
+    let failed_path = repo_dir.join("c.txt");
+    let failed_path = failed_path.to_string_lossy();
+    store.remove_file(&failed_path).unwrap();
+    store.mark_failed(&failed_path, "no lines").unwrap();
+    // The store closes the index shortly after its last call, status only waits that long.
+
+    let output = run(&repo_dir, &db_dir, &["-t", "status", "--format", "json"]);
+    assert!(output.status.success());
+    let status: IndexStatus = serde_json::from_slice(&output.stdout).unwrap();
+    // .gitignore is indexed, but hidden files aren't eligible.
+    assert_eq!(status.indexed_files, 3);
+    assert_eq!(status.eligible_files, 3);
+    assert!(status.size_on_disk > 0);
+    assert_eq!(status.tables, 5);
+    assert!(status.pages > 0);
+    let files: Vec<(&str, bool)> = status
+        .files
+        .iter()
+        .map(|file| (file.path.as_str(), file.stale))
+        .collect();
+    assert_eq!(
+        files,
+        vec![(".gitignore", false), ("a.txt", false), ("b.txt", true)]
+    );
+    assert!(
+        status
+            .files
+            .iter()
+            .all(|file| file.indexed_commit.is_some())
+    );
+    assert_eq!(status.failed_files.len(), 1);
+    assert_eq!(status.failed_files[0].path, "c.txt");
+
+    let output = run(&repo_dir, &db_dir, &["-t", "status"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.starts_with("Indexed 3 of 3 eligible files"),
+        "Unexpected stdout: {}",
+        stdout
+    );
+    assert!(stdout.contains("1 stale files, 1 failed files"));
+}
+
+#[test]
+fn test_query_before_indexing_stores_the_file_once() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    fs::create_dir_all(repo_dir.join("src")).unwrap();
+    let repo_dir = repo_dir.canonicalize().unwrap();
+    git(&repo_dir, &["init"]);
+    fs::write(repo_dir.join("src/a.txt"), "one\ntwo\n").unwrap();
+    git(&repo_dir, &["add", "."]);
+    git(&repo_dir, &["commit", "-m", "Initial commit"]);
+    let db_dir = temp_dir.path().join("db");
+
+    // Nothing is indexed, the first query traces the lines itself and stores them.
+    let query = ["-t", "query", "src/a.txt", "-s", "1", "-e", "2"];
+    let output = run(&repo_dir, &db_dir, &query);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Commit hashes found"));
+    // The second one finds them under the absolute path.
+    let output = run(&repo_dir, &db_dir, &query);
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("Commit hashes found"));
+
+    let output = run(&repo_dir, &db_dir, &["-t", "status", "--format", "json"]);
+    let status: IndexStatus = serde_json::from_slice(&output.stdout).unwrap();
+    let files: Vec<(&str, bool)> = status
+        .files
+        .iter()
+        .map(|file| (file.path.as_str(), file.stale))
+        .collect();
+    assert_eq!(files, vec![("src/a.txt", false)]);
+}
+
+#[test]
+fn test_files_a_merge_kept_as_they_were_are_not_stale() {
+    let temp_dir = tempdir().expect("Failed to create temp directory");
+    let repo_dir = temp_dir.path().join("repo");
+    fs::create_dir_all(&repo_dir).unwrap();
+    let repo_dir = repo_dir.canonicalize().unwrap();
+    git(&repo_dir, &["init", "-b", "main"]);
+    fs::write(repo_dir.join("a.txt"), "one\n").unwrap();
+    fs::write(repo_dir.join("b.txt"), "two\n").unwrap();
+    git(&repo_dir, &["add", "."]);
+    git_at(&repo_dir, "2024-01-01T10:00:00Z", &["commit", "-m", "Base"]);
+    git(&repo_dir, &["checkout", "-b", "side"]);
+    fs::write(repo_dir.join("a.txt"), "side\n").unwrap();
+    fs::write(repo_dir.join("b.txt"), "side\n").unwrap();
+    git_at(
+        &repo_dir,
+        "2024-01-03T10:00:00Z",
+        &["commit", "-am", "Side"],
+    );
+    git(&repo_dir, &["checkout", "main"]);
+    fs::write(repo_dir.join("a.txt"), "main\n").unwrap();
+    git_at(
+        &repo_dir,
+        "2024-01-02T10:00:00Z",
+        &["commit", "-am", "Main"],
+    );
+    // Keeps a.txt from main, the newer change of the side branch is left out.
+    git_at(
+        &repo_dir,
+        "2024-01-04T10:00:00Z",
+        &["merge", "--no-commit", "-s", "ours", "side"],
+    );
+    fs::write(repo_dir.join("b.txt"), "side\n").unwrap();
+    git_at(
+        &repo_dir,
+        "2024-01-04T10:00:00Z",
+        &["commit", "-am", "Merge side"],
+    );
+    let latest_commit = |file_name: &str| {
+        git(&repo_dir, &["log", "-1", "--format=%H", "--", file_name])
+            .trim()
+            .to_string()
+    };
+
+    for indexing_mode in ["per-file", "repository"] {
+        let db_dir = temp_dir.path().join(indexing_mode);
+        let index = ["-t", "index", "--indexing-mode", indexing_mode];
+        assert!(run(&repo_dir, &db_dir, &index).status.success());
+
+        let output = run(&repo_dir, &db_dir, &["-t", "status", "--format", "json"]);
+        let status: IndexStatus = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(status.files.len(), 2, "{}", indexing_mode);
+        for file in status.files.iter() {
+            assert!(!file.stale, "{} is stale, {}", file.path, indexing_mode);
+            assert_eq!(file.latest_commit, Some(latest_commit(&file.path)));
+        }
+    }
+    // Indexing again has nothing left to do.
+    let db_dir = temp_dir.path().join("repository");
+    let output = run(
+        &repo_dir,
+        &db_dir,
+        &["-t", "index", "--indexing-mode", "repository"],
+    );
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("All files are already indexed at their latest commit")
+    );
+}